@@ -0,0 +1,53 @@
+//------ aftermath_cfparser -- Classfile parser for the Aftermath JVM. -------//
+//
+// Part of the Aftermath JVM, under the MIT license. The LICENSE file is present
+// at the project root, please consult to it for license information.
+//
+//===----------------------------------------------------------------------===//
+//
+// This file contains the parsing benchmarks, comparing `Parser::parse` with
+// `Parser::parse_lazy`, which leaves the attribute tables to be decoded on
+// first access.
+//
+// Run with `cargo bench -p aftermath`.
+//
+//===----------------------------------------------------------------------===//
+
+use aftermath::class_parser::Parser;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+const CLASSES: &[(&str, &[u8])] = &[
+    (
+        "hello_world",
+        include_bytes!("../../class_basket/hello_world.class"),
+    ),
+    ("large", include_bytes!("../../class_basket/large.class")),
+];
+
+fn parse(c: &mut Criterion) {
+    for (name, bytes) in CLASSES {
+        let mut group = c.benchmark_group(*name);
+
+        group.bench_function("parse", |b| {
+            b.iter(|| Parser::new(black_box(bytes)).parse().unwrap())
+        });
+        group.bench_function("parse_lazy", |b| {
+            b.iter(|| Parser::new(black_box(bytes)).parse_lazy().unwrap())
+        });
+        // Decoding everything afterwards costs about as much as parsing eagerly.
+        group.bench_function("parse_lazy + into_class_file", |b| {
+            b.iter(|| {
+                Parser::new(black_box(bytes))
+                    .parse_lazy()
+                    .unwrap()
+                    .into_class_file()
+                    .unwrap()
+            })
+        });
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
@@ -0,0 +1,27 @@
+//------ aftermath_cfparser -- Classfile parser for the Aftermath JVM. -------//
+//
+// Part of the Aftermath JVM, under the MIT license. The LICENSE file is present
+// at the project root, please consult to it for license information.
+//
+//===----------------------------------------------------------------------===//
+//
+// This file contains a fuzzer for the writer. Anything the parser accepts
+// must be written out, and reading that back must give the same bytes again.
+//
+//===----------------------------------------------------------------------===//
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(class) = aftermath::class_parser::Parser::new(data).parse() else {
+        return;
+    };
+
+    let written = class.to_bytes().unwrap();
+    let reparsed = aftermath::class_parser::Parser::new(&written)
+        .parse()
+        .unwrap();
+
+    assert_eq!(reparsed.to_bytes().unwrap(), written);
+});
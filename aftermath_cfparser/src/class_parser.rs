@@ -11,11 +11,20 @@
 //===----------------------------------------------------------------------===//
 
 use crate::consts;
+use crate::instructions::WIDE;
+use crate::mutf8;
+use crate::visitor::{self, ClassVisitor, MethodVisitor};
+
+pub use crate::constant_pool::ConstantPool;
 
 use super::errors;
 pub use errors::Attributes as ErrorAttributes;
-pub use errors::ParsingError;
+use errors::CpNodeError;
+pub use errors::{ErrorLocation, ParsingError};
+use std::borrow::Cow;
 use std::fmt::Debug;
+use std::ops::Range;
+use std::sync::OnceLock;
 
 type U1 = u8;
 type U4 = u32;
@@ -51,6 +60,12 @@ impl U2 {
     }
 }
 
+impl From<u16> for U2 {
+    fn from(value: u16) -> Self {
+        Self(value.to_be_bytes())
+    }
+}
+
 // -------------------------------------------------------------------------------------------------
 
 #[derive(Debug)]
@@ -107,6 +122,12 @@ pub enum Attributes<'class> {
     NestMembers(NestMembers<'class>),
     Record(Record<'class>),
     PermittedSubclasses(PermittedSubclasses<'class>),
+    // Attributes we don't recognize (`SourceID`, vendor attributes, ...) must be ignored by the
+    // JVM, but the raw bytes are kept so tooling can still get at them.
+    Unknown {
+        name: Cow<'class, str>,
+        info: &'class [U1],
+    },
 }
 
 #[derive(Debug)]
@@ -125,7 +146,7 @@ pub enum TargetInfo {
 
 #[derive(Debug)]
 pub enum ElementValue {
-    ConstValueIndex(U2),
+    ConstValueIndex(ConstValue),
     EnumConstValue(EnumConstValue),
     ClassInfoIndex(U2),
     AnnotationValue(Annotation),
@@ -366,6 +387,7 @@ pub struct TypeAnnotationInner {
 
 #[derive(Debug)]
 pub struct TypeAnnotation {
+    pub target_type: U1,
     pub target_info: TargetInfo,
     pub target_path: TypePath,
     pub type_index: U2,
@@ -398,6 +420,12 @@ pub struct RuntimeVisibleParameterAnnotations {
     pub parameter_annotations: Vec<ParameterAnnotationsRuntimeParameterAnnotationsAttr>,
 }
 
+#[derive(Debug)]
+pub struct ConstValue {
+    pub tag: U1,
+    pub const_value_index: U2,
+}
+
 #[derive(Debug)]
 pub struct EnumConstValue {
     pub type_name_index: U2,
@@ -568,7 +596,7 @@ pub struct MethodInfo<'class> {
 pub struct ClassFile<'class> {
     pub minor_v: U2,
     pub major_v: U2,
-    pub cp: Vec<CpNode<'class>>,
+    pub cp: ConstantPool<'class>,
     pub access_flags: U2,
     pub this_class: U2,
     pub super_class: U2,
@@ -578,6 +606,42 @@ pub struct ClassFile<'class> {
     pub attributes: Vec<Attributes<'class>>,
 }
 
+// A class whose attribute tables are only decoded on first access. The constant pool, names and
+// member tables are parsed up front, which is all a class path indexer needs.
+#[derive(Debug)]
+pub struct LazyClassFile<'class> {
+    pub minor_v: U2,
+    pub major_v: U2,
+    pub cp: ConstantPool<'class>,
+    pub access_flags: U2,
+    pub this_class: U2,
+    pub super_class: U2,
+    pub interfaces: &'class [U2],
+    pub fields: Vec<LazyMemberInfo<'class>>,
+    pub methods: Vec<LazyMemberInfo<'class>>,
+    pub attributes: LazyAttributes<'class>,
+}
+
+#[derive(Debug)]
+pub struct LazyMemberInfo<'class> {
+    pub access_flags: U2,
+    pub name_index: U2,
+    pub descriptor_index: U2,
+    pub attributes: LazyAttributes<'class>,
+}
+
+#[derive(Debug)]
+pub struct LazyAttributes<'class> {
+    count: u16,
+    // The attribute table, without its count.
+    bytes: &'class [U1],
+    offset: usize,
+    input: &'class [U1],
+    // Where the table is (`methods[3]`), for error locations.
+    owner: Option<(&'static str, usize)>,
+    decoded: OnceLock<Vec<Attributes<'class>>>,
+}
+
 #[derive(Debug)]
 pub struct TypeParameterTarget {
     pub type_parameter_index: U1,
@@ -690,7 +754,7 @@ pub struct NameAndType {
 
 #[derive(Debug)]
 pub struct Utf8<'class> {
-    pub bytes: &'class str,
+    pub bytes: Cow<'class, str>,
 }
 
 #[derive(Debug)]
@@ -731,10 +795,24 @@ pub struct Package {
 pub struct Parser<'class> {
     pub bytes: &'class [U1],
     index: usize,
+    // Where the last read started. An error is reported there, at the start of the field that
+    // caused it, rather than after it.
+    start: usize,
+    // The whole class file, for the excerpt in error locations.
+    input: &'class [U1],
+    // The structures being parsed. Breadcrumbs are only popped once a structure has been parsed,
+    // so when an error bubbles up this still points at where it happened.
+    path: Vec<Breadcrumb<'class>>,
+}
+
+#[derive(Debug)]
+enum Breadcrumb<'class> {
+    Index(&'static str, usize),
+    Attribute(Cow<'class, str>),
 }
 
 impl<'class> Utf8<'class> {
-    pub fn verify_binary_class_or_interface_name(&self) -> Result<(), ParsingError<'class>> {
+    pub fn verify_binary_class_or_interface_name(&self) -> Result<(), ParsingError> {
         if self.bytes.chars().any(|x| x == '.') {
             return Err(ParsingError::BinaryNameContainsDot);
         }
@@ -743,19 +821,246 @@ impl<'class> Utf8<'class> {
     }
 }
 
+impl<'class> LazyAttributes<'class> {
+    pub fn len(&self) -> usize {
+        self.count.into()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// The raw attribute table, without its count.
+    pub fn raw(&self) -> &'class [U1] {
+        self.bytes
+    }
+
+    /// Decodes the attributes the first time it's called, the result is cached afterwards.
+    pub fn get(&self, cp: &ConstantPool<'class>) -> Result<&[Attributes<'class>], ParsingError> {
+        if let Some(attributes) = self.decoded.get() {
+            return Ok(attributes);
+        }
+
+        let mut parser = Parser {
+            bytes: self.bytes,
+            index: self.offset,
+            start: self.offset,
+            input: self.input,
+            path: self
+                .owner
+                .map(|(name, index)| Breadcrumb::Index(name, index))
+                .into_iter()
+                .collect(),
+        };
+
+        let attributes = parser
+            .attributes(self.count, cp)
+            .map_err(|error| parser.locate(error))?;
+
+        Ok(self.decoded.get_or_init(|| attributes))
+    }
+
+    fn into_vec(self, cp: &ConstantPool<'class>) -> Result<Vec<Attributes<'class>>, ParsingError> {
+        self.get(cp)?;
+        Ok(self.decoded.into_inner().unwrap_or_default())
+    }
+}
+
+impl<'class> LazyClassFile<'class> {
+    /// Decodes every attribute table, giving the same result as `Parser::parse`.
+    pub fn into_class_file(self) -> Result<ClassFile<'class>, ParsingError> {
+        let cp = self.cp;
+
+        let fields = self
+            .fields
+            .into_iter()
+            .map(|field| {
+                Ok(FieldInfo {
+                    access_flags: field.access_flags,
+                    name_index: field.name_index,
+                    descriptor_index: field.descriptor_index,
+                    attributes: field.attributes.into_vec(&cp)?,
+                })
+            })
+            .collect::<Result<_, ParsingError>>()?;
+        let methods = self
+            .methods
+            .into_iter()
+            .map(|method| {
+                Ok(MethodInfo {
+                    access_flags: method.access_flags,
+                    name_index: method.name_index,
+                    descriptor_index: method.descriptor_index,
+                    attributes: method.attributes.into_vec(&cp)?,
+                })
+            })
+            .collect::<Result<_, ParsingError>>()?;
+        let attributes = self.attributes.into_vec(&cp)?;
+
+        Ok(ClassFile {
+            minor_v: self.minor_v,
+            major_v: self.major_v,
+            cp,
+            access_flags: self.access_flags,
+            this_class: self.this_class,
+            super_class: self.super_class,
+            interfaces: self.interfaces,
+            fields,
+            methods,
+            attributes,
+        })
+    }
+}
+
+impl CpNode<'_> {
+    pub fn kind(&self) -> CpNodeError {
+        match self {
+            CpNode::Class(_) => CpNodeError::Class,
+            CpNode::String(_) => CpNodeError::String,
+            CpNode::MethodType(_) => CpNodeError::MethodType,
+            CpNode::Module(_) => CpNodeError::Module,
+            CpNode::Package(_) => CpNodeError::Package,
+            CpNode::Integer(_) => CpNodeError::Integer,
+            CpNode::Float(_) => CpNodeError::Float,
+            CpNode::Dynamic(_) => CpNodeError::Dynamic,
+            CpNode::NameAndType(_) => CpNodeError::NameAndType,
+            CpNode::InvokeDynamic(_) => CpNodeError::InvokeDynamic,
+            CpNode::FieldRef(_) => CpNodeError::FieldRef,
+            CpNode::MethodRef(_) => CpNodeError::MethodRef,
+            CpNode::InterfaceMethodRef(_) => CpNodeError::InterfaceMethodRef,
+            CpNode::Long(_) => CpNodeError::Long,
+            CpNode::Double(_) => CpNodeError::Double,
+            CpNode::MethodHandle(_) => CpNodeError::MethodHandle,
+            CpNode::Utf8(_) => CpNodeError::Utf8,
+            CpNode::None => CpNodeError::None,
+        }
+    }
+}
+
+impl Attributes<'_> {
+    /// The name the attribute is stored under in the class file.
+    pub fn name(&self) -> &str {
+        match self {
+            Attributes::Value(_) => "ConstantValue",
+            Attributes::Code(_) => "Code",
+            Attributes::StackMapTable(_) => "StackMapTable",
+            Attributes::Exceptions(_) => "Exceptions",
+            Attributes::InnerClass(_) => "InnerClasses",
+            Attributes::EnclosingMethod(_) => "EnclosingMethod",
+            Attributes::Synthetic(_) => "Synthetic",
+            Attributes::Signature(_) => "Signature",
+            Attributes::SourceFile(_) => "SourceFile",
+            Attributes::SourceDebugExt(_) => "SourceDebugExtension",
+            Attributes::LineNumberTable(_) => "LineNumberTable",
+            Attributes::LocalVariableTable(_) => "LocalVariableTable",
+            Attributes::LocalVariableTypeTable(_) => "LocalVariableTypeTable",
+            Attributes::Deprecated(_) => "Deprecated",
+            Attributes::RuntimeVisibleAnnotations(_) => "RuntimeVisibleAnnotations",
+            Attributes::RuntimeInvisibleAnnotations(_) => "RuntimeInvisibleAnnotations",
+            Attributes::RuntimeVisibleParameterAnnotations(_) => {
+                "RuntimeVisibleParameterAnnotations"
+            }
+            Attributes::RuntimeInvisibleParameterAnnotations(_) => {
+                "RuntimeInvisibleParameterAnnotations"
+            }
+            Attributes::RuntimeVisibleTypeAnnotations(_) => "RuntimeVisibleTypeAnnotations",
+            Attributes::RuntimeInvisibleTypeAnnotations(_) => "RuntimeInvisibleTypeAnnotations",
+            Attributes::AnnotationDefault(_) => "AnnotationDefault",
+            Attributes::BootstrapMethods(_) => "BootstrapMethods",
+            Attributes::MethodParameters(_) => "MethodParameters",
+            Attributes::Module(_) => "Module",
+            Attributes::ModulePackages(_) => "ModulePackages",
+            Attributes::ModuleMainClass(_) => "ModuleMainClass",
+            Attributes::NestHost(_) => "NestHost",
+            Attributes::NestMembers(_) => "NestMembers",
+            Attributes::Record(_) => "Record",
+            Attributes::PermittedSubclasses(_) => "PermittedSubclasses",
+            Attributes::Unknown { name, .. } => name,
+        }
+    }
+}
+
+impl Integer {
+    pub fn value(&self) -> i32 {
+        self.bytes as i32
+    }
+}
+
+impl Float {
+    // `from_bits` keeps the exact bit pattern, including NaN payloads. Compare the results with
+    // `to_bits` (not `==`) when the distinction between NaNs matters.
+    pub fn value(&self) -> f32 {
+        f32::from_bits(self.bytes)
+    }
+}
+
+impl Long {
+    pub fn value(&self) -> i64 {
+        ((u64::from(self.high_bytes) << 32) | u64::from(self.low_bytes)) as i64
+    }
+}
+
+impl Double {
+    pub fn value(&self) -> f64 {
+        f64::from_bits((u64::from(self.high_bytes) << 32) | u64::from(self.low_bytes))
+    }
+}
+
 impl<'class> Parser<'class> {
     pub fn new(bytes: &'class [u8]) -> Self {
-        Self { bytes, index: 0 }
+        Self {
+            bytes,
+            index: 0,
+            start: 0,
+            input: bytes,
+            path: vec![],
+        }
     }
 
-    fn u1(&mut self) -> Result<U1, ParsingError<'class>> {
+    /// Wraps `error` with the offset of the field it's about and the breadcrumb path.
+    fn locate(&self, error: ParsingError) -> ParsingError {
+        if let ParsingError::Located { .. } = error {
+            return error;
+        }
+
+        let mut path = String::new();
+
+        for breadcrumb in &self.path {
+            if !path.is_empty() {
+                path.push('.');
+            }
+
+            match breadcrumb {
+                Breadcrumb::Index(name, index) => path += &format!("{}[{}]", name, index),
+                Breadcrumb::Attribute(name) => path += &format!("attributes[{}]", name),
+            }
+        }
+
+        // Two lines, starting at the offset.
+        let excerpt_offset = self.start.min(self.input.len());
+        let excerpt_end = (excerpt_offset + 32).min(self.input.len());
+
+        ParsingError::Located {
+            error: Box::new(error),
+            location: Box::new(ErrorLocation {
+                offset: self.start,
+                path,
+                excerpt_offset,
+                excerpt: self.input[excerpt_offset..excerpt_end].to_vec(),
+            }),
+        }
+    }
+
+    fn u1(&mut self) -> Result<U1, ParsingError> {
+        self.start = self.index;
         let output = i!(self.bytes, 0);
         self.bytes = i!(self.bytes, 1..);
         self.index += 1;
         Ok(output)
     }
 
-    fn u1_range(&mut self, length: U4) -> Result<&'class [U1], ParsingError<'class>> {
+    fn u1_range(&mut self, length: U4) -> Result<&'class [U1], ParsingError> {
+        self.start = self.index;
         let output = i!(self.bytes, 0..length as usize);
         self.bytes = i!(self.bytes, length as usize..);
         self.index += length as usize;
@@ -763,11 +1068,15 @@ impl<'class> Parser<'class> {
         Ok(output)
     }
 
-    fn u2(&mut self) -> Result<U2, ParsingError<'class>> {
-        Ok(U2([self.u1()?, self.u1()?]))
+    fn u2(&mut self) -> Result<U2, ParsingError> {
+        let &[a, b] = self.u1_range(2)? else {
+            return Err(ParsingError::OutOfBounds);
+        };
+
+        Ok(U2([a, b]))
     }
 
-    fn u2_range(&mut self, length: U4) -> Result<&'class [U2], ParsingError<'class>> {
+    fn u2_range(&mut self, length: U4) -> Result<&'class [U2], ParsingError> {
         Ok(unsafe {
             core::slice::from_raw_parts(
                 self.u1_range(length * 2)?.as_ptr().cast(),
@@ -776,16 +1085,23 @@ impl<'class> Parser<'class> {
         })
     }
 
-    fn u4(&mut self) -> Result<U4, ParsingError<'class>> {
-        Ok(U4::from_be_bytes(self.u1_range(4)?.try_into().unwrap()))
+    fn u4(&mut self) -> Result<U4, ParsingError> {
+        let &[a, b, c, d] = self.u1_range(4)? else {
+            return Err(ParsingError::OutOfBounds);
+        };
+
+        Ok(U4::from_be_bytes([a, b, c, d]))
     }
 
-    fn element_value(&mut self) -> Result<ElementValue, ParsingError<'class>> {
+    fn element_value(&mut self) -> Result<ElementValue, ParsingError> {
         let tag = self.u1()?;
 
         match tag as char {
             'B' | 'C' | 'D' | 'F' | 'I' | 'J' | 'S' | 's' | 'Z' => {
-                Ok(ElementValue::ConstValueIndex(self.u2()?))
+                Ok(ElementValue::ConstValueIndex(ConstValue {
+                    tag,
+                    const_value_index: self.u2()?,
+                }))
             }
 
             'e' => {
@@ -819,7 +1135,7 @@ impl<'class> Parser<'class> {
         }
     }
 
-    fn annotation(&mut self) -> Result<Annotation, ParsingError<'class>> {
+    fn annotation(&mut self) -> Result<Annotation, ParsingError> {
         let type_index = self.u2()?;
         let num_element_value_pairs = self.u2()?;
         let mut element_value_pairs = Vec::with_capacity(num_element_value_pairs.to_u2().into());
@@ -840,17 +1156,19 @@ impl<'class> Parser<'class> {
         })
     }
 
-    fn annotation_range(&mut self, length: u16) -> Result<Vec<Annotation>, ParsingError<'class>> {
+    fn annotation_range(&mut self, length: u16) -> Result<Vec<Annotation>, ParsingError> {
         let mut annotations = Vec::with_capacity(length.into());
 
-        for _ in 0..length {
+        for i in 0..length {
+            self.path.push(Breadcrumb::Index("annotations", i.into()));
             annotations.push(self.annotation()?);
+            self.path.pop();
         }
 
         Ok(annotations)
     }
 
-    fn cp(&mut self, length: u16) -> Result<Vec<CpNode<'class>>, ParsingError<'class>> {
+    fn cp(&mut self, length: u16) -> Result<Vec<CpNode<'class>>, ParsingError> {
         if length == 0 {
             return Err(ParsingError::InvalidConstantPoolLength);
         }
@@ -858,17 +1176,23 @@ impl<'class> Parser<'class> {
         let mut cp: Vec<CpNode<'class>> = Vec::with_capacity(length as usize - 1);
 
         while cp.len() + 1 < length as usize {
+            self.path
+                .push(Breadcrumb::Index("constant_pool", cp.len() + 1));
+
             let tag = self.u1()?;
 
             match tag {
                 1 => {
                     let length = self.u2()?;
+                    let start = self.index;
                     let bytes = self.u1_range(length.to_u2().into())?;
 
-                    // TODO: mutf8 verification
-
                     cp.push(CpNode::Utf8(Utf8 {
-                        bytes: std::str::from_utf8(bytes)?,
+                        bytes: mutf8::decode(bytes).map_err(|offset| {
+                            ParsingError::InvalidMutf8 {
+                                offset: start + offset,
+                            }
+                        })?,
                     }))
                 }
 
@@ -1008,12 +1332,16 @@ impl<'class> Parser<'class> {
 
                 _ => return Err(ParsingError::ConstantPoolTag(tag)),
             }
+
+            self.path.pop();
         }
 
         Ok(cp)
     }
 
-    pub fn type_annotation(&mut self) -> Result<TypeAnnotation, ParsingError<'class>> {
+    // The target types are listed one by one, as in JVMS table 4.7.20-A.
+    #[allow(clippy::manual_range_patterns)]
+    pub fn type_annotation(&mut self) -> Result<TypeAnnotation, ParsingError> {
         let target_type = self.u1()?;
         let target_info = match target_type {
             0x00 | 0x01 => {
@@ -1120,6 +1448,7 @@ impl<'class> Parser<'class> {
         }
 
         Ok(TypeAnnotation {
+            target_type,
             target_info,
             target_path,
             type_index,
@@ -1128,20 +1457,19 @@ impl<'class> Parser<'class> {
         })
     }
 
-    fn type_annotation_range(
-        &mut self,
-        length: u16,
-    ) -> Result<Vec<TypeAnnotation>, ParsingError<'class>> {
+    fn type_annotation_range(&mut self, length: u16) -> Result<Vec<TypeAnnotation>, ParsingError> {
         let mut annotations = Vec::with_capacity(length.into());
 
-        for _ in 0..length {
+        for i in 0..length {
+            self.path.push(Breadcrumb::Index("annotations", i.into()));
             annotations.push(self.type_annotation()?);
+            self.path.pop();
         }
 
         Ok(annotations)
     }
 
-    fn stackmapframe(&mut self) -> Result<StackMapFrame, ParsingError<'class>> {
+    fn stackmapframe(&mut self) -> Result<StackMapFrame, ParsingError> {
         let frame_type = self.u1()?;
 
         match frame_type {
@@ -1215,7 +1543,7 @@ impl<'class> Parser<'class> {
         }
     }
 
-    fn verification_type_info(&mut self) -> Result<VerificationTypeInfo, ParsingError<'class>> {
+    fn verification_type_info(&mut self) -> Result<VerificationTypeInfo, ParsingError> {
         let tag = self.u1()?;
 
         Ok(match tag {
@@ -1245,20 +1573,53 @@ impl<'class> Parser<'class> {
     fn attributes(
         &mut self,
         length: u16,
-        cp: &Vec<CpNode>,
-    ) -> Result<Vec<Attributes<'class>>, ParsingError<'class>> {
-        let mut attributes = Vec::with_capacity(length as usize);
+        cp: &ConstantPool<'class>,
+    ) -> Result<Vec<Attributes<'class>>, ParsingError> {
+        self.attributes_at(0..length as usize, cp)
+    }
 
-        for _ in 0..length as usize {
+    // Parses the attributes of a table from the one at `indexes.start` on, the indexes are only
+    // used in error locations.
+    fn attributes_at(
+        &mut self,
+        indexes: Range<usize>,
+        cp: &ConstantPool<'class>,
+    ) -> Result<Vec<Attributes<'class>>, ParsingError> {
+        let mut attributes = Vec::with_capacity(indexes.len());
+
+        for i in indexes {
             let attribute_name_index = self.u2()?;
+            let name_offset = self.start;
+
+            self.path.push(match cp.get(attribute_name_index.to_u2()) {
+                Some(CpNode::Utf8(name)) => Breadcrumb::Attribute(name.bytes.clone()),
+                _ => Breadcrumb::Index("attributes", i),
+            });
+
             let attribute_length = self.u4()?;
+            let length_offset = self.start;
+
+            // Problems with the name are reported at the name, not at the length read after it.
+            self.start = name_offset;
+
             if attribute_name_index.to_u2() == 0 {
                 return Err(ParsingError::InvalidTag);
             }
-            let tag = i!(cp, attribute_name_index.to_u2() as usize - 1);
+            let tag = cp
+                .get(attribute_name_index.to_u2())
+                .ok_or(ParsingError::OutOfBounds)?;
+
+            self.start = length_offset;
+
+            // Every attribute body is parsed from a slice of exactly `attribute_length` bytes, so a
+            // malformed attribute can never read into (or desynchronize) whatever comes after it.
+            let body = i!(self.bytes, ..attribute_length as usize);
+            let rest = i!(self.bytes, attribute_length as usize..);
+            let start = self.index;
+            self.bytes = body;
 
             if let CpNode::Utf8(tag) = tag {
-                match tag.bytes {
+                match &*tag.bytes {
                     "ConstantValue" => {
                         let value_index = self.u2()?;
 
@@ -1409,8 +1770,10 @@ impl<'class> Parser<'class> {
                         let number_of_entries = self.u2()?;
                         let mut entries = Vec::with_capacity(number_of_entries.to_u2().into());
 
-                        for _ in 0..number_of_entries.to_u2() {
+                        for i in 0..number_of_entries.to_u2() {
+                            self.path.push(Breadcrumb::Index("entries", i.into()));
                             entries.push(self.stackmapframe()?);
+                            self.path.pop();
                         }
 
                         attributes.push(Attributes::StackMapTable(StackMapTable { entries }))
@@ -1680,11 +2043,14 @@ impl<'class> Parser<'class> {
                         let count = self.u2()?;
                         let mut components = Vec::with_capacity(count.to_u2().into());
 
-                        for _ in 0..count.to_u2() {
+                        for i in 0..count.to_u2() {
+                            self.path.push(Breadcrumb::Index("components", i.into()));
+
                             let name_index = self.u2()?;
                             let descriptor_index = self.u2()?;
                             let attributes_count = self.u2()?;
                             let attributes = self.attributes(attributes_count.to_u2(), cp)?;
+                            self.path.pop();
 
                             components.push(RecordComponentInfo {
                                 name_index,
@@ -1705,11 +2071,29 @@ impl<'class> Parser<'class> {
                         }))
                     }
 
-                    _ => return Err(ParsingError::InvalidAttribute(tag.bytes.to_string())),
+                    _ => attributes.push(Attributes::Unknown {
+                        name: tag.bytes.clone(),
+                        info: self.u1_range(attribute_length)?,
+                    }),
+                }
+
+                let consumed = self.index - start;
+
+                if consumed != attribute_length as usize {
+                    self.start = length_offset;
+                    return Err(ParsingError::AttributeLengthMismatch {
+                        name: tag.bytes.to_string(),
+                        declared: attribute_length,
+                        consumed,
+                    });
                 }
             } else {
+                self.start = name_offset;
                 return Err(ParsingError::AttributeNotUtf8);
             }
+
+            self.bytes = rest;
+            self.path.pop();
         }
 
         Ok(attributes)
@@ -1718,16 +2102,19 @@ impl<'class> Parser<'class> {
     fn methods(
         &mut self,
         length: u16,
-        cp: &Vec<CpNode<'class>>,
-    ) -> Result<Vec<MethodInfo<'class>>, ParsingError<'class>> {
+        cp: &ConstantPool<'class>,
+    ) -> Result<Vec<MethodInfo<'class>>, ParsingError> {
         let mut methods = Vec::with_capacity(length as usize);
 
-        for _ in 0..length {
+        for i in 0..length {
+            self.path.push(Breadcrumb::Index("methods", i.into()));
+
             let access_flags = self.u2()?;
             let name_index = self.u2()?;
             let descriptor_index = self.u2()?;
             let attributes_count = self.u2()?;
             let attributes = self.attributes(attributes_count.to_u2(), cp)?;
+            self.path.pop();
             methods.push(MethodInfo {
                 access_flags,
                 name_index,
@@ -1742,16 +2129,19 @@ impl<'class> Parser<'class> {
     fn fields(
         &mut self,
         length: u16,
-        cp: &Vec<CpNode<'class>>,
-    ) -> Result<Vec<FieldInfo<'class>>, ParsingError<'class>> {
+        cp: &ConstantPool<'class>,
+    ) -> Result<Vec<FieldInfo<'class>>, ParsingError> {
         let mut fields = Vec::with_capacity(length as usize);
 
-        for _ in 0..length {
+        for i in 0..length {
+            self.path.push(Breadcrumb::Index("fields", i.into()));
+
             let access_flags = self.u2()?;
             let name_index = self.u2()?;
             let descriptor_index = self.u2()?;
             let attributes_count = self.u2()?;
             let attributes = self.attributes(attributes_count.to_u2(), cp)?;
+            self.path.pop();
 
             fields.push(FieldInfo {
                 access_flags,
@@ -1764,7 +2154,325 @@ impl<'class> Parser<'class> {
         Ok(fields)
     }
 
-    pub fn parse(&mut self) -> Result<ClassFile<'class>, ParsingError<'class>> {
+    /// Parses the class file. Errors are wrapped in `ParsingError::Located`, use
+    /// `ParsingError::kind` to get at the error itself.
+    pub fn parse(&mut self) -> Result<ClassFile<'class>, ParsingError> {
+        self.path.clear();
+        self.class_file().map_err(|error| self.locate(error))
+    }
+
+    /// Parses the class file, leaving every attribute table to be decoded on first access.
+    pub fn parse_lazy(&mut self) -> Result<LazyClassFile<'class>, ParsingError> {
+        self.path.clear();
+        self.lazy_class_file().map_err(|error| self.locate(error))
+    }
+
+    // Skips over an attribute table, only checking that each attribute fits in the class file.
+    fn lazy_attributes(
+        &mut self,
+        owner: Option<(&'static str, usize)>,
+    ) -> Result<LazyAttributes<'class>, ParsingError> {
+        let count = self.u2()?.to_u2();
+        let offset = self.index;
+        let bytes = self.bytes;
+
+        for i in 0..count {
+            self.path.push(Breadcrumb::Index("attributes", i.into()));
+
+            self.u2()?;
+            let attribute_length = self.u4()?;
+            self.u1_range(attribute_length)?;
+            self.path.pop();
+        }
+
+        Ok(LazyAttributes {
+            count,
+            bytes: &bytes[..self.index - offset],
+            offset,
+            input: self.input,
+            owner,
+            decoded: OnceLock::new(),
+        })
+    }
+
+    fn lazy_members(
+        &mut self,
+        name: &'static str,
+    ) -> Result<Vec<LazyMemberInfo<'class>>, ParsingError> {
+        let length = self.u2()?.to_u2();
+        let mut members = Vec::with_capacity(length.into());
+
+        for i in 0..length {
+            self.path.push(Breadcrumb::Index(name, i.into()));
+
+            let access_flags = self.u2()?;
+            let name_index = self.u2()?;
+            let descriptor_index = self.u2()?;
+            let attributes = self.lazy_attributes(Some((name, i.into())))?;
+
+            members.push(LazyMemberInfo {
+                access_flags,
+                name_index,
+                descriptor_index,
+                attributes,
+            });
+            self.path.pop();
+        }
+
+        Ok(members)
+    }
+
+    fn lazy_class_file(&mut self) -> Result<LazyClassFile<'class>, ParsingError> {
+        let magic = self.u4()?;
+
+        if magic != /* 0xCAFEBABE */ consts::MAGIC {
+            return Err(ParsingError::Magic);
+        }
+
+        let minor_v = self.u2()?;
+        let major_v = self.u2()?;
+        let cp_count = self.u2()?;
+        let cp = ConstantPool::new(self.cp(cp_count.to_u2())?);
+        let access_flags = self.u2()?;
+        let this_class = self.u2()?;
+        let super_class = self.u2()?;
+        let interfaces_count = self.u2()?;
+        let interfaces = self.u2_range(interfaces_count.to_u2() as u32)?;
+        let fields = self.lazy_members("fields")?;
+        let methods = self.lazy_members("methods")?;
+        let attributes = self.lazy_attributes(None)?;
+
+        Ok(LazyClassFile {
+            minor_v,
+            major_v,
+            cp,
+            access_flags,
+            this_class,
+            super_class,
+            interfaces,
+            fields,
+            methods,
+            attributes,
+        })
+    }
+
+    /// Walks the class file, calling into `visitor` instead of building a ClassFile.
+    pub fn accept(&mut self, visitor: &mut dyn ClassVisitor<'class>) -> Result<(), ParsingError> {
+        self.path.clear();
+        self.visit_class_file(visitor)
+            .map_err(|error| self.locate(error))
+    }
+
+    fn visit_class_file(
+        &mut self,
+        visitor: &mut dyn ClassVisitor<'class>,
+    ) -> Result<(), ParsingError> {
+        let magic = self.u4()?;
+
+        if magic != /* 0xCAFEBABE */ consts::MAGIC {
+            return Err(ParsingError::Magic);
+        }
+
+        let minor_v = self.u2()?;
+        let major_v = self.u2()?;
+        let cp_count = self.u2()?;
+        let cp = ConstantPool::new(self.cp(cp_count.to_u2())?);
+        visitor.visit(minor_v, major_v, &cp);
+
+        let access_flags = self.u2()?;
+        let this_class = self.u2()?;
+        let super_class = self.u2()?;
+        let interfaces_count = self.u2()?;
+        let interfaces = self.u2_range(interfaces_count.to_u2() as u32)?;
+        visitor.visit_class(access_flags, this_class, super_class, interfaces, &cp);
+
+        let fields_count = self.u2()?;
+
+        for i in 0..fields_count.to_u2() {
+            self.path.push(Breadcrumb::Index("fields", i.into()));
+
+            let access_flags = self.u2()?;
+            let name_index = self.u2()?;
+            let descriptor_index = self.u2()?;
+
+            match visitor.visit_field(access_flags, name_index, descriptor_index, &cp) {
+                Some(field) => {
+                    let attributes_count = self.u2()?;
+
+                    for attribute in self.attributes(attributes_count.to_u2(), &cp)? {
+                        visitor::attribute(field, attribute, &cp);
+                    }
+
+                    field.visit_end();
+                }
+                None => {
+                    self.lazy_attributes(None)?;
+                }
+            }
+
+            self.path.pop();
+        }
+
+        let methods_count = self.u2()?;
+
+        for i in 0..methods_count.to_u2() {
+            self.path.push(Breadcrumb::Index("methods", i.into()));
+
+            let access_flags = self.u2()?;
+            let name_index = self.u2()?;
+            let descriptor_index = self.u2()?;
+
+            match visitor.visit_method(access_flags, name_index, descriptor_index, &cp) {
+                Some(method) => {
+                    let attributes_count = self.u2()?;
+
+                    for i in 0..attributes_count.to_u2() as usize {
+                        // Code the visitor doesn't look at is skipped over without being decoded.
+                        if let Some((max_stack, max_locals)) = self.peek_code(&cp) {
+                            if !method.visit_code(max_stack, max_locals, &cp) {
+                                self.skip_attribute("Code")?;
+                                continue;
+                            }
+                        }
+
+                        for attribute in self.attributes_at(i..i + 1, &cp)? {
+                            Self::visit_method_attribute(method, attribute, &cp)?;
+                        }
+                    }
+
+                    method.visit_end();
+                }
+                None => {
+                    self.lazy_attributes(None)?;
+                }
+            }
+
+            self.path.pop();
+        }
+
+        if visitor.visit_attributes(&cp) {
+            let attributes_count = self.u2()?;
+
+            for attribute in self.attributes(attributes_count.to_u2(), &cp)? {
+                visitor::attribute(visitor, attribute, &cp);
+            }
+        } else {
+            self.lazy_attributes(None)?;
+        }
+
+        visitor.visit_end();
+
+        Ok(())
+    }
+
+    // The max_stack and max_locals of the next attribute, when it's a Code attribute. A Code
+    // attribute too short to hold them isn't recognized, and fails to parse afterwards.
+    fn peek_code(&self, cp: &ConstantPool<'class>) -> Option<(U2, U2)> {
+        let header = self.bytes.get(..10)?;
+        let name_index = u16::from_be_bytes([header[0], header[1]]);
+        let length = u32::from_be_bytes([header[2], header[3], header[4], header[5]]);
+
+        match cp.get(name_index)? {
+            CpNode::Utf8(name) if name.bytes == "Code" && length >= 4 => {
+                Some((U2([header[6], header[7]]), U2([header[8], header[9]])))
+            }
+            _ => None,
+        }
+    }
+
+    fn skip_attribute(&mut self, name: &'static str) -> Result<(), ParsingError> {
+        self.path.push(Breadcrumb::Attribute(Cow::Borrowed(name)));
+
+        self.u2()?;
+        let attribute_length = self.u4()?;
+        self.u1_range(attribute_length)?;
+
+        self.path.pop();
+        Ok(())
+    }
+
+    fn visit_code(
+        method: &mut dyn MethodVisitor<'class>,
+        code: AttrCode<'class>,
+        cp: &ConstantPool<'class>,
+    ) -> Result<(), ParsingError> {
+        for handler in &code.exception_table {
+            method.visit_exception_handler(handler, cp);
+        }
+
+        for instruction in code.instructions() {
+            let (pc, instruction) = instruction?;
+            let wide = code.code.get(pc as usize) == Some(&WIDE);
+            method.visit_instruction(pc, &instruction, wide, cp);
+        }
+
+        for attribute in code.attributes {
+            match attribute {
+                Attributes::StackMapTable(z) => {
+                    for frame in &z.entries {
+                        method.visit_frame(frame, cp);
+                    }
+                }
+                Attributes::LineNumberTable(z) => {
+                    for line in &z.line_number_table {
+                        method.visit_line_number(line.start_pc, line.line_number);
+                    }
+                }
+                attribute => visitor::attribute(method, attribute, cp),
+            }
+        }
+
+        method.visit_code_end();
+
+        Ok(())
+    }
+
+    fn visit_method_attribute(
+        method: &mut dyn MethodVisitor<'class>,
+        attribute: Attributes<'class>,
+        cp: &ConstantPool<'class>,
+    ) -> Result<(), ParsingError> {
+        let (parameter_annotations, visible) = match attribute {
+            // `visit_code` was called before the attribute was parsed.
+            Attributes::Code(code) => return Self::visit_code(method, code, cp),
+
+            Attributes::AnnotationDefault(z) => {
+                if let Some(annotation_visitor) = method.visit_annotation_default(cp) {
+                    visitor::element_value(annotation_visitor, None, &z.default_value);
+                    annotation_visitor.visit_end();
+                }
+
+                return Ok(());
+            }
+
+            Attributes::RuntimeVisibleParameterAnnotations(z) => (z.parameter_annotations, true),
+            Attributes::RuntimeInvisibleParameterAnnotations(z) => (z.parameter_annotations, false),
+
+            attribute => {
+                visitor::attribute(method, attribute, cp);
+                return Ok(());
+            }
+        };
+
+        method.visit_annotable_parameter_count(parameter_annotations.len() as U1, visible, cp);
+
+        for (parameter, annotations) in parameter_annotations.iter().enumerate() {
+            for annotation in &annotations.annotations {
+                if let Some(annotation_visitor) = method.visit_parameter_annotation(
+                    parameter as U1,
+                    annotation.type_index,
+                    visible,
+                    cp,
+                ) {
+                    visitor::annotation(annotation_visitor, annotation);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn class_file(&mut self) -> Result<ClassFile<'class>, ParsingError> {
         let magic = self.u4()?;
 
         if magic != /* 0xCAFEBABE */ consts::MAGIC {
@@ -1774,7 +2482,7 @@ impl<'class> Parser<'class> {
         let minor_v = self.u2()?;
         let major_v = self.u2()?;
         let cp_count = self.u2()?;
-        let cp = self.cp(cp_count.to_u2())?;
+        let cp = ConstantPool::new(self.cp(cp_count.to_u2())?);
         let access_flags = self.u2()?;
         let this_class = self.u2()?;
         let super_class = self.u2()?;
@@ -0,0 +1,273 @@
+//------ aftermath_cfparser -- Classfile parser for the Aftermath JVM. -------//
+//
+// Part of the Aftermath JVM, under the MIT license. The LICENSE file is present
+// at the project root, please consult to it for license information.
+//
+//===----------------------------------------------------------------------===//
+//
+// This file contains a checked, typed view over the constant pool of a class.
+//
+// Constant pool indexes are 1-based, index 0 is never valid, and the slot
+// after a Long or Double node is unusable. Every accessor in here takes the
+// raw index as it appears in the class file and returns an error naming the
+// expected node kind instead of panicking.
+//
+//===----------------------------------------------------------------------===//
+
+use crate::class_parser::{Class, CpNode, NameAndType, ParsingError};
+use crate::errors::CpNodeError;
+use std::ops::Deref;
+
+#[derive(Debug)]
+pub struct ConstantPool<'class> {
+    nodes: Vec<CpNode<'class>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemberKind {
+    Field,
+    Method,
+    InterfaceMethod,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NameAndTypeRef<'a> {
+    pub name: &'a str,
+    pub descriptor: &'a str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemberRef<'a> {
+    pub kind: MemberKind,
+    pub class_name: &'a str,
+    pub name: &'a str,
+    pub descriptor: &'a str,
+}
+
+/// A resolved loadable constant (JVMS §4.4), as pushed by `ldc` or used by a ConstantValue
+/// attribute.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Constant<'a> {
+    Integer(i32),
+    Float(f32),
+    Long(i64),
+    Double(f64),
+    String(&'a str),
+    Class(&'a str),
+    MethodType(&'a str),
+    MethodHandle {
+        reference_kind: u8,
+        reference: MemberRef<'a>,
+    },
+    Dynamic {
+        bootstrap_method_attr_index: u16,
+        name: &'a str,
+        descriptor: &'a str,
+    },
+}
+
+impl<'class> Deref for ConstantPool<'class> {
+    type Target = [CpNode<'class>];
+
+    fn deref(&self) -> &Self::Target {
+        &self.nodes
+    }
+}
+
+impl<'a, 'class> IntoIterator for &'a ConstantPool<'class> {
+    type Item = &'a CpNode<'class>;
+    type IntoIter = std::slice::Iter<'a, CpNode<'class>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.nodes.iter()
+    }
+}
+
+impl<'class> ConstantPool<'class> {
+    pub fn new(nodes: Vec<CpNode<'class>>) -> Self {
+        Self { nodes }
+    }
+
+    /// Returns the node at `index`, or `None` if `index` is 0 or out of range.
+    pub fn get(&self, index: u16) -> Option<&CpNode<'class>> {
+        self.nodes.get(usize::from(index).checked_sub(1)?)
+    }
+
+    fn expect<'a, T>(
+        &'a self,
+        index: u16,
+        expected: &'static [CpNodeError],
+        f: impl FnOnce(&'a CpNode<'class>) -> Option<T>,
+    ) -> Result<T, ParsingError> {
+        self.get(index)
+            .and_then(f)
+            .ok_or(ParsingError::InvalidCpIndex { expected, index })
+    }
+
+    pub fn utf8(&self, index: u16) -> Result<&str, ParsingError> {
+        self.expect(index, &[CpNodeError::Utf8], |node| match node {
+            CpNode::Utf8(utf8) => Some(&*utf8.bytes),
+            _ => None,
+        })
+    }
+
+    pub fn class(&self, index: u16) -> Result<&Class, ParsingError> {
+        self.expect(index, &[CpNodeError::Class], |node| match node {
+            CpNode::Class(class) => Some(class),
+            _ => None,
+        })
+    }
+
+    pub fn class_name(&self, index: u16) -> Result<&str, ParsingError> {
+        self.utf8(self.class(index)?.name_index.to_u2())
+    }
+
+    pub fn module_name(&self, index: u16) -> Result<&str, ParsingError> {
+        let name_index = self.expect(index, &[CpNodeError::Module], |node| match node {
+            CpNode::Module(module) => Some(module.name_index),
+            _ => None,
+        })?;
+
+        self.utf8(name_index.to_u2())
+    }
+
+    pub fn package_name(&self, index: u16) -> Result<&str, ParsingError> {
+        let name_index = self.expect(index, &[CpNodeError::Package], |node| match node {
+            CpNode::Package(package) => Some(package.name_index),
+            _ => None,
+        })?;
+
+        self.utf8(name_index.to_u2())
+    }
+
+    pub fn name_and_type(&self, index: u16) -> Result<NameAndTypeRef<'_>, ParsingError> {
+        let name_and_type: &NameAndType =
+            self.expect(index, &[CpNodeError::NameAndType], |node| match node {
+                CpNode::NameAndType(name_and_type) => Some(name_and_type),
+                _ => None,
+            })?;
+
+        Ok(NameAndTypeRef {
+            name: self.utf8(name_and_type.name_index.to_u2())?,
+            descriptor: self.utf8(name_and_type.descriptor_index.to_u2())?,
+        })
+    }
+
+    /// Resolves a FieldRef, MethodRef or InterfaceMethodRef node.
+    pub fn member_ref(&self, index: u16) -> Result<MemberRef<'_>, ParsingError> {
+        let (kind, class_index, name_and_type_index) = self.expect(
+            index,
+            &[
+                CpNodeError::FieldRef,
+                CpNodeError::MethodRef,
+                CpNodeError::InterfaceMethodRef,
+            ],
+            |node| match node {
+                CpNode::FieldRef(z) => {
+                    Some((MemberKind::Field, z.class_index, z.name_and_type_index))
+                }
+                CpNode::MethodRef(z) => {
+                    Some((MemberKind::Method, z.class_index, z.name_and_type_index))
+                }
+                CpNode::InterfaceMethodRef(z) => Some((
+                    MemberKind::InterfaceMethod,
+                    z.class_index,
+                    z.name_and_type_index,
+                )),
+                _ => None,
+            },
+        )?;
+
+        let name_and_type = self.name_and_type(name_and_type_index.to_u2())?;
+
+        Ok(MemberRef {
+            kind,
+            class_name: self.class_name(class_index.to_u2())?,
+            name: name_and_type.name,
+            descriptor: name_and_type.descriptor,
+        })
+    }
+
+    /// Resolves a String node to the contents of the Utf8 node it points to.
+    pub fn string_value(&self, index: u16) -> Result<&str, ParsingError> {
+        let string_index = self.expect(index, &[CpNodeError::String], |node| match node {
+            CpNode::String(string) => Some(string.string_index),
+            _ => None,
+        })?;
+
+        self.utf8(string_index.to_u2())
+    }
+
+    /// Resolves any loadable node (JVMS §4.4), which is what `ldc`, `ldc_w` and `ldc2_w` accept.
+    pub fn constant(&self, index: u16) -> Result<Constant<'_>, ParsingError> {
+        const LOADABLE: &[CpNodeError] = &[
+            CpNodeError::Integer,
+            CpNodeError::Float,
+            CpNodeError::Long,
+            CpNodeError::Double,
+            CpNodeError::Class,
+            CpNodeError::String,
+            CpNodeError::MethodHandle,
+            CpNodeError::MethodType,
+            CpNodeError::Dynamic,
+        ];
+
+        let node = self.get(index).ok_or(ParsingError::InvalidCpIndex {
+            expected: LOADABLE,
+            index,
+        })?;
+
+        Ok(match node {
+            CpNode::Integer(z) => Constant::Integer(z.value()),
+            CpNode::Float(z) => Constant::Float(z.value()),
+            CpNode::Long(z) => Constant::Long(z.value()),
+            CpNode::Double(z) => Constant::Double(z.value()),
+            CpNode::String(_) => Constant::String(self.string_value(index)?),
+            CpNode::Class(_) => Constant::Class(self.class_name(index)?),
+            CpNode::MethodType(z) => Constant::MethodType(self.utf8(z.descriptor_index.to_u2())?),
+            CpNode::MethodHandle(z) => Constant::MethodHandle {
+                reference_kind: z.reference_kind,
+                reference: self.member_ref(z.reference_index.to_u2())?,
+            },
+            CpNode::Dynamic(z) => {
+                let name_and_type = self.name_and_type(z.name_and_type_index.to_u2())?;
+
+                Constant::Dynamic {
+                    bootstrap_method_attr_index: z.bootstrap_method_attr_index.to_u2(),
+                    name: name_and_type.name,
+                    descriptor: name_and_type.descriptor,
+                }
+            }
+            _ => {
+                return Err(ParsingError::InvalidCpIndex {
+                    expected: LOADABLE,
+                    index,
+                })
+            }
+        })
+    }
+
+    /// Resolves the `constantvalue_index` of a ConstantValue attribute, which may only point to
+    /// an Integer, Float, Long, Double or String node.
+    pub fn constant_value(&self, index: u16) -> Result<Constant<'_>, ParsingError> {
+        match self.get(index) {
+            Some(
+                CpNode::Integer(_)
+                | CpNode::Float(_)
+                | CpNode::Long(_)
+                | CpNode::Double(_)
+                | CpNode::String(_),
+            ) => self.constant(index),
+            _ => Err(ParsingError::InvalidCpIndex {
+                expected: &[
+                    CpNodeError::Integer,
+                    CpNodeError::Float,
+                    CpNodeError::Long,
+                    CpNodeError::Double,
+                    CpNodeError::String,
+                ],
+                index,
+            }),
+        }
+    }
+}
@@ -11,7 +11,11 @@
 
 use std::ops::RangeInclusive;
 pub const MAGIC: u32 = 0xCAFEBABE;
-pub const MAJOR_VERSION_RANGE: RangeInclusive<u16> = 45..=61;
+pub const MAJOR_VERSION_RANGE: RangeInclusive<u16> = 45..=67;
+// Classes using preview features of a release have this minor version (JVMS §4.1).
+pub const PREVIEW_MINOR_VERSION: u16 = 0xFFFF;
+// Before Java 12 (major version 56) the minor version could be anything.
+pub const FIRST_MAJOR_VERSION_WITH_PREVIEWS: u16 = 56;
 
 pub mod class_acc_flags {
     pub const PUBLIC: u16 = 0x0001;
@@ -24,3 +28,49 @@ pub mod class_acc_flags {
     pub const ENUM: u16 = 0x4000;
     pub const MODULE: u16 = 0x8000;
 }
+
+pub mod field_acc_flags {
+    pub const PUBLIC: u16 = 0x0001;
+    pub const PRIVATE: u16 = 0x0002;
+    pub const PROTECTED: u16 = 0x0004;
+    pub const STATIC: u16 = 0x0008;
+    pub const FINAL: u16 = 0x0010;
+    pub const VOLATILE: u16 = 0x0040;
+    pub const TRANSIENT: u16 = 0x0080;
+    pub const SYNTHETIC: u16 = 0x1000;
+    pub const ENUM: u16 = 0x4000;
+}
+
+pub mod method_acc_flags {
+    pub const PUBLIC: u16 = 0x0001;
+    pub const PRIVATE: u16 = 0x0002;
+    pub const PROTECTED: u16 = 0x0004;
+    pub const STATIC: u16 = 0x0008;
+    pub const FINAL: u16 = 0x0010;
+    pub const SYNCHRONIZED: u16 = 0x0020;
+    pub const BRIDGE: u16 = 0x0040;
+    pub const VARARGS: u16 = 0x0080;
+    pub const NATIVE: u16 = 0x0100;
+    pub const ABSTRACT: u16 = 0x0400;
+    pub const STRICT: u16 = 0x0800;
+    pub const SYNTHETIC: u16 = 0x1000;
+}
+
+pub mod module_acc_flags {
+    pub const OPEN: u16 = 0x0020;
+    pub const SYNTHETIC: u16 = 0x1000;
+    pub const MANDATED: u16 = 0x8000;
+}
+
+pub mod requires_acc_flags {
+    pub const TRANSITIVE: u16 = 0x0020;
+    pub const STATIC_PHASE: u16 = 0x0040;
+    pub const SYNTHETIC: u16 = 0x1000;
+    pub const MANDATED: u16 = 0x8000;
+}
+
+// Shared by the exports and opens tables.
+pub mod exports_acc_flags {
+    pub const SYNTHETIC: u16 = 0x1000;
+    pub const MANDATED: u16 = 0x8000;
+}
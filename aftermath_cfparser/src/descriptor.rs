@@ -0,0 +1,254 @@
+//------ aftermath_cfparser -- Classfile parser for the Aftermath JVM. -------//
+//
+// Part of the Aftermath JVM, under the MIT license. The LICENSE file is present
+// at the project root, please consult to it for license information.
+//
+//===----------------------------------------------------------------------===//
+//
+// This file contains a parser for field and method descriptors (JVMS §4.3).
+//
+//===----------------------------------------------------------------------===//
+
+use crate::class_parser::ParsingError;
+use crate::errors::DescriptorError;
+use std::fmt::{self, Display};
+
+// Arrays can't have more than 255 dimensions (JVMS §4.3.2).
+const MAX_ARRAY_DIMENSIONS: usize = 255;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldType<'a> {
+    Byte,
+    Char,
+    Double,
+    Float,
+    Int,
+    Long,
+    Short,
+    Boolean,
+    // The binary name of the class, in its internal form (`java/lang/Object`).
+    Object(&'a str),
+    Array(Box<FieldType<'a>>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodDescriptor<'a> {
+    pub parameters: Vec<FieldType<'a>>,
+    // `None` for `void` methods.
+    pub return_type: Option<FieldType<'a>>,
+}
+
+struct DescriptorParser<'a> {
+    descriptor: &'a str,
+    index: usize,
+}
+
+impl<'a> FieldType<'a> {
+    pub fn parse(descriptor: &'a str) -> Result<Self, ParsingError> {
+        let mut parser = DescriptorParser {
+            descriptor,
+            index: 0,
+        };
+
+        let field_type = parser.field_type()?;
+        parser.end()?;
+
+        Ok(field_type)
+    }
+
+    /// The number of local variable or operand stack slots a value of this type takes up.
+    pub fn slots(&self) -> u16 {
+        match self {
+            FieldType::Long | FieldType::Double => 2,
+            _ => 1,
+        }
+    }
+
+    pub fn is_reference(&self) -> bool {
+        matches!(self, FieldType::Object(_) | FieldType::Array(_))
+    }
+
+    pub fn array_dimensions(&self) -> usize {
+        match self {
+            FieldType::Array(component) => 1 + component.array_dimensions(),
+            _ => 0,
+        }
+    }
+}
+
+impl<'a> MethodDescriptor<'a> {
+    pub fn parse(descriptor: &'a str) -> Result<Self, ParsingError> {
+        let mut parser = DescriptorParser {
+            descriptor,
+            index: 0,
+        };
+
+        parser.expect(b'(', DescriptorError::MissingParameterList)?;
+
+        let mut parameters = vec![];
+
+        while parser.peek()? != b')' {
+            parameters.push(parser.field_type()?);
+        }
+
+        parser.index += 1;
+
+        let return_type = if parser.peek()? == b'V' {
+            parser.index += 1;
+            None
+        } else {
+            Some(parser.field_type()?)
+        };
+
+        parser.end()?;
+
+        Ok(Self {
+            parameters,
+            return_type,
+        })
+    }
+
+    /// The number of local variable slots taken by the parameters, not counting `this`.
+    pub fn parameter_slots(&self) -> u16 {
+        self.parameters.iter().map(FieldType::slots).sum()
+    }
+
+    pub fn return_slots(&self) -> u16 {
+        self.return_type.as_ref().map_or(0, FieldType::slots)
+    }
+}
+
+impl<'a> DescriptorParser<'a> {
+    fn error(&self, kind: DescriptorError) -> ParsingError {
+        ParsingError::InvalidDescriptor {
+            descriptor: self.descriptor.to_string(),
+            offset: self.index,
+            kind,
+        }
+    }
+
+    fn peek(&self) -> Result<u8, ParsingError> {
+        self.descriptor
+            .as_bytes()
+            .get(self.index)
+            .copied()
+            .ok_or_else(|| self.error(DescriptorError::UnexpectedEnd))
+    }
+
+    fn expect(&mut self, byte: u8, kind: DescriptorError) -> Result<(), ParsingError> {
+        if self.peek()? != byte {
+            return Err(self.error(kind));
+        }
+
+        self.index += 1;
+        Ok(())
+    }
+
+    fn end(&self) -> Result<(), ParsingError> {
+        if self.index != self.descriptor.len() {
+            return Err(self.error(DescriptorError::TrailingCharacters));
+        }
+
+        Ok(())
+    }
+
+    fn field_type(&mut self) -> Result<FieldType<'a>, ParsingError> {
+        let start = self.index;
+
+        while self.peek()? == b'[' {
+            self.index += 1;
+        }
+
+        let dimensions = self.index - start;
+
+        if dimensions > MAX_ARRAY_DIMENSIONS {
+            self.index = start;
+            return Err(self.error(DescriptorError::TooManyDimensions));
+        }
+
+        let mut field_type = match self.peek()? {
+            b'B' => FieldType::Byte,
+            b'C' => FieldType::Char,
+            b'D' => FieldType::Double,
+            b'F' => FieldType::Float,
+            b'I' => FieldType::Int,
+            b'J' => FieldType::Long,
+            b'S' => FieldType::Short,
+            b'Z' => FieldType::Boolean,
+            b'L' => FieldType::Object(self.class_name()?),
+            _ => return Err(self.error(DescriptorError::InvalidType)),
+        };
+
+        if !matches!(field_type, FieldType::Object(_)) {
+            self.index += 1;
+        }
+
+        for _ in 0..dimensions {
+            field_type = FieldType::Array(Box::new(field_type));
+        }
+
+        Ok(field_type)
+    }
+
+    // Parses `L<binary name>;`, checking that the name is made of valid unqualified names
+    // (JVMS §4.2.2) separated by `/`.
+    fn class_name(&mut self) -> Result<&'a str, ParsingError> {
+        let start = self.index + 1;
+        let length = self.descriptor[start..]
+            .find(';')
+            .ok_or_else(|| self.error(DescriptorError::UnterminatedClassName))?;
+        let name = &self.descriptor[start..start + length];
+
+        let mut offset = start;
+
+        for segment in name.split('/') {
+            if segment.is_empty() {
+                self.index = offset;
+                return Err(self.error(DescriptorError::InvalidClassName));
+            }
+
+            if let Some(position) = segment.find(['.', '[']) {
+                self.index = offset + position;
+                return Err(self.error(DescriptorError::InvalidClassName));
+            }
+
+            offset += segment.len() + 1;
+        }
+
+        self.index = start + length + 1;
+        Ok(name)
+    }
+}
+
+impl Display for FieldType<'_> {
+    // Renders the type back into its descriptor form.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldType::Byte => write!(f, "B"),
+            FieldType::Char => write!(f, "C"),
+            FieldType::Double => write!(f, "D"),
+            FieldType::Float => write!(f, "F"),
+            FieldType::Int => write!(f, "I"),
+            FieldType::Long => write!(f, "J"),
+            FieldType::Short => write!(f, "S"),
+            FieldType::Boolean => write!(f, "Z"),
+            FieldType::Object(name) => write!(f, "L{};", name),
+            FieldType::Array(component) => write!(f, "[{}", component),
+        }
+    }
+}
+
+impl Display for MethodDescriptor<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(")?;
+
+        for parameter in &self.parameters {
+            write!(f, "{}", parameter)?;
+        }
+
+        match &self.return_type {
+            Some(return_type) => write!(f, "){}", return_type),
+            None => write!(f, ")V"),
+        }
+    }
+}
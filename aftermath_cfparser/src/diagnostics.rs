@@ -0,0 +1,160 @@
+//------ aftermath_cfparser -- Classfile parser for the Aftermath JVM. -------//
+//
+// Part of the Aftermath JVM, under the MIT license. The LICENSE file is present
+// at the project root, please consult to it for license information.
+//
+//===----------------------------------------------------------------------===//
+//
+// This file contains Diagnostics, the report the Verifier fills in.
+//
+// Every problem found with a class is kept as a Diagnostic, with how severe it
+// is, where in the class it was found and the error itself, which carries a
+// stable code. Errors make the class fail verification, warnings don't.
+//
+//===----------------------------------------------------------------------===//
+
+use crate::errors::ParsingError;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    // The structure the problem is in, like `methods[3].attributes[Code]`. Empty for the class
+    // itself. Problems spanning several classes start with the name of the class, as in
+    // `Foo.methods[3]`.
+    pub path: String,
+    pub error: ParsingError,
+}
+
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    entries: Vec<Diagnostic>,
+}
+
+impl Diagnostic {
+    pub fn code(&self) -> &'static str {
+        self.error.code()
+    }
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, severity: Severity, path: impl Into<String>, error: ParsingError) {
+        self.entries.push(Diagnostic {
+            severity,
+            path: path.into(),
+            error,
+        });
+    }
+
+    pub fn error(&mut self, path: impl Into<String>, error: ParsingError) {
+        self.push(Severity::Error, path, error);
+    }
+
+    pub fn warning(&mut self, path: impl Into<String>, error: ParsingError) {
+        self.push(Severity::Warning, path, error);
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Diagnostic> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.iter().filter(|z| z.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.iter().filter(|z| z.severity == Severity::Warning)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    /// Passes when there are no errors, handing back the warnings. Fails with every diagnostic
+    /// otherwise.
+    pub fn into_result(self) -> Result<Self, Self> {
+        if self.has_errors() {
+            Err(self)
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+impl IntoIterator for Diagnostics {
+    type Item = Diagnostic;
+    type IntoIter = std::vec::IntoIter<Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a Diagnostic;
+    type IntoIter = std::slice::Iter<'a, Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}[{}]: {}",
+            self.severity,
+            self.code(),
+            self.error.kind()
+        )?;
+
+        if !self.path.is_empty() {
+            write!(f, "\n  --> in {}", self.path)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diagnostic in self {
+            writeln!(f, "{}", diagnostic)?;
+        }
+
+        write!(
+            f,
+            "{} error(s), {} warning(s)",
+            self.errors().count(),
+            self.warnings().count()
+        )
+    }
+}
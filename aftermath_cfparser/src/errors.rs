@@ -9,10 +9,10 @@
 //
 //===----------------------------------------------------------------------===//
 
-use std::str::Utf8Error;
+use std::fmt::{self, Display};
 use thiserror::Error;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CpNodeError {
     Class,
     String,
@@ -34,7 +34,30 @@ pub enum CpNodeError {
     None,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescriptorError {
+    UnexpectedEnd,
+    InvalidType,
+    MissingParameterList,
+    UnterminatedClassName,
+    InvalidClassName,
+    TooManyDimensions,
+    TrailingCharacters,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureError {
+    UnexpectedEnd,
+    InvalidType,
+    InvalidIdentifier,
+    MissingBound,
+    MissingParameterList,
+    TooManyDimensions,
+    TooDeeplyNested,
+    TrailingCharacters,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Attributes {
     Value,
     Code,
@@ -66,10 +89,11 @@ pub enum Attributes {
     NestMembers,
     Record,
     PermittedSubclasses,
+    Unknown,
 }
 
 #[derive(Error, Debug)]
-pub enum ParsingError<'a> {
+pub enum ParsingError {
     // Parsing errors.
     #[error(
         "Malformed class -> The magic of the class file should only be 0xCAFEBABE! ☕ 💃 ✨
@@ -88,9 +112,40 @@ pub enum ParsingError<'a> {
     InvalidFrameType(u8),
     #[error("Malformed class -> Invalid verification type info tag -> `{0}`")]
     InvalidTagVerificationTypeInfo(u8),
+    #[error(
+        "Malformed class -> Constant pool index {index} does not point to a {expected:?} node."
+    )]
+    InvalidCpIndex {
+        expected: &'static [CpNodeError],
+        index: u16,
+    },
+    #[error("Malformed class -> Invalid descriptor `{descriptor}` at offset {offset} -> {kind:?}")]
+    InvalidDescriptor {
+        descriptor: String,
+        offset: usize,
+        kind: DescriptorError,
+    },
+    #[error("Malformed class -> Invalid signature `{signature}` at offset {offset} -> {kind:?}")]
+    InvalidSignature {
+        signature: String,
+        offset: usize,
+        kind: SignatureError,
+    },
+    #[error("Malformed class -> Truncated instruction at pc {pc}.")]
+    TruncatedInstruction { pc: u32 },
+    #[error("Malformed class -> Invalid opcode {opcode:#04X} at pc {pc}.")]
+    InvalidOpcode { pc: u32, opcode: u8 },
+    #[error("Malformed class -> Invalid instruction operand at pc {pc} -> {reason}.")]
+    InvalidInstructionOperand { pc: u32, reason: &'static str },
     #[error("Malformed class -> Invalid Attribute -> `{0}`")]
     InvalidAttribute(String),
-    #[error("I/O Error -> ")]
+    #[error("Malformed class -> The `{name}` attribute declared a length of {declared} bytes, but its body was {consumed} bytes long.")]
+    AttributeLengthMismatch {
+        name: String,
+        declared: u32,
+        consumed: usize,
+    },
+    #[error("I/O Error -> {0}")]
     IoError(#[from] std::io::Error),
     // ---------------------------------------------------------------------------------------------
     // Verification errors
@@ -98,6 +153,12 @@ pub enum ParsingError<'a> {
     InvalidMajorV,
     #[error("Malformed class -> Invalid Minor version")]
     InvalidMinorV,
+    #[error("Unsupported class -> The class (major version {major_v}) uses preview features, but previews aren't enabled.
+        Help ~> Was it compiled with --enable-preview?")]
+    PreviewNotEnabled { major_v: u16 },
+    #[error("Unsupported class -> The class uses preview features of major version {major_v}, but previews are only enabled for major version {enabled}.
+        Help ~> Preview features only work on the exact release they were compiled for, recompile the class for that release.")]
+    PreviewVersionMismatch { major_v: u16, enabled: u16 },
     #[error("Malformed class -> The interface flag was set without the abstract flag being set, which is invalid.")]
     InterfaceWithoutAbstract,
     #[error("Malformed class -> The interface flag was set, while one (or all) of the following flags was set, which is illegal.
@@ -116,6 +177,84 @@ pub enum ParsingError<'a> {
     FinalAndAbstractWhileNonInterface,
     #[error("Malformed class -> The annotation flag was set, but the interface flag was not set, which is illegal.")]
     AnnotationWithoutInterface,
+    #[error("Malformed class -> More than one of the public, private and protected flags was set on a field or method, they're mutually exclusive.")]
+    MultipleAccessModifiers,
+    #[error("Malformed class -> A field had both the final and the volatile flags set, which is illegal.")]
+    FinalAndVolatileField,
+    #[error("Malformed class -> A field of an interface must have the public, static and final flags set, and may only have the synthetic flag set besides them.")]
+    IllegalFlagsOnInterfaceField,
+    #[error("Malformed class -> A method of an interface had illegal flags set.
+    • Since major version 52, it must be either public or private, and can't be protected, final, synchronized or native
+    • Before major version 52, it must be public and abstract, and may only be varargs, bridge or synthetic besides that")]
+    IllegalFlagsOnInterfaceMethod,
+    #[error("Malformed class -> An abstract method had one (or all) of the following flags set, which is illegal.
+    • Private
+    • Static
+    • Final
+    • Synchronized
+    • Native
+    • Strict (from major version 46 up to 60)")]
+    IllegalFlagsOnAbstractMethod,
+    #[error("Malformed class -> An <init> method may only have the public, private, protected, varargs, strict and synthetic flags set.")]
+    IllegalFlagsOnInit,
+    #[error("Malformed class -> Since major version 51, the <clinit> method must have the static flag set.")]
+    ClinitNotStatic,
+    #[error(
+        "Malformed class -> An abstract or native method had a Code attribute, which is illegal."
+    )]
+    CodeInAbstractOrNativeMethod,
+    #[error("Malformed class -> A method that is neither abstract nor native must have exactly one Code attribute.")]
+    NotExactlyOneCodeAttribute,
+    #[error("Verification failed -> At pc {pc}, expected {expected} but found {found}.")]
+    TypeMismatch {
+        pc: u32,
+        expected: String,
+        found: String,
+    },
+    #[error("Verification failed -> At pc {pc}, {reason}.")]
+    InvalidBytecode { pc: u32, reason: &'static str },
+    #[error("Verification incomplete -> {from} was assumed to be assignable to {to}, as the class hierarchy doesn't know enough of the classes involved.")]
+    AssumedAssignable { from: String, to: String },
+    #[error("Malformed class -> The {1} field of a {0:?} attribute wasn't the start of an instruction in the code array.")]
+    InvalidPcInAttribute(Attributes, &'static str),
+    #[error("Invalid hierarchy -> {class} extends {superclass}, which is final.")]
+    ExtendsFinalClass { class: String, superclass: String },
+    #[error("Invalid hierarchy -> {class} extends {superclass}, which is an interface.")]
+    SuperclassIsInterface { class: String, superclass: String },
+    #[error("Invalid hierarchy -> {class} implements {interface}, which isn't an interface.")]
+    NotAnInterface { class: String, interface: String },
+    #[error("Invalid hierarchy -> {0} is its own superclass or superinterface.")]
+    CircularInheritance(String),
+    #[error("Invalid hierarchy -> {class} extends or implements {sealed}, which is sealed and doesn't permit it.")]
+    NotPermittedSubclass { class: String, sealed: String },
+    #[error(
+        "Invalid hierarchy -> {sealed} permits {class}, which doesn't extend or implement it."
+    )]
+    PermittedSubclassNotExtending { class: String, sealed: String },
+    #[error("Invalid hierarchy -> {member} and {host} don't agree on {host} being the nest host of {member}.")]
+    NestMismatch { host: String, member: String },
+    #[error("Invalid hierarchy -> {class} overrides {method} of {superclass}, which is final.")]
+    OverridesFinalMethod {
+        class: String,
+        method: String,
+        superclass: String,
+    },
+    #[error("Invalid record -> A record class must have the final flag set.")]
+    RecordNotFinal,
+    #[error("Invalid record -> A record class must extend java/lang/Record.")]
+    RecordSuperclassNotRecord,
+    #[error("Invalid record -> The record component {0} has no private final field with its name and descriptor.")]
+    RecordComponentWithoutField(String),
+    #[error("Invalid record -> The instance field {0} isn't one of the record components.")]
+    RecordFieldWithoutComponent(String),
+    #[error("Invalid record -> The record component {0} has no public accessor method with its name, taking no parameters and returning its type.")]
+    RecordComponentWithoutAccessor(String),
+    #[error("Malformed class -> An open module can't have an opens table.")]
+    OpensInOpenModule,
+    #[error("Malformed class -> Every module but java.base must require java.base exactly once, and java.base can't require anything.")]
+    InvalidJavaBaseRequires,
+    #[error("Malformed class -> The package {0} is exported, opened, or holds the main class or a service implementation of the module, but isn't listed in its ModulePackages attribute.")]
+    PackageNotInModule(String),
     #[error("Malformed class -> Invalid version as Module, major version must be equal or bigger than 53.")]
     InvalidVersionAsModule,
     #[error(
@@ -146,13 +285,17 @@ pub enum ParsingError<'a> {
     MethodHandle1to4NotPointingToFieldRef,
     #[error("Malformed class -> The value of reference_kind in a MethodHandle constant pool node was either 5 or 8, but the reference_index didn't point to a MethodRef, which is illegal.")]
     MethodHandle5or8NotPointingToMethodRef,
+    #[error("Malformed class -> The value of reference_kind in a MethodHandle constant pool node was either 6 or 7, but the reference_index didn't point to a MethodRef, or to an InterfaceMethodRef from version 52 on, which is illegal.")]
+    MethodHandle6or7NotPointingToMethod,
+    #[error("Malformed class -> The value of reference_kind in a MethodHandle constant pool node was 9, but the reference_index didn't point to an InterfaceMethodRef, which is illegal.")]
+    MethodHandle9NotPointingToInterfaceMethodRef,
 
     #[error("Malformed class -> A {0:?} node in the constant pool did not point to a {1:?} node in the constant pool in the {2} field, which is illegal.")]
-    InvalidIndexFromNodeToNode(CpNodeError, CpNodeError, &'a str),
+    InvalidIndexFromNodeToNode(CpNodeError, CpNodeError, &'static str),
     #[error("Malformed class -> A {0:?} attribute in the constant pool did not point to a {1:?} node in the constant pool in the {2} field, which is illegal.")]
-    InvalidIndexFromAttributeToNode(Attributes, CpNodeError, &'a str),
+    InvalidIndexFromAttributeToNode(Attributes, CpNodeError, &'static str),
     #[error("Malformed class -> A {0:?} attribute in the constant pool did not point to any of {1:?} nodes in the constant pool in the {2} field, which is illegal.")]
-    InvalidIndexFromAttributeToNodes(Attributes, &'a [CpNodeError], &'a str),
+    InvalidIndexFromAttributeToNodes(Attributes, &'static [CpNodeError], &'static str),
 
     #[error("Malformed class -> This class file is a module, but it either
     • Did not have a Module attribute
@@ -213,6 +356,173 @@ pub enum ParsingError<'a> {
     InvalidConstantPoolLength,
     #[error("InvalidTag -> Tag can't be 0")]
     InvalidTag,
-    #[error("Utf8Error -> ")]
-    Utf8Error(#[from] Utf8Error),
+    #[error("Malformed class -> Invalid Modified UTF-8 in a Utf8 constant pool node at byte offset {offset}.")]
+    InvalidMutf8 { offset: usize },
+    #[error("Can't write class -> {0} doesn't fit in its field.")]
+    TooLarge(&'static str),
+    #[error("Can't write class -> No Utf8 constant pool node holds the attribute name {0}.")]
+    AttributeNameNotInConstantPool(String),
+    #[error("Unknown attribute -> `{0}` isn't an attribute the JVM knows of, it will be ignored.")]
+    UnknownAttribute(String),
+
+    // Wraps any of the above with where in the class file it happened.
+    #[error("{error}\n{location}")]
+    Located {
+        error: Box<ParsingError>,
+        location: Box<ErrorLocation>,
+    },
+}
+
+// Where a parsing error happened: the byte offset, the path of the structure being parsed
+// (`methods[12].attributes[Code].attributes[StackMapTable].entries[3]`) and the bytes around the
+// offset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorLocation {
+    pub offset: usize,
+    pub path: String,
+    pub excerpt_offset: usize,
+    pub excerpt: Vec<u8>,
+}
+
+impl ParsingError {
+    /// The error itself, without the location it may be wrapped with.
+    pub fn kind(&self) -> &ParsingError {
+        match self {
+            ParsingError::Located { error, .. } => error,
+            error => error,
+        }
+    }
+
+    pub fn location(&self) -> Option<&ErrorLocation> {
+        match self {
+            ParsingError::Located { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+
+    /// A stable code for the kind of error, such as `E0017` for `InvalidMajorV`. Codes are never
+    /// renumbered or reused, new kinds of errors get the next free code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParsingError::Magic => "E0001",
+            ParsingError::ConstantPoolTag(..) => "E0002",
+            ParsingError::AttributeNotUtf8 => "E0003",
+            ParsingError::InvalidElementValue(..) => "E0004",
+            ParsingError::InvalidTargetType(..) => "E0005",
+            ParsingError::InvalidFrameType(..) => "E0006",
+            ParsingError::InvalidTagVerificationTypeInfo(..) => "E0007",
+            ParsingError::InvalidCpIndex { .. } => "E0008",
+            ParsingError::InvalidDescriptor { .. } => "E0009",
+            ParsingError::InvalidSignature { .. } => "E0010",
+            ParsingError::TruncatedInstruction { .. } => "E0011",
+            ParsingError::InvalidOpcode { .. } => "E0012",
+            ParsingError::InvalidInstructionOperand { .. } => "E0013",
+            ParsingError::InvalidAttribute(..) => "E0014",
+            ParsingError::AttributeLengthMismatch { .. } => "E0015",
+            ParsingError::IoError(..) => "E0016",
+            ParsingError::InvalidMajorV => "E0017",
+            ParsingError::InvalidMinorV => "E0018",
+            ParsingError::PreviewNotEnabled { .. } => "E0019",
+            ParsingError::PreviewVersionMismatch { .. } => "E0020",
+            ParsingError::InterfaceWithoutAbstract => "E0021",
+            ParsingError::IllegalFlagsWhileInterface => "E0022",
+            ParsingError::IllegalFlagsWhileNonInterface => "E0023",
+            ParsingError::FinalAndAbstractWhileNonInterface => "E0024",
+            ParsingError::AnnotationWithoutInterface => "E0025",
+            ParsingError::InvalidVersionAsModule => "E0026",
+            ParsingError::BinaryNameContainsDot => "E0027",
+            ParsingError::ThisClassNotModuleInfoAsModule => "E0028",
+            ParsingError::VarsNotZeroAsModule => "E0029",
+            ParsingError::InvalidAmountOfBootStrapMethodsInClass => "E0030",
+            ParsingError::BootstrapMethodAttrIndexInDynamicAttributeIsNotValidIndex => "E0031",
+            ParsingError::BootstrapMethodAttrIndexInInvokeDynamicAttributeIsNotValidIndex => {
+                "E0032"
+            }
+            ParsingError::InvalidReferenceKind => "E0033",
+            ParsingError::MethodHandle1to4NotPointingToFieldRef => "E0034",
+            ParsingError::MethodHandle5or8NotPointingToMethodRef => "E0035",
+            ParsingError::InvalidIndexFromNodeToNode(..) => "E0036",
+            ParsingError::InvalidIndexFromAttributeToNode(..) => "E0037",
+            ParsingError::InvalidIndexFromAttributeToNodes(..) => "E0038",
+            ParsingError::InvalidAttributesAsModule => "E0039",
+            ParsingError::CodeAttributeCodeLength => "E0040",
+            ParsingError::InvalidIndexesInCodeAttribute => "E0041",
+            ParsingError::InvalidClassAttributes => "E0042",
+            ParsingError::InvalidFieldInfoAttributes => "E0043",
+            ParsingError::InvalidMethodInfoAttributes => "E0044",
+            ParsingError::InvalidCodeAttributes => "E0045",
+            ParsingError::InvalidRecordComponentInfoAttributes => "E0046",
+            ParsingError::OutOfBounds => "E0047",
+            ParsingError::InvalidConstantPoolLength => "E0048",
+            ParsingError::InvalidTag => "E0049",
+            ParsingError::InvalidMutf8 { .. } => "E0050",
+            ParsingError::TooLarge(..) => "E0051",
+            ParsingError::AttributeNameNotInConstantPool(..) => "E0052",
+            ParsingError::UnknownAttribute(..) => "E0053",
+            ParsingError::MultipleAccessModifiers => "E0054",
+            ParsingError::FinalAndVolatileField => "E0055",
+            ParsingError::IllegalFlagsOnInterfaceField => "E0056",
+            ParsingError::IllegalFlagsOnInterfaceMethod => "E0057",
+            ParsingError::IllegalFlagsOnAbstractMethod => "E0058",
+            ParsingError::IllegalFlagsOnInit => "E0059",
+            ParsingError::ClinitNotStatic => "E0060",
+            ParsingError::CodeInAbstractOrNativeMethod => "E0061",
+            ParsingError::NotExactlyOneCodeAttribute => "E0062",
+            ParsingError::TypeMismatch { .. } => "E0063",
+            ParsingError::InvalidBytecode { .. } => "E0064",
+            ParsingError::InvalidPcInAttribute(..) => "E0065",
+            ParsingError::ExtendsFinalClass { .. } => "E0066",
+            ParsingError::SuperclassIsInterface { .. } => "E0067",
+            ParsingError::NotAnInterface { .. } => "E0068",
+            ParsingError::CircularInheritance(..) => "E0069",
+            ParsingError::NotPermittedSubclass { .. } => "E0070",
+            ParsingError::PermittedSubclassNotExtending { .. } => "E0071",
+            ParsingError::NestMismatch { .. } => "E0072",
+            ParsingError::OverridesFinalMethod { .. } => "E0073",
+            ParsingError::RecordNotFinal => "E0074",
+            ParsingError::RecordSuperclassNotRecord => "E0075",
+            ParsingError::RecordComponentWithoutField(..) => "E0076",
+            ParsingError::RecordFieldWithoutComponent(..) => "E0077",
+            ParsingError::RecordComponentWithoutAccessor(..) => "E0078",
+            ParsingError::OpensInOpenModule => "E0079",
+            ParsingError::InvalidJavaBaseRequires => "E0080",
+            ParsingError::PackageNotInModule(..) => "E0081",
+            ParsingError::MethodHandle6or7NotPointingToMethod => "E0082",
+            ParsingError::MethodHandle9NotPointingToInterfaceMethodRef => "E0083",
+            ParsingError::AssumedAssignable { .. } => "E0084",
+            ParsingError::Located { error, .. } => error.code(),
+        }
+    }
+}
+
+impl Display for ErrorLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "  --> at byte offset {:#x}", self.offset)?;
+
+        if !self.path.is_empty() {
+            write!(f, ", in {}", self.path)?;
+        }
+
+        // 16 bytes per line, with the offending byte in brackets.
+        for (line, chunk) in self.excerpt.chunks(16).enumerate() {
+            let line_offset = self.excerpt_offset + line * 16;
+            write!(f, "\n   {:08x} |", line_offset)?;
+
+            for (column, byte) in chunk.iter().enumerate() {
+                if line_offset + column == self.offset {
+                    write!(f, "[{:02x}]", byte)?;
+                } else if line_offset + column == self.offset + 1 {
+                    write!(f, "{:02x}", byte)?;
+                } else {
+                    write!(f, " {:02x}", byte)?;
+                }
+            }
+        }
+
+        if self.offset >= self.excerpt_offset + self.excerpt.len() {
+            write!(f, "\n   (the error is at the end of the input)")?;
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,303 @@
+//------ aftermath_cfparser -- Classfile parser for the Aftermath JVM. -------//
+//
+// Part of the Aftermath JVM, under the MIT license. The LICENSE file is present
+// at the project root, please consult to it for license information.
+//
+//===----------------------------------------------------------------------===//
+//
+// This file contains the HierarchyValidator, which checks what the Verifier
+// can't see from a single class: how a set of classes, like the ones of an
+// application's classpath, relate to each other.
+//
+// Superclasses can't be final or interfaces, the interfaces a class implements
+// have to be interfaces, and nothing can inherit from itself (JVMS §5.3.5).
+// Sealed classes have to permit their subclasses (JVMS §4.7.31), nest hosts
+// and nest members have to list each other (JVMS §5.4.4), and final methods
+// can't be overridden (JVMS §5.4.5).
+//
+// Only the classes in the set are looked at. A class referring to one that
+// isn't is assumed to be right about it.
+//
+//===----------------------------------------------------------------------===//
+
+use crate::class_parser::{Attributes, ClassFile, MethodInfo, U2};
+use crate::consts::{class_acc_flags, method_acc_flags};
+use crate::diagnostics::Diagnostics;
+use crate::errors::ParsingError;
+use crate::typecheck::MAX_HIERARCHY_DEPTH;
+use std::collections::{BTreeMap, BTreeSet};
+
+pub struct HierarchyValidator<'a> {
+    classes: BTreeMap<&'a str, Class<'a>>,
+}
+
+// A class of the set, with the names it refers to resolved.
+struct Class<'a> {
+    file: &'a ClassFile<'a>,
+    name: &'a str,
+    superclass: Option<&'a str>,
+    interfaces: Vec<&'a str>,
+    // None when the class isn't sealed.
+    permitted: Option<Vec<&'a str>>,
+    nest_host: Option<&'a str>,
+    nest_members: Vec<&'a str>,
+}
+
+impl<'a> HierarchyValidator<'a> {
+    /// Classes whose names can't be resolved are left out, the Verifier reports those. When two
+    /// classes have the same name, the first one is used.
+    pub fn new(classes: &'a [ClassFile<'a>]) -> Self {
+        let mut by_name = BTreeMap::new();
+
+        for file in classes {
+            if let Some(class) = Class::new(file) {
+                by_name.entry(class.name).or_insert(class);
+            }
+        }
+
+        Self { classes: by_name }
+    }
+
+    /// Everything wrong with how the classes relate, by class name. The paths start with the name
+    /// of the class the problem is reported on.
+    pub fn diagnostics(&self) -> Diagnostics {
+        let mut diagnostics = Diagnostics::new();
+
+        for class in self.classes.values() {
+            self.class_diagnostics(&mut diagnostics, class);
+        }
+
+        diagnostics
+    }
+
+    fn class_diagnostics(&self, diagnostics: &mut Diagnostics, class: &Class) {
+        // Everything else walks up the hierarchy, which would go around in circles.
+        if self.is_circular(class.name) {
+            diagnostics.error(
+                class.name,
+                ParsingError::CircularInheritance(class.name.to_string()),
+            );
+            return;
+        }
+
+        if let Some(superclass) = class.superclass.and_then(|z| self.classes.get(z)) {
+            let flags = superclass.file.access_flags.to_u2();
+
+            if flags & class_acc_flags::INTERFACE != 0 {
+                diagnostics.error(
+                    class.name,
+                    ParsingError::SuperclassIsInterface {
+                        class: class.name.to_string(),
+                        superclass: superclass.name.to_string(),
+                    },
+                );
+            } else if flags & class_acc_flags::FINAL != 0 {
+                diagnostics.error(
+                    class.name,
+                    ParsingError::ExtendsFinalClass {
+                        class: class.name.to_string(),
+                        superclass: superclass.name.to_string(),
+                    },
+                );
+            }
+
+            self.verify_permitted(diagnostics, class, superclass);
+        }
+
+        for interface in class.interfaces.iter().filter_map(|z| self.classes.get(z)) {
+            if interface.file.access_flags.to_u2() & class_acc_flags::INTERFACE == 0 {
+                diagnostics.error(
+                    class.name,
+                    ParsingError::NotAnInterface {
+                        class: class.name.to_string(),
+                        interface: interface.name.to_string(),
+                    },
+                );
+            }
+
+            self.verify_permitted(diagnostics, class, interface);
+        }
+
+        // The JVM doesn't mind a sealed class permitting classes that don't extend it, but
+        // javac would never have written it.
+        for permitted in class.permitted.iter().flatten() {
+            let Some(permitted) = self.classes.get(permitted) else {
+                continue;
+            };
+
+            if permitted.superclass != Some(class.name)
+                && !permitted.interfaces.contains(&class.name)
+            {
+                diagnostics.warning(
+                    format!("{}.attributes[PermittedSubclasses]", class.name),
+                    ParsingError::PermittedSubclassNotExtending {
+                        class: permitted.name.to_string(),
+                        sealed: class.name.to_string(),
+                    },
+                );
+            }
+        }
+
+        self.verify_nest(diagnostics, class);
+
+        for (i, method) in class.file.methods.iter().enumerate() {
+            if let Some(error) = self.overridden_final(class, method) {
+                diagnostics.error(format!("{}.methods[{}]", class.name, i), error);
+            }
+        }
+    }
+
+    // Whether `name` can be reached by going up the superclasses and superinterfaces of `name`.
+    fn is_circular(&self, name: &str) -> bool {
+        let mut visited = BTreeSet::new();
+        let mut pending = vec![name];
+
+        while let Some(current) = pending.pop() {
+            let Some(class) = self.classes.get(current) else {
+                continue;
+            };
+
+            for supertype in class.superclass.iter().chain(&class.interfaces) {
+                if *supertype == name {
+                    return true;
+                }
+
+                if visited.insert(*supertype) {
+                    pending.push(supertype);
+                }
+            }
+        }
+
+        false
+    }
+
+    fn verify_permitted(&self, diagnostics: &mut Diagnostics, class: &Class, supertype: &Class) {
+        match &supertype.permitted {
+            Some(permitted) if !permitted.contains(&class.name) => diagnostics.error(
+                class.name,
+                ParsingError::NotPermittedSubclass {
+                    class: class.name.to_string(),
+                    sealed: supertype.name.to_string(),
+                },
+            ),
+            _ => {}
+        }
+    }
+
+    fn verify_nest(&self, diagnostics: &mut Diagnostics, class: &Class) {
+        if let Some(host) = class.nest_host.and_then(|z| self.classes.get(z)) {
+            if !host.nest_members.contains(&class.name) {
+                diagnostics.error(
+                    format!("{}.attributes[NestHost]", class.name),
+                    ParsingError::NestMismatch {
+                        host: host.name.to_string(),
+                        member: class.name.to_string(),
+                    },
+                );
+            }
+        }
+
+        for member in class
+            .nest_members
+            .iter()
+            .filter_map(|z| self.classes.get(z))
+        {
+            if member.nest_host != Some(class.name) {
+                diagnostics.error(
+                    format!("{}.attributes[NestMembers]", class.name),
+                    ParsingError::NestMismatch {
+                        host: class.name.to_string(),
+                        member: member.name.to_string(),
+                    },
+                );
+            }
+        }
+    }
+
+    // The final method of a superclass `method` overrides, if there's one. Private and static
+    // methods don't override anything, and aren't overridden, and package private methods are only
+    // overridden from the same package.
+    fn overridden_final(&self, class: &Class, method: &MethodInfo) -> Option<ParsingError> {
+        use method_acc_flags::{FINAL, PRIVATE, PROTECTED, PUBLIC, STATIC};
+
+        let cp = &class.file.cp;
+        let name = cp.utf8(method.name_index.to_u2()).ok()?;
+        let descriptor = cp.utf8(method.descriptor_index.to_u2()).ok()?;
+
+        if name.starts_with('<') || method.access_flags.to_u2() & (PRIVATE | STATIC) != 0 {
+            return None;
+        }
+
+        let mut superclass = class.superclass;
+
+        for _ in 0..MAX_HIERARCHY_DEPTH {
+            let current = self.classes.get(superclass?)?;
+
+            let overridden = current.file.methods.iter().find(|z| {
+                current.file.cp.utf8(z.name_index.to_u2()).ok() == Some(name)
+                    && current.file.cp.utf8(z.descriptor_index.to_u2()).ok() == Some(descriptor)
+            });
+
+            if let Some(overridden) = overridden {
+                let flags = overridden.access_flags.to_u2();
+                let accessible = flags & (PUBLIC | PROTECTED) != 0
+                    || package(current.name) == package(class.name);
+
+                if flags & FINAL != 0 && flags & (PRIVATE | STATIC) == 0 && accessible {
+                    return Some(ParsingError::OverridesFinalMethod {
+                        class: class.name.to_string(),
+                        method: format!("{}{}", name, descriptor),
+                        superclass: current.name.to_string(),
+                    });
+                }
+            }
+
+            superclass = current.superclass;
+        }
+
+        None
+    }
+}
+
+impl<'a> Class<'a> {
+    fn new(file: &'a ClassFile<'a>) -> Option<Self> {
+        let cp = &file.cp;
+        let names = |classes: &[U2]| -> Vec<&'a str> {
+            classes
+                .iter()
+                .filter_map(|z| cp.class_name(z.to_u2()).ok())
+                .collect()
+        };
+
+        let mut class = Self {
+            file,
+            name: cp.class_name(file.this_class.to_u2()).ok()?,
+            superclass: match file.super_class.to_u2() {
+                0 => None,
+                index => cp.class_name(index).ok(),
+            },
+            interfaces: names(file.interfaces),
+            permitted: None,
+            nest_host: None,
+            nest_members: vec![],
+        };
+
+        for attribute in &file.attributes {
+            match attribute {
+                Attributes::PermittedSubclasses(z) => class.permitted = Some(names(z.classes)),
+                Attributes::NestHost(z) => {
+                    class.nest_host = cp.class_name(z.host_class_index.to_u2()).ok()
+                }
+                Attributes::NestMembers(z) => class.nest_members = names(z.classes),
+                _ => {}
+            }
+        }
+
+        Some(class)
+    }
+}
+
+// The package of a class in internal form, empty for the unnamed package.
+pub(crate) fn package(class: &str) -> &str {
+    class.rsplit_once('/').map_or("", |(package, _)| package)
+}
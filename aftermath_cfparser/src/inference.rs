@@ -0,0 +1,439 @@
+//------ aftermath_cfparser -- Classfile parser for the Aftermath JVM. -------//
+//
+// Part of the Aftermath JVM, under the MIT license. The LICENSE file is present
+// at the project root, please consult to it for license information.
+//
+//===----------------------------------------------------------------------===//
+//
+// This file contains the verification by type inference of JVMS §4.10.2, for
+// classes older than major version 50, which have no StackMapTable.
+//
+// The types of the locals and operand stack at each instruction are found by
+// data-flow analysis: starting from the entry of the method, the state after
+// an instruction is merged into the state of each of its successors, until
+// nothing changes anymore. Instructions are simulated the same way as by the
+// type checker. Where paths meet, references merge into their first common
+// superclass, as far as the ClassHierarchy knows.
+//
+// Subroutines (jsr and ret) are handled as described in JVMS §4.10.2.4: the
+// locals a subroutine writes to are tracked, and on a ret, the instruction
+// following each jsr to that subroutine gets those locals from the subroutine
+// and the rest from the state at the jsr.
+//
+//===----------------------------------------------------------------------===//
+
+use crate::class_parser::{ClassFile, MethodInfo};
+use crate::errors::ParsingError;
+use crate::instructions::Instruction;
+use crate::typecheck::{
+    array_of, invalid, mismatch, reference_component, ClassHierarchy, Frame, Handler, Method,
+    VerificationType, WithClass, MAX_HIERARCHY_DEPTH, OBJECT,
+};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Verifies the code of `method` by type inference, which doesn't need a StackMapTable. Methods
+/// without code pass. Returns the classes assumed to be assignable, as `type_check_method` does.
+pub fn type_infer_method(
+    class: &ClassFile,
+    method: &MethodInfo,
+    hierarchy: &dyn ClassHierarchy,
+) -> Result<Vec<(String, String)>, ParsingError> {
+    let hierarchy = WithClass {
+        class,
+        rest: hierarchy,
+    };
+
+    match Method::new(class, method, &hierarchy)? {
+        Some(method) => {
+            method.type_infer()?;
+            Ok(method.assumed.into_inner().into_iter().collect())
+        }
+        None => Ok(vec![]),
+    }
+}
+
+// The types before an instruction, and which locals were written to since the start of the
+// subroutine the instruction is in.
+#[derive(Clone)]
+struct State {
+    frame: Frame,
+    modified: Vec<bool>,
+}
+
+impl Method<'_> {
+    fn type_infer(&self) -> Result<(), ParsingError> {
+        let mut instructions = BTreeMap::new();
+
+        for instruction in self.code.instructions() {
+            let (pc, instruction) = instruction?;
+            instructions.insert(pc, instruction);
+        }
+
+        let handlers = self.handlers()?;
+        let initial = self.frame(&self.initial_locals()?, &[], 0)?;
+        let modified = vec![false; initial.locals.len()];
+
+        let mut states = BTreeMap::new();
+        let mut changed = BTreeSet::new();
+        self.merge_into(
+            &mut states,
+            &mut changed,
+            &instructions,
+            0,
+            0,
+            Cow::Owned(State {
+                frame: initial,
+                modified,
+            }),
+        )?;
+
+        // The jsr instructions calling each subroutine, and the ret instructions returning from
+        // it, by the pc the subroutine starts at.
+        let mut callers: BTreeMap<u32, BTreeSet<u32>> = BTreeMap::new();
+        let mut returns: BTreeMap<u32, BTreeSet<u32>> = BTreeMap::new();
+
+        // The handlers covering each instruction, found by going through them in order.
+        let mut covering: BTreeMap<u32, Vec<&Handler>> = BTreeMap::new();
+        let mut pending: Vec<&Handler> = handlers.iter().collect();
+        pending.sort_by_key(|z| std::cmp::Reverse(z.start_pc));
+        let mut active: Vec<&Handler> = vec![];
+
+        for pc in instructions.keys() {
+            while pending.last().is_some_and(|z| z.start_pc <= *pc) {
+                active.extend(pending.pop());
+            }
+
+            active.retain(|z| *pc < z.end_pc);
+
+            if !active.is_empty() {
+                covering.insert(*pc, active.clone());
+            }
+        }
+
+        while let Some(pc) = changed.pop_first() {
+            let instruction = &instructions[&pc];
+            let mut next = states[&pc].clone();
+            self.execute(&mut next.frame, pc, instruction)?;
+
+            let before = &states[&pc];
+
+            if let Some(index) = stored_local(instruction) {
+                let index = usize::from(index);
+                let wide = next.frame.locals[index].is_wide();
+                next.modified[index] = true;
+                next.modified[index + usize::from(wide)] = true;
+
+                // Overwriting the second half of a long or double invalidates it.
+                if index > 0 && before.frame.locals[index - 1].is_wide() {
+                    next.modified[index - 1] = true;
+                }
+            }
+
+            // Calling a constructor initializes the object in every local holding it.
+            if let Instruction::InvokeSpecial(_) = instruction {
+                for (i, (before, after)) in before
+                    .frame
+                    .locals
+                    .iter()
+                    .zip(&next.frame.locals)
+                    .enumerate()
+                {
+                    if before != after {
+                        next.modified[i] = true;
+                    }
+                }
+            }
+
+            // A handler starts with the locals from before the instruction that threw, and only
+            // the exception on the stack.
+            let mut exception: Option<State> = None;
+
+            for handler in covering.get(&pc).into_iter().flatten() {
+                let exception = exception.get_or_insert_with(|| states[&pc].clone());
+                exception.frame.stack = vec![handler.catch_type.clone()];
+
+                self.merge_into(
+                    &mut states,
+                    &mut changed,
+                    &instructions,
+                    pc,
+                    handler.handler_pc,
+                    Cow::Borrowed(exception),
+                )?;
+            }
+
+            let following = instructions
+                .range(pc + 1..)
+                .next()
+                .map(|(following, _)| *following);
+
+            match instruction {
+                Instruction::Jsr(_) | Instruction::JsrW(_) => {
+                    let Some(VerificationType::ReturnAddress(subroutine)) =
+                        next.frame.stack.last().cloned()
+                    else {
+                        unreachable!("jsr pushes a return address");
+                    };
+
+                    if following.is_none() {
+                        return Err(invalid(pc, "a jsr is the last instruction of the code"));
+                    }
+
+                    callers.entry(subroutine).or_default().insert(pc);
+
+                    // The state at this jsr may have changed, so the ret instructions have to
+                    // hand it back again.
+                    changed.extend(returns.get(&subroutine).into_iter().flatten());
+
+                    next.modified.fill(false);
+                    self.merge_into(
+                        &mut states,
+                        &mut changed,
+                        &instructions,
+                        pc,
+                        subroutine,
+                        Cow::Owned(next),
+                    )?;
+                }
+                Instruction::Ret(index) => {
+                    let Some(VerificationType::ReturnAddress(subroutine)) =
+                        next.frame.locals.get(usize::from(*index)).cloned()
+                    else {
+                        unreachable!("ret takes a return address");
+                    };
+
+                    returns.entry(subroutine).or_default().insert(pc);
+
+                    for caller in callers.get(&subroutine).into_iter().flatten() {
+                        let Some((target, _)) = instructions.range(caller + 1..).next() else {
+                            continue;
+                        };
+
+                        let at_caller = &states[caller];
+                        let locals = at_caller
+                            .frame
+                            .locals
+                            .iter()
+                            .zip(&next.frame.locals)
+                            .zip(&next.modified)
+                            .map(|((caller, ret), modified)| {
+                                if *modified { ret } else { caller }.clone()
+                            })
+                            .collect();
+
+                        let returned = State {
+                            frame: Frame {
+                                locals,
+                                stack: next.frame.stack.clone(),
+                                this_uninit: next.frame.this_uninit,
+                            },
+                            modified: at_caller
+                                .modified
+                                .iter()
+                                .zip(&next.modified)
+                                .map(|(caller, ret)| *caller || *ret)
+                                .collect(),
+                        };
+
+                        self.merge_into(
+                            &mut states,
+                            &mut changed,
+                            &instructions,
+                            pc,
+                            *target,
+                            Cow::Owned(returned),
+                        )?;
+                    }
+                }
+                _ => {
+                    for target in instruction.branch_targets(pc) {
+                        let target = u32::try_from(target)
+                            .map_err(|_| invalid(pc, "a branch target is before the code"))?;
+
+                        self.merge_into(
+                            &mut states,
+                            &mut changed,
+                            &instructions,
+                            pc,
+                            target,
+                            Cow::Borrowed(&next),
+                        )?;
+                    }
+
+                    if instruction.falls_through() {
+                        let following = following.ok_or_else(|| {
+                            invalid(pc, "execution falls off the end of the code")
+                        })?;
+
+                        self.merge_into(
+                            &mut states,
+                            &mut changed,
+                            &instructions,
+                            pc,
+                            following,
+                            Cow::Owned(next),
+                        )?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Merges `state` into the state before the instruction at `target`, scheduling the
+    // instruction to be simulated again when that changed anything.
+    fn merge_into(
+        &self,
+        states: &mut BTreeMap<u32, State>,
+        changed: &mut BTreeSet<u32>,
+        instructions: &BTreeMap<u32, Instruction>,
+        pc: u32,
+        target: u32,
+        state: Cow<State>,
+    ) -> Result<(), ParsingError> {
+        if !instructions.contains_key(&target) {
+            return Err(invalid(
+                pc,
+                "control flows to the middle of an instruction, or out of the code",
+            ));
+        }
+
+        let Some(current) = states.get_mut(&target) else {
+            states.insert(target, state.into_owned());
+            changed.insert(target);
+            return Ok(());
+        };
+
+        if current.frame.stack.len() != state.frame.stack.len() {
+            return Err(mismatch(
+                pc,
+                format!("{} stack slot(s)", current.frame.stack.len()),
+                format!("{} stack slot(s)", state.frame.stack.len()),
+            ));
+        }
+
+        let mut updated = false;
+
+        // Unlike locals, values on the stack can't be dropped.
+        for (a, b) in current.frame.stack.iter_mut().zip(&state.frame.stack) {
+            if a != b {
+                let merged = self.merge(a, b).ok_or_else(|| mismatch(pc, &*a, b))?;
+                updated |= *a != merged;
+                *a = merged;
+            }
+        }
+
+        for (a, b) in current.frame.locals.iter_mut().zip(&state.frame.locals) {
+            if a != b {
+                let merged = self.merge(a, b).unwrap_or(VerificationType::Top);
+                updated |= *a != merged;
+                *a = merged;
+            }
+        }
+
+        for (a, b) in current.modified.iter_mut().zip(&state.modified) {
+            updated |= !*a && *b;
+            *a |= *b;
+        }
+
+        if state.frame.this_uninit && !current.frame.this_uninit {
+            current.frame.this_uninit = true;
+            updated = true;
+        }
+
+        if updated {
+            changed.insert(target);
+        }
+
+        Ok(())
+    }
+
+    // The most specific type both `a` and `b` are assignable to, if there's one other than Top.
+    fn merge(&self, a: &VerificationType, b: &VerificationType) -> Option<VerificationType> {
+        use VerificationType as T;
+
+        match (a, b) {
+            (a, b) if a == b => Some(a.clone()),
+            (T::Null, reference @ T::Reference(_)) | (reference @ T::Reference(_), T::Null) => {
+                Some(reference.clone())
+            }
+            (T::Reference(a), T::Reference(b)) => Some(T::Reference(self.common_class(a, b))),
+            _ => None,
+        }
+    }
+
+    fn common_class(&self, a: &str, b: &str) -> String {
+        match (a.strip_prefix('['), b.strip_prefix('[')) {
+            // Arrays of references merge into arrays of the common class of their components, so
+            // arrays of different depths end up as arrays of Object as deep as the shallower one.
+            (Some(a), Some(b)) => {
+                return match (reference_component(a), reference_component(b)) {
+                    (Some(a), Some(b)) => array_of(&self.common_class(a, b)),
+                    _ => OBJECT.to_string(),
+                };
+            }
+            (None, None) => {}
+            _ => return OBJECT.to_string(),
+        }
+
+        let (a, a_known) = self.superclasses(a);
+        let (b, b_known) = self.superclasses(b);
+
+        if let Some(common) = a.iter().find(|z| b.contains(z)) {
+            return common.to_string();
+        }
+
+        // The superclasses past a class that isn't known can't be compared. The class where the
+        // chain stops is used instead: as it isn't known, whatever it's checked against later is
+        // assumed to be assignable, and reported. When neither chain is known, the smaller name is
+        // picked, so that the order paths are merged in doesn't matter.
+        match (a_known, b_known) {
+            (false, false) => a.last().min(b.last()),
+            (false, true) => a.last(),
+            (true, false) => b.last(),
+            (true, true) => None,
+        }
+        .unwrap_or(&OBJECT)
+        .to_string()
+    }
+
+    // `class` and its superclasses, and whether they're all known up to java/lang/Object.
+    fn superclasses<'b>(&'b self, class: &'b str) -> (Vec<&'b str>, bool) {
+        let mut superclasses = vec![class];
+        let mut class = class;
+
+        for _ in 0..MAX_HIERARCHY_DEPTH {
+            match self.hierarchy.superclass(class) {
+                Some(superclass) => {
+                    superclasses.push(superclass);
+                    class = superclass;
+                }
+                None => break,
+            }
+        }
+
+        let known = class == OBJECT || self.hierarchy.is_interface(class).is_some();
+
+        (superclasses, known)
+    }
+}
+
+// The local variable an instruction stores into.
+fn stored_local(instruction: &Instruction) -> Option<u16> {
+    use Instruction as I;
+
+    match instruction {
+        I::Istore(index)
+        | I::Lstore(index)
+        | I::Fstore(index)
+        | I::Dstore(index)
+        | I::Astore(index) => Some(*index),
+        I::Istore0 | I::Lstore0 | I::Fstore0 | I::Dstore0 | I::Astore0 => Some(0),
+        I::Istore1 | I::Lstore1 | I::Fstore1 | I::Dstore1 | I::Astore1 => Some(1),
+        I::Istore2 | I::Lstore2 | I::Fstore2 | I::Dstore2 | I::Astore2 => Some(2),
+        I::Istore3 | I::Lstore3 | I::Fstore3 | I::Dstore3 | I::Astore3 => Some(3),
+        _ => None,
+    }
+}
@@ -0,0 +1,1311 @@
+//------ aftermath_cfparser -- Classfile parser for the Aftermath JVM. -------//
+//
+// Part of the Aftermath JVM, under the MIT license. The LICENSE file is present
+// at the project root, please consult to it for license information.
+//
+//===----------------------------------------------------------------------===//
+//
+// This file contains a decoder for the bytecode stored in Code attributes
+// (JVMS §6.5), and the encoder writing instructions back.
+//
+// Local variable indexes are always widened to a u16, whether or not the
+// instruction was prefixed by `wide`. Branch offsets are kept relative to the
+// pc of the instruction, exactly as they appear in the class file, so code is
+// only written back where it was read from if every instruction keeps its
+// length: `encode_wide` puts back a `wide` the operands don't need.
+//
+//===----------------------------------------------------------------------===//
+
+use crate::class_parser::{AttrCode, ParsingError};
+
+pub(crate) const WIDE: u8 = 0xC4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArrayType {
+    Boolean = 4,
+    Char = 5,
+    Float = 6,
+    Double = 7,
+    Byte = 8,
+    Short = 9,
+    Int = 10,
+    Long = 11,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    Nop,
+    AconstNull,
+    IconstM1,
+    Iconst0,
+    Iconst1,
+    Iconst2,
+    Iconst3,
+    Iconst4,
+    Iconst5,
+    Lconst0,
+    Lconst1,
+    Fconst0,
+    Fconst1,
+    Fconst2,
+    Dconst0,
+    Dconst1,
+    Bipush(i8),
+    Sipush(i16),
+    Ldc(u16),
+    LdcW(u16),
+    Ldc2W(u16),
+    Iload(u16),
+    Lload(u16),
+    Fload(u16),
+    Dload(u16),
+    Aload(u16),
+    Iload0,
+    Iload1,
+    Iload2,
+    Iload3,
+    Lload0,
+    Lload1,
+    Lload2,
+    Lload3,
+    Fload0,
+    Fload1,
+    Fload2,
+    Fload3,
+    Dload0,
+    Dload1,
+    Dload2,
+    Dload3,
+    Aload0,
+    Aload1,
+    Aload2,
+    Aload3,
+    Iaload,
+    Laload,
+    Faload,
+    Daload,
+    Aaload,
+    Baload,
+    Caload,
+    Saload,
+    Istore(u16),
+    Lstore(u16),
+    Fstore(u16),
+    Dstore(u16),
+    Astore(u16),
+    Istore0,
+    Istore1,
+    Istore2,
+    Istore3,
+    Lstore0,
+    Lstore1,
+    Lstore2,
+    Lstore3,
+    Fstore0,
+    Fstore1,
+    Fstore2,
+    Fstore3,
+    Dstore0,
+    Dstore1,
+    Dstore2,
+    Dstore3,
+    Astore0,
+    Astore1,
+    Astore2,
+    Astore3,
+    Iastore,
+    Lastore,
+    Fastore,
+    Dastore,
+    Aastore,
+    Bastore,
+    Castore,
+    Sastore,
+    Pop,
+    Pop2,
+    Dup,
+    DupX1,
+    DupX2,
+    Dup2,
+    Dup2X1,
+    Dup2X2,
+    Swap,
+    Iadd,
+    Ladd,
+    Fadd,
+    Dadd,
+    Isub,
+    Lsub,
+    Fsub,
+    Dsub,
+    Imul,
+    Lmul,
+    Fmul,
+    Dmul,
+    Idiv,
+    Ldiv,
+    Fdiv,
+    Ddiv,
+    Irem,
+    Lrem,
+    Frem,
+    Drem,
+    Ineg,
+    Lneg,
+    Fneg,
+    Dneg,
+    Ishl,
+    Lshl,
+    Ishr,
+    Lshr,
+    Iushr,
+    Lushr,
+    Iand,
+    Land,
+    Ior,
+    Lor,
+    Ixor,
+    Lxor,
+    Iinc {
+        index: u16,
+        value: i16,
+    },
+    I2l,
+    I2f,
+    I2d,
+    L2i,
+    L2f,
+    L2d,
+    F2i,
+    F2l,
+    F2d,
+    D2i,
+    D2l,
+    D2f,
+    I2b,
+    I2c,
+    I2s,
+    Lcmp,
+    Fcmpl,
+    Fcmpg,
+    Dcmpl,
+    Dcmpg,
+    Ifeq(i16),
+    Ifne(i16),
+    Iflt(i16),
+    Ifge(i16),
+    Ifgt(i16),
+    Ifle(i16),
+    IfIcmpeq(i16),
+    IfIcmpne(i16),
+    IfIcmplt(i16),
+    IfIcmpge(i16),
+    IfIcmpgt(i16),
+    IfIcmple(i16),
+    IfAcmpeq(i16),
+    IfAcmpne(i16),
+    Goto(i16),
+    Jsr(i16),
+    Ret(u16),
+    TableSwitch {
+        default: i32,
+        low: i32,
+        high: i32,
+        offsets: Vec<i32>,
+    },
+    LookupSwitch {
+        default: i32,
+        pairs: Vec<(i32, i32)>,
+    },
+    Ireturn,
+    Lreturn,
+    Freturn,
+    Dreturn,
+    Areturn,
+    Return,
+    GetStatic(u16),
+    PutStatic(u16),
+    GetField(u16),
+    PutField(u16),
+    InvokeVirtual(u16),
+    InvokeSpecial(u16),
+    InvokeStatic(u16),
+    InvokeInterface {
+        index: u16,
+        count: u8,
+    },
+    InvokeDynamic(u16),
+    New(u16),
+    NewArray(ArrayType),
+    ANewArray(u16),
+    ArrayLength,
+    AThrow,
+    CheckCast(u16),
+    InstanceOf(u16),
+    MonitorEnter,
+    MonitorExit,
+    MultiANewArray {
+        index: u16,
+        dimensions: u8,
+    },
+    IfNull(i16),
+    IfNonNull(i16),
+    GotoW(i32),
+    JsrW(i32),
+}
+
+pub struct Instructions<'code> {
+    code: &'code [u8],
+    // The pc of the instruction being decoded, and the index of the next byte to read.
+    pc: usize,
+    index: usize,
+    failed: bool,
+}
+
+impl<'class> AttrCode<'class> {
+    pub fn instructions(&self) -> Instructions<'class> {
+        Instructions::new(self.code)
+    }
+}
+
+impl Instruction {
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Instruction::Nop => "nop",
+            Instruction::AconstNull => "aconst_null",
+            Instruction::IconstM1 => "iconst_m1",
+            Instruction::Iconst0 => "iconst_0",
+            Instruction::Iconst1 => "iconst_1",
+            Instruction::Iconst2 => "iconst_2",
+            Instruction::Iconst3 => "iconst_3",
+            Instruction::Iconst4 => "iconst_4",
+            Instruction::Iconst5 => "iconst_5",
+            Instruction::Lconst0 => "lconst_0",
+            Instruction::Lconst1 => "lconst_1",
+            Instruction::Fconst0 => "fconst_0",
+            Instruction::Fconst1 => "fconst_1",
+            Instruction::Fconst2 => "fconst_2",
+            Instruction::Dconst0 => "dconst_0",
+            Instruction::Dconst1 => "dconst_1",
+            Instruction::Bipush(..) => "bipush",
+            Instruction::Sipush(..) => "sipush",
+            Instruction::Ldc(..) => "ldc",
+            Instruction::LdcW(..) => "ldc_w",
+            Instruction::Ldc2W(..) => "ldc2_w",
+            Instruction::Iload(..) => "iload",
+            Instruction::Lload(..) => "lload",
+            Instruction::Fload(..) => "fload",
+            Instruction::Dload(..) => "dload",
+            Instruction::Aload(..) => "aload",
+            Instruction::Iload0 => "iload_0",
+            Instruction::Iload1 => "iload_1",
+            Instruction::Iload2 => "iload_2",
+            Instruction::Iload3 => "iload_3",
+            Instruction::Lload0 => "lload_0",
+            Instruction::Lload1 => "lload_1",
+            Instruction::Lload2 => "lload_2",
+            Instruction::Lload3 => "lload_3",
+            Instruction::Fload0 => "fload_0",
+            Instruction::Fload1 => "fload_1",
+            Instruction::Fload2 => "fload_2",
+            Instruction::Fload3 => "fload_3",
+            Instruction::Dload0 => "dload_0",
+            Instruction::Dload1 => "dload_1",
+            Instruction::Dload2 => "dload_2",
+            Instruction::Dload3 => "dload_3",
+            Instruction::Aload0 => "aload_0",
+            Instruction::Aload1 => "aload_1",
+            Instruction::Aload2 => "aload_2",
+            Instruction::Aload3 => "aload_3",
+            Instruction::Iaload => "iaload",
+            Instruction::Laload => "laload",
+            Instruction::Faload => "faload",
+            Instruction::Daload => "daload",
+            Instruction::Aaload => "aaload",
+            Instruction::Baload => "baload",
+            Instruction::Caload => "caload",
+            Instruction::Saload => "saload",
+            Instruction::Istore(..) => "istore",
+            Instruction::Lstore(..) => "lstore",
+            Instruction::Fstore(..) => "fstore",
+            Instruction::Dstore(..) => "dstore",
+            Instruction::Astore(..) => "astore",
+            Instruction::Istore0 => "istore_0",
+            Instruction::Istore1 => "istore_1",
+            Instruction::Istore2 => "istore_2",
+            Instruction::Istore3 => "istore_3",
+            Instruction::Lstore0 => "lstore_0",
+            Instruction::Lstore1 => "lstore_1",
+            Instruction::Lstore2 => "lstore_2",
+            Instruction::Lstore3 => "lstore_3",
+            Instruction::Fstore0 => "fstore_0",
+            Instruction::Fstore1 => "fstore_1",
+            Instruction::Fstore2 => "fstore_2",
+            Instruction::Fstore3 => "fstore_3",
+            Instruction::Dstore0 => "dstore_0",
+            Instruction::Dstore1 => "dstore_1",
+            Instruction::Dstore2 => "dstore_2",
+            Instruction::Dstore3 => "dstore_3",
+            Instruction::Astore0 => "astore_0",
+            Instruction::Astore1 => "astore_1",
+            Instruction::Astore2 => "astore_2",
+            Instruction::Astore3 => "astore_3",
+            Instruction::Iastore => "iastore",
+            Instruction::Lastore => "lastore",
+            Instruction::Fastore => "fastore",
+            Instruction::Dastore => "dastore",
+            Instruction::Aastore => "aastore",
+            Instruction::Bastore => "bastore",
+            Instruction::Castore => "castore",
+            Instruction::Sastore => "sastore",
+            Instruction::Pop => "pop",
+            Instruction::Pop2 => "pop2",
+            Instruction::Dup => "dup",
+            Instruction::DupX1 => "dup_x1",
+            Instruction::DupX2 => "dup_x2",
+            Instruction::Dup2 => "dup2",
+            Instruction::Dup2X1 => "dup2_x1",
+            Instruction::Dup2X2 => "dup2_x2",
+            Instruction::Swap => "swap",
+            Instruction::Iadd => "iadd",
+            Instruction::Ladd => "ladd",
+            Instruction::Fadd => "fadd",
+            Instruction::Dadd => "dadd",
+            Instruction::Isub => "isub",
+            Instruction::Lsub => "lsub",
+            Instruction::Fsub => "fsub",
+            Instruction::Dsub => "dsub",
+            Instruction::Imul => "imul",
+            Instruction::Lmul => "lmul",
+            Instruction::Fmul => "fmul",
+            Instruction::Dmul => "dmul",
+            Instruction::Idiv => "idiv",
+            Instruction::Ldiv => "ldiv",
+            Instruction::Fdiv => "fdiv",
+            Instruction::Ddiv => "ddiv",
+            Instruction::Irem => "irem",
+            Instruction::Lrem => "lrem",
+            Instruction::Frem => "frem",
+            Instruction::Drem => "drem",
+            Instruction::Ineg => "ineg",
+            Instruction::Lneg => "lneg",
+            Instruction::Fneg => "fneg",
+            Instruction::Dneg => "dneg",
+            Instruction::Ishl => "ishl",
+            Instruction::Lshl => "lshl",
+            Instruction::Ishr => "ishr",
+            Instruction::Lshr => "lshr",
+            Instruction::Iushr => "iushr",
+            Instruction::Lushr => "lushr",
+            Instruction::Iand => "iand",
+            Instruction::Land => "land",
+            Instruction::Ior => "ior",
+            Instruction::Lor => "lor",
+            Instruction::Ixor => "ixor",
+            Instruction::Lxor => "lxor",
+            Instruction::Iinc { .. } => "iinc",
+            Instruction::I2l => "i2l",
+            Instruction::I2f => "i2f",
+            Instruction::I2d => "i2d",
+            Instruction::L2i => "l2i",
+            Instruction::L2f => "l2f",
+            Instruction::L2d => "l2d",
+            Instruction::F2i => "f2i",
+            Instruction::F2l => "f2l",
+            Instruction::F2d => "f2d",
+            Instruction::D2i => "d2i",
+            Instruction::D2l => "d2l",
+            Instruction::D2f => "d2f",
+            Instruction::I2b => "i2b",
+            Instruction::I2c => "i2c",
+            Instruction::I2s => "i2s",
+            Instruction::Lcmp => "lcmp",
+            Instruction::Fcmpl => "fcmpl",
+            Instruction::Fcmpg => "fcmpg",
+            Instruction::Dcmpl => "dcmpl",
+            Instruction::Dcmpg => "dcmpg",
+            Instruction::Ifeq(..) => "ifeq",
+            Instruction::Ifne(..) => "ifne",
+            Instruction::Iflt(..) => "iflt",
+            Instruction::Ifge(..) => "ifge",
+            Instruction::Ifgt(..) => "ifgt",
+            Instruction::Ifle(..) => "ifle",
+            Instruction::IfIcmpeq(..) => "if_icmpeq",
+            Instruction::IfIcmpne(..) => "if_icmpne",
+            Instruction::IfIcmplt(..) => "if_icmplt",
+            Instruction::IfIcmpge(..) => "if_icmpge",
+            Instruction::IfIcmpgt(..) => "if_icmpgt",
+            Instruction::IfIcmple(..) => "if_icmple",
+            Instruction::IfAcmpeq(..) => "if_acmpeq",
+            Instruction::IfAcmpne(..) => "if_acmpne",
+            Instruction::Goto(..) => "goto",
+            Instruction::Jsr(..) => "jsr",
+            Instruction::Ret(..) => "ret",
+            Instruction::TableSwitch { .. } => "tableswitch",
+            Instruction::LookupSwitch { .. } => "lookupswitch",
+            Instruction::Ireturn => "ireturn",
+            Instruction::Lreturn => "lreturn",
+            Instruction::Freturn => "freturn",
+            Instruction::Dreturn => "dreturn",
+            Instruction::Areturn => "areturn",
+            Instruction::Return => "return",
+            Instruction::GetStatic(..) => "getstatic",
+            Instruction::PutStatic(..) => "putstatic",
+            Instruction::GetField(..) => "getfield",
+            Instruction::PutField(..) => "putfield",
+            Instruction::InvokeVirtual(..) => "invokevirtual",
+            Instruction::InvokeSpecial(..) => "invokespecial",
+            Instruction::InvokeStatic(..) => "invokestatic",
+            Instruction::InvokeInterface { .. } => "invokeinterface",
+            Instruction::InvokeDynamic(..) => "invokedynamic",
+            Instruction::New(..) => "new",
+            Instruction::NewArray(..) => "newarray",
+            Instruction::ANewArray(..) => "anewarray",
+            Instruction::ArrayLength => "arraylength",
+            Instruction::AThrow => "athrow",
+            Instruction::CheckCast(..) => "checkcast",
+            Instruction::InstanceOf(..) => "instanceof",
+            Instruction::MonitorEnter => "monitorenter",
+            Instruction::MonitorExit => "monitorexit",
+            Instruction::MultiANewArray { .. } => "multianewarray",
+            Instruction::IfNull(..) => "ifnull",
+            Instruction::IfNonNull(..) => "ifnonnull",
+            Instruction::GotoW(..) => "goto_w",
+            Instruction::JsrW(..) => "jsr_w",
+        }
+    }
+
+    /// The absolute targets of every jump this instruction can take, including switch defaults.
+    /// Targets are not range checked, and may well be negative in malformed code.
+    pub fn branch_targets(&self, pc: u32) -> Vec<i64> {
+        let target = |offset: i32| i64::from(pc) + i64::from(offset);
+
+        match self {
+            Instruction::Ifeq(offset)
+            | Instruction::Ifne(offset)
+            | Instruction::Iflt(offset)
+            | Instruction::Ifge(offset)
+            | Instruction::Ifgt(offset)
+            | Instruction::Ifle(offset)
+            | Instruction::IfIcmpeq(offset)
+            | Instruction::IfIcmpne(offset)
+            | Instruction::IfIcmplt(offset)
+            | Instruction::IfIcmpge(offset)
+            | Instruction::IfIcmpgt(offset)
+            | Instruction::IfIcmple(offset)
+            | Instruction::IfAcmpeq(offset)
+            | Instruction::IfAcmpne(offset)
+            | Instruction::Goto(offset)
+            | Instruction::Jsr(offset)
+            | Instruction::IfNull(offset)
+            | Instruction::IfNonNull(offset) => vec![target((*offset).into())],
+
+            Instruction::GotoW(offset) | Instruction::JsrW(offset) => vec![target(*offset)],
+
+            Instruction::TableSwitch {
+                default, offsets, ..
+            } => std::iter::once(default)
+                .chain(offsets)
+                .map(|offset| target(*offset))
+                .collect(),
+
+            Instruction::LookupSwitch { default, pairs } => std::iter::once(default)
+                .chain(pairs.iter().map(|(_, offset)| offset))
+                .map(|offset| target(*offset))
+                .collect(),
+
+            _ => vec![],
+        }
+    }
+
+    /// Whether execution can continue with the next instruction in the code array.
+    pub fn falls_through(&self) -> bool {
+        !matches!(
+            self,
+            Instruction::Goto(_)
+                | Instruction::GotoW(_)
+                | Instruction::Jsr(_)
+                | Instruction::JsrW(_)
+                | Instruction::Ret(_)
+                | Instruction::TableSwitch { .. }
+                | Instruction::LookupSwitch { .. }
+                | Instruction::Ireturn
+                | Instruction::Lreturn
+                | Instruction::Freturn
+                | Instruction::Dreturn
+                | Instruction::Areturn
+                | Instruction::Return
+                | Instruction::AThrow
+        )
+    }
+
+    /// The opcode of the instruction, as written without `wide`.
+    pub fn opcode(&self) -> u8 {
+        match self {
+            Instruction::Nop => 0x00,
+            Instruction::AconstNull => 0x01,
+            Instruction::IconstM1 => 0x02,
+            Instruction::Iconst0 => 0x03,
+            Instruction::Iconst1 => 0x04,
+            Instruction::Iconst2 => 0x05,
+            Instruction::Iconst3 => 0x06,
+            Instruction::Iconst4 => 0x07,
+            Instruction::Iconst5 => 0x08,
+            Instruction::Lconst0 => 0x09,
+            Instruction::Lconst1 => 0x0A,
+            Instruction::Fconst0 => 0x0B,
+            Instruction::Fconst1 => 0x0C,
+            Instruction::Fconst2 => 0x0D,
+            Instruction::Dconst0 => 0x0E,
+            Instruction::Dconst1 => 0x0F,
+            Instruction::Bipush(..) => 0x10,
+            Instruction::Sipush(..) => 0x11,
+            Instruction::Ldc(..) => 0x12,
+            Instruction::LdcW(..) => 0x13,
+            Instruction::Ldc2W(..) => 0x14,
+            Instruction::Iload(..) => 0x15,
+            Instruction::Lload(..) => 0x16,
+            Instruction::Fload(..) => 0x17,
+            Instruction::Dload(..) => 0x18,
+            Instruction::Aload(..) => 0x19,
+            Instruction::Iload0 => 0x1A,
+            Instruction::Iload1 => 0x1B,
+            Instruction::Iload2 => 0x1C,
+            Instruction::Iload3 => 0x1D,
+            Instruction::Lload0 => 0x1E,
+            Instruction::Lload1 => 0x1F,
+            Instruction::Lload2 => 0x20,
+            Instruction::Lload3 => 0x21,
+            Instruction::Fload0 => 0x22,
+            Instruction::Fload1 => 0x23,
+            Instruction::Fload2 => 0x24,
+            Instruction::Fload3 => 0x25,
+            Instruction::Dload0 => 0x26,
+            Instruction::Dload1 => 0x27,
+            Instruction::Dload2 => 0x28,
+            Instruction::Dload3 => 0x29,
+            Instruction::Aload0 => 0x2A,
+            Instruction::Aload1 => 0x2B,
+            Instruction::Aload2 => 0x2C,
+            Instruction::Aload3 => 0x2D,
+            Instruction::Iaload => 0x2E,
+            Instruction::Laload => 0x2F,
+            Instruction::Faload => 0x30,
+            Instruction::Daload => 0x31,
+            Instruction::Aaload => 0x32,
+            Instruction::Baload => 0x33,
+            Instruction::Caload => 0x34,
+            Instruction::Saload => 0x35,
+            Instruction::Istore(..) => 0x36,
+            Instruction::Lstore(..) => 0x37,
+            Instruction::Fstore(..) => 0x38,
+            Instruction::Dstore(..) => 0x39,
+            Instruction::Astore(..) => 0x3A,
+            Instruction::Istore0 => 0x3B,
+            Instruction::Istore1 => 0x3C,
+            Instruction::Istore2 => 0x3D,
+            Instruction::Istore3 => 0x3E,
+            Instruction::Lstore0 => 0x3F,
+            Instruction::Lstore1 => 0x40,
+            Instruction::Lstore2 => 0x41,
+            Instruction::Lstore3 => 0x42,
+            Instruction::Fstore0 => 0x43,
+            Instruction::Fstore1 => 0x44,
+            Instruction::Fstore2 => 0x45,
+            Instruction::Fstore3 => 0x46,
+            Instruction::Dstore0 => 0x47,
+            Instruction::Dstore1 => 0x48,
+            Instruction::Dstore2 => 0x49,
+            Instruction::Dstore3 => 0x4A,
+            Instruction::Astore0 => 0x4B,
+            Instruction::Astore1 => 0x4C,
+            Instruction::Astore2 => 0x4D,
+            Instruction::Astore3 => 0x4E,
+            Instruction::Iastore => 0x4F,
+            Instruction::Lastore => 0x50,
+            Instruction::Fastore => 0x51,
+            Instruction::Dastore => 0x52,
+            Instruction::Aastore => 0x53,
+            Instruction::Bastore => 0x54,
+            Instruction::Castore => 0x55,
+            Instruction::Sastore => 0x56,
+            Instruction::Pop => 0x57,
+            Instruction::Pop2 => 0x58,
+            Instruction::Dup => 0x59,
+            Instruction::DupX1 => 0x5A,
+            Instruction::DupX2 => 0x5B,
+            Instruction::Dup2 => 0x5C,
+            Instruction::Dup2X1 => 0x5D,
+            Instruction::Dup2X2 => 0x5E,
+            Instruction::Swap => 0x5F,
+            Instruction::Iadd => 0x60,
+            Instruction::Ladd => 0x61,
+            Instruction::Fadd => 0x62,
+            Instruction::Dadd => 0x63,
+            Instruction::Isub => 0x64,
+            Instruction::Lsub => 0x65,
+            Instruction::Fsub => 0x66,
+            Instruction::Dsub => 0x67,
+            Instruction::Imul => 0x68,
+            Instruction::Lmul => 0x69,
+            Instruction::Fmul => 0x6A,
+            Instruction::Dmul => 0x6B,
+            Instruction::Idiv => 0x6C,
+            Instruction::Ldiv => 0x6D,
+            Instruction::Fdiv => 0x6E,
+            Instruction::Ddiv => 0x6F,
+            Instruction::Irem => 0x70,
+            Instruction::Lrem => 0x71,
+            Instruction::Frem => 0x72,
+            Instruction::Drem => 0x73,
+            Instruction::Ineg => 0x74,
+            Instruction::Lneg => 0x75,
+            Instruction::Fneg => 0x76,
+            Instruction::Dneg => 0x77,
+            Instruction::Ishl => 0x78,
+            Instruction::Lshl => 0x79,
+            Instruction::Ishr => 0x7A,
+            Instruction::Lshr => 0x7B,
+            Instruction::Iushr => 0x7C,
+            Instruction::Lushr => 0x7D,
+            Instruction::Iand => 0x7E,
+            Instruction::Land => 0x7F,
+            Instruction::Ior => 0x80,
+            Instruction::Lor => 0x81,
+            Instruction::Ixor => 0x82,
+            Instruction::Lxor => 0x83,
+            Instruction::Iinc { .. } => 0x84,
+            Instruction::I2l => 0x85,
+            Instruction::I2f => 0x86,
+            Instruction::I2d => 0x87,
+            Instruction::L2i => 0x88,
+            Instruction::L2f => 0x89,
+            Instruction::L2d => 0x8A,
+            Instruction::F2i => 0x8B,
+            Instruction::F2l => 0x8C,
+            Instruction::F2d => 0x8D,
+            Instruction::D2i => 0x8E,
+            Instruction::D2l => 0x8F,
+            Instruction::D2f => 0x90,
+            Instruction::I2b => 0x91,
+            Instruction::I2c => 0x92,
+            Instruction::I2s => 0x93,
+            Instruction::Lcmp => 0x94,
+            Instruction::Fcmpl => 0x95,
+            Instruction::Fcmpg => 0x96,
+            Instruction::Dcmpl => 0x97,
+            Instruction::Dcmpg => 0x98,
+            Instruction::Ifeq(..) => 0x99,
+            Instruction::Ifne(..) => 0x9A,
+            Instruction::Iflt(..) => 0x9B,
+            Instruction::Ifge(..) => 0x9C,
+            Instruction::Ifgt(..) => 0x9D,
+            Instruction::Ifle(..) => 0x9E,
+            Instruction::IfIcmpeq(..) => 0x9F,
+            Instruction::IfIcmpne(..) => 0xA0,
+            Instruction::IfIcmplt(..) => 0xA1,
+            Instruction::IfIcmpge(..) => 0xA2,
+            Instruction::IfIcmpgt(..) => 0xA3,
+            Instruction::IfIcmple(..) => 0xA4,
+            Instruction::IfAcmpeq(..) => 0xA5,
+            Instruction::IfAcmpne(..) => 0xA6,
+            Instruction::Goto(..) => 0xA7,
+            Instruction::Jsr(..) => 0xA8,
+            Instruction::Ret(..) => 0xA9,
+            Instruction::TableSwitch { .. } => 0xAA,
+            Instruction::LookupSwitch { .. } => 0xAB,
+            Instruction::Ireturn => 0xAC,
+            Instruction::Lreturn => 0xAD,
+            Instruction::Freturn => 0xAE,
+            Instruction::Dreturn => 0xAF,
+            Instruction::Areturn => 0xB0,
+            Instruction::Return => 0xB1,
+            Instruction::GetStatic(..) => 0xB2,
+            Instruction::PutStatic(..) => 0xB3,
+            Instruction::GetField(..) => 0xB4,
+            Instruction::PutField(..) => 0xB5,
+            Instruction::InvokeVirtual(..) => 0xB6,
+            Instruction::InvokeSpecial(..) => 0xB7,
+            Instruction::InvokeStatic(..) => 0xB8,
+            Instruction::InvokeInterface { .. } => 0xB9,
+            Instruction::InvokeDynamic(..) => 0xBA,
+            Instruction::New(..) => 0xBB,
+            Instruction::NewArray(..) => 0xBC,
+            Instruction::ANewArray(..) => 0xBD,
+            Instruction::ArrayLength => 0xBE,
+            Instruction::AThrow => 0xBF,
+            Instruction::CheckCast(..) => 0xC0,
+            Instruction::InstanceOf(..) => 0xC1,
+            Instruction::MonitorEnter => 0xC2,
+            Instruction::MonitorExit => 0xC3,
+            Instruction::MultiANewArray { .. } => 0xC5,
+            Instruction::IfNull(..) => 0xC6,
+            Instruction::IfNonNull(..) => 0xC7,
+            Instruction::GotoW(..) => 0xC8,
+            Instruction::JsrW(..) => 0xC9,
+        }
+    }
+
+    /// Appends the instruction to `code`, which has to hold the code array up to where the
+    /// instruction starts, as the padding of switches depends on it. Local variable indexes that
+    /// don't fit in a byte, and iinc increments that don't fit in an i8, are written with `wide`,
+    /// and an `Ldc` whose index doesn't fit in a byte as `ldc_w`.
+    pub fn encode(&self, code: &mut Vec<u8>) {
+        use Instruction as I;
+
+        let opcode = self.opcode();
+
+        match self {
+            I::Bipush(value) => code.extend([opcode, *value as u8]),
+            I::Sipush(value) => {
+                code.push(opcode);
+                code.extend(value.to_be_bytes());
+            }
+            I::Ldc(index) => match u8::try_from(*index) {
+                Ok(index) => code.extend([opcode, index]),
+                Err(_) => I::LdcW(*index).encode(code),
+            },
+            I::Iload(index)
+            | I::Lload(index)
+            | I::Fload(index)
+            | I::Dload(index)
+            | I::Aload(index)
+            | I::Istore(index)
+            | I::Lstore(index)
+            | I::Fstore(index)
+            | I::Dstore(index)
+            | I::Astore(index)
+            | I::Ret(index) => match u8::try_from(*index) {
+                Ok(index) => code.extend([opcode, index]),
+                Err(_) => self.encode_wide(code),
+            },
+            I::Iinc { index, value } => match (u8::try_from(*index), i8::try_from(*value)) {
+                (Ok(index), Ok(value)) => code.extend([opcode, index, value as u8]),
+                _ => self.encode_wide(code),
+            },
+            I::LdcW(index)
+            | I::Ldc2W(index)
+            | I::GetStatic(index)
+            | I::PutStatic(index)
+            | I::GetField(index)
+            | I::PutField(index)
+            | I::InvokeVirtual(index)
+            | I::InvokeSpecial(index)
+            | I::InvokeStatic(index)
+            | I::New(index)
+            | I::ANewArray(index)
+            | I::CheckCast(index)
+            | I::InstanceOf(index) => {
+                code.push(opcode);
+                code.extend(index.to_be_bytes());
+            }
+            I::Ifeq(offset)
+            | I::Ifne(offset)
+            | I::Iflt(offset)
+            | I::Ifge(offset)
+            | I::Ifgt(offset)
+            | I::Ifle(offset)
+            | I::IfIcmpeq(offset)
+            | I::IfIcmpne(offset)
+            | I::IfIcmplt(offset)
+            | I::IfIcmpge(offset)
+            | I::IfIcmpgt(offset)
+            | I::IfIcmple(offset)
+            | I::IfAcmpeq(offset)
+            | I::IfAcmpne(offset)
+            | I::Goto(offset)
+            | I::Jsr(offset)
+            | I::IfNull(offset)
+            | I::IfNonNull(offset) => {
+                code.push(opcode);
+                code.extend(offset.to_be_bytes());
+            }
+            I::GotoW(offset) | I::JsrW(offset) => {
+                code.push(opcode);
+                code.extend(offset.to_be_bytes());
+            }
+            I::TableSwitch {
+                default,
+                low,
+                high,
+                offsets,
+            } => {
+                code.push(opcode);
+                code.resize(code.len().next_multiple_of(4), 0);
+
+                for value in [default, low, high].into_iter().chain(offsets) {
+                    code.extend(value.to_be_bytes());
+                }
+            }
+            I::LookupSwitch { default, pairs } => {
+                code.push(opcode);
+                code.resize(code.len().next_multiple_of(4), 0);
+                code.extend(default.to_be_bytes());
+                code.extend((pairs.len() as i32).to_be_bytes());
+
+                for (key, offset) in pairs {
+                    code.extend(key.to_be_bytes());
+                    code.extend(offset.to_be_bytes());
+                }
+            }
+            I::InvokeInterface { index, count } => {
+                code.push(opcode);
+                code.extend(index.to_be_bytes());
+                code.extend([*count, 0]);
+            }
+            I::InvokeDynamic(index) => {
+                code.push(opcode);
+                code.extend(index.to_be_bytes());
+                code.extend([0, 0]);
+            }
+            I::NewArray(atype) => code.extend([opcode, *atype as u8]),
+            I::MultiANewArray { index, dimensions } => {
+                code.push(opcode);
+                code.extend(index.to_be_bytes());
+                code.push(*dimensions);
+            }
+            _ => code.push(opcode),
+        }
+    }
+
+    /// Appends the instruction prefixed by `wide`, even if its operands fit without it, as the
+    /// decoder drops the prefix. Instructions `wide` can't modify are encoded as by `encode`.
+    pub fn encode_wide(&self, code: &mut Vec<u8>) {
+        use Instruction as I;
+
+        match self {
+            I::Iload(index)
+            | I::Lload(index)
+            | I::Fload(index)
+            | I::Dload(index)
+            | I::Aload(index)
+            | I::Istore(index)
+            | I::Lstore(index)
+            | I::Fstore(index)
+            | I::Dstore(index)
+            | I::Astore(index)
+            | I::Ret(index) => {
+                code.extend([WIDE, self.opcode()]);
+                code.extend(index.to_be_bytes());
+            }
+            I::Iinc { index, value } => {
+                code.extend([WIDE, self.opcode()]);
+                code.extend(index.to_be_bytes());
+                code.extend(value.to_be_bytes());
+            }
+            _ => self.encode(code),
+        }
+    }
+}
+
+impl<'code> Instructions<'code> {
+    pub fn new(code: &'code [u8]) -> Self {
+        Self {
+            code,
+            pc: 0,
+            index: 0,
+            failed: false,
+        }
+    }
+
+    fn truncated(&self) -> ParsingError {
+        ParsingError::TruncatedInstruction { pc: self.pc as u32 }
+    }
+
+    fn invalid_operand(&self, reason: &'static str) -> ParsingError {
+        ParsingError::InvalidInstructionOperand {
+            pc: self.pc as u32,
+            reason,
+        }
+    }
+
+    fn u1(&mut self) -> Result<u8, ParsingError> {
+        let output = *self.code.get(self.index).ok_or_else(|| self.truncated())?;
+        self.index += 1;
+        Ok(output)
+    }
+
+    fn u2(&mut self) -> Result<u16, ParsingError> {
+        Ok(u16::from_be_bytes([self.u1()?, self.u1()?]))
+    }
+
+    fn u4(&mut self) -> Result<u32, ParsingError> {
+        Ok(u32::from_be_bytes([
+            self.u1()?,
+            self.u1()?,
+            self.u1()?,
+            self.u1()?,
+        ]))
+    }
+
+    // Switch operands are aligned to a multiple of 4 bytes from the start of the code array.
+    fn padding(&mut self) -> Result<(), ParsingError> {
+        while !self.index.is_multiple_of(4) {
+            self.u1()?;
+        }
+
+        Ok(())
+    }
+
+    // Checks that `count` entries of `size` bytes are present before allocating for them.
+    fn ensure(&self, count: usize, size: usize) -> Result<(), ParsingError> {
+        match count.checked_mul(size) {
+            Some(length) if self.code.len() - self.index >= length => Ok(()),
+            _ => Err(self.truncated()),
+        }
+    }
+
+    fn table_switch(&mut self) -> Result<Instruction, ParsingError> {
+        self.padding()?;
+
+        let default = self.u4()? as i32;
+        let low = self.u4()? as i32;
+        let high = self.u4()? as i32;
+
+        if low > high {
+            return Err(self.invalid_operand("tableswitch low is greater than high"));
+        }
+
+        let count = (i64::from(high) - i64::from(low) + 1) as usize;
+        self.ensure(count, 4)?;
+
+        let mut offsets = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            offsets.push(self.u4()? as i32);
+        }
+
+        Ok(Instruction::TableSwitch {
+            default,
+            low,
+            high,
+            offsets,
+        })
+    }
+
+    fn lookup_switch(&mut self) -> Result<Instruction, ParsingError> {
+        self.padding()?;
+
+        let default = self.u4()? as i32;
+        let npairs = self.u4()? as i32;
+
+        if npairs < 0 {
+            return Err(self.invalid_operand("lookupswitch npairs is negative"));
+        }
+
+        self.ensure(npairs as usize, 8)?;
+
+        let mut pairs = Vec::with_capacity(npairs as usize);
+
+        for _ in 0..npairs {
+            pairs.push((self.u4()? as i32, self.u4()? as i32));
+        }
+
+        Ok(Instruction::LookupSwitch { default, pairs })
+    }
+
+    fn invoke_interface(&mut self) -> Result<Instruction, ParsingError> {
+        let index = self.u2()?;
+        let count = self.u1()?;
+
+        if count == 0 {
+            return Err(self.invalid_operand("invokeinterface count is zero"));
+        }
+
+        if self.u1()? != 0 {
+            return Err(self.invalid_operand("invokeinterface fourth operand byte is not zero"));
+        }
+
+        Ok(Instruction::InvokeInterface { index, count })
+    }
+
+    fn invoke_dynamic(&mut self) -> Result<Instruction, ParsingError> {
+        let index = self.u2()?;
+
+        if self.u2()? != 0 {
+            return Err(self.invalid_operand("invokedynamic operand bytes 3 and 4 are not zero"));
+        }
+
+        Ok(Instruction::InvokeDynamic(index))
+    }
+
+    fn new_array(&mut self) -> Result<Instruction, ParsingError> {
+        Ok(Instruction::NewArray(match self.u1()? {
+            4 => ArrayType::Boolean,
+            5 => ArrayType::Char,
+            6 => ArrayType::Float,
+            7 => ArrayType::Double,
+            8 => ArrayType::Byte,
+            9 => ArrayType::Short,
+            10 => ArrayType::Int,
+            11 => ArrayType::Long,
+            _ => return Err(self.invalid_operand("newarray atype is not in 4..=11")),
+        }))
+    }
+
+    fn multi_a_new_array(&mut self) -> Result<Instruction, ParsingError> {
+        let index = self.u2()?;
+        let dimensions = self.u1()?;
+
+        if dimensions == 0 {
+            return Err(self.invalid_operand("multianewarray dimensions is zero"));
+        }
+
+        Ok(Instruction::MultiANewArray { index, dimensions })
+    }
+
+    fn wide(&mut self) -> Result<Instruction, ParsingError> {
+        Ok(match self.u1()? {
+            0x15 => Instruction::Iload(self.u2()?),
+            0x16 => Instruction::Lload(self.u2()?),
+            0x17 => Instruction::Fload(self.u2()?),
+            0x18 => Instruction::Dload(self.u2()?),
+            0x19 => Instruction::Aload(self.u2()?),
+            0x36 => Instruction::Istore(self.u2()?),
+            0x37 => Instruction::Lstore(self.u2()?),
+            0x38 => Instruction::Fstore(self.u2()?),
+            0x39 => Instruction::Dstore(self.u2()?),
+            0x3A => Instruction::Astore(self.u2()?),
+            0xA9 => Instruction::Ret(self.u2()?),
+            0x84 => Instruction::Iinc {
+                index: self.u2()?,
+                value: self.u2()? as i16,
+            },
+            _ => return Err(self.invalid_operand("wide applied to an instruction it can't modify")),
+        })
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, ParsingError> {
+        let opcode = self.u1()?;
+
+        Ok(match opcode {
+            0x00 => Instruction::Nop,
+            0x01 => Instruction::AconstNull,
+            0x02 => Instruction::IconstM1,
+            0x03 => Instruction::Iconst0,
+            0x04 => Instruction::Iconst1,
+            0x05 => Instruction::Iconst2,
+            0x06 => Instruction::Iconst3,
+            0x07 => Instruction::Iconst4,
+            0x08 => Instruction::Iconst5,
+            0x09 => Instruction::Lconst0,
+            0x0A => Instruction::Lconst1,
+            0x0B => Instruction::Fconst0,
+            0x0C => Instruction::Fconst1,
+            0x0D => Instruction::Fconst2,
+            0x0E => Instruction::Dconst0,
+            0x0F => Instruction::Dconst1,
+            0x10 => Instruction::Bipush(self.u1()? as i8),
+            0x11 => Instruction::Sipush(self.u2()? as i16),
+            0x12 => Instruction::Ldc(self.u1()?.into()),
+            0x13 => Instruction::LdcW(self.u2()?),
+            0x14 => Instruction::Ldc2W(self.u2()?),
+            0x15 => Instruction::Iload(self.u1()?.into()),
+            0x16 => Instruction::Lload(self.u1()?.into()),
+            0x17 => Instruction::Fload(self.u1()?.into()),
+            0x18 => Instruction::Dload(self.u1()?.into()),
+            0x19 => Instruction::Aload(self.u1()?.into()),
+            0x1A => Instruction::Iload0,
+            0x1B => Instruction::Iload1,
+            0x1C => Instruction::Iload2,
+            0x1D => Instruction::Iload3,
+            0x1E => Instruction::Lload0,
+            0x1F => Instruction::Lload1,
+            0x20 => Instruction::Lload2,
+            0x21 => Instruction::Lload3,
+            0x22 => Instruction::Fload0,
+            0x23 => Instruction::Fload1,
+            0x24 => Instruction::Fload2,
+            0x25 => Instruction::Fload3,
+            0x26 => Instruction::Dload0,
+            0x27 => Instruction::Dload1,
+            0x28 => Instruction::Dload2,
+            0x29 => Instruction::Dload3,
+            0x2A => Instruction::Aload0,
+            0x2B => Instruction::Aload1,
+            0x2C => Instruction::Aload2,
+            0x2D => Instruction::Aload3,
+            0x2E => Instruction::Iaload,
+            0x2F => Instruction::Laload,
+            0x30 => Instruction::Faload,
+            0x31 => Instruction::Daload,
+            0x32 => Instruction::Aaload,
+            0x33 => Instruction::Baload,
+            0x34 => Instruction::Caload,
+            0x35 => Instruction::Saload,
+            0x36 => Instruction::Istore(self.u1()?.into()),
+            0x37 => Instruction::Lstore(self.u1()?.into()),
+            0x38 => Instruction::Fstore(self.u1()?.into()),
+            0x39 => Instruction::Dstore(self.u1()?.into()),
+            0x3A => Instruction::Astore(self.u1()?.into()),
+            0x3B => Instruction::Istore0,
+            0x3C => Instruction::Istore1,
+            0x3D => Instruction::Istore2,
+            0x3E => Instruction::Istore3,
+            0x3F => Instruction::Lstore0,
+            0x40 => Instruction::Lstore1,
+            0x41 => Instruction::Lstore2,
+            0x42 => Instruction::Lstore3,
+            0x43 => Instruction::Fstore0,
+            0x44 => Instruction::Fstore1,
+            0x45 => Instruction::Fstore2,
+            0x46 => Instruction::Fstore3,
+            0x47 => Instruction::Dstore0,
+            0x48 => Instruction::Dstore1,
+            0x49 => Instruction::Dstore2,
+            0x4A => Instruction::Dstore3,
+            0x4B => Instruction::Astore0,
+            0x4C => Instruction::Astore1,
+            0x4D => Instruction::Astore2,
+            0x4E => Instruction::Astore3,
+            0x4F => Instruction::Iastore,
+            0x50 => Instruction::Lastore,
+            0x51 => Instruction::Fastore,
+            0x52 => Instruction::Dastore,
+            0x53 => Instruction::Aastore,
+            0x54 => Instruction::Bastore,
+            0x55 => Instruction::Castore,
+            0x56 => Instruction::Sastore,
+            0x57 => Instruction::Pop,
+            0x58 => Instruction::Pop2,
+            0x59 => Instruction::Dup,
+            0x5A => Instruction::DupX1,
+            0x5B => Instruction::DupX2,
+            0x5C => Instruction::Dup2,
+            0x5D => Instruction::Dup2X1,
+            0x5E => Instruction::Dup2X2,
+            0x5F => Instruction::Swap,
+            0x60 => Instruction::Iadd,
+            0x61 => Instruction::Ladd,
+            0x62 => Instruction::Fadd,
+            0x63 => Instruction::Dadd,
+            0x64 => Instruction::Isub,
+            0x65 => Instruction::Lsub,
+            0x66 => Instruction::Fsub,
+            0x67 => Instruction::Dsub,
+            0x68 => Instruction::Imul,
+            0x69 => Instruction::Lmul,
+            0x6A => Instruction::Fmul,
+            0x6B => Instruction::Dmul,
+            0x6C => Instruction::Idiv,
+            0x6D => Instruction::Ldiv,
+            0x6E => Instruction::Fdiv,
+            0x6F => Instruction::Ddiv,
+            0x70 => Instruction::Irem,
+            0x71 => Instruction::Lrem,
+            0x72 => Instruction::Frem,
+            0x73 => Instruction::Drem,
+            0x74 => Instruction::Ineg,
+            0x75 => Instruction::Lneg,
+            0x76 => Instruction::Fneg,
+            0x77 => Instruction::Dneg,
+            0x78 => Instruction::Ishl,
+            0x79 => Instruction::Lshl,
+            0x7A => Instruction::Ishr,
+            0x7B => Instruction::Lshr,
+            0x7C => Instruction::Iushr,
+            0x7D => Instruction::Lushr,
+            0x7E => Instruction::Iand,
+            0x7F => Instruction::Land,
+            0x80 => Instruction::Ior,
+            0x81 => Instruction::Lor,
+            0x82 => Instruction::Ixor,
+            0x83 => Instruction::Lxor,
+            0x84 => Instruction::Iinc {
+                index: self.u1()?.into(),
+                value: (self.u1()? as i8).into(),
+            },
+            0x85 => Instruction::I2l,
+            0x86 => Instruction::I2f,
+            0x87 => Instruction::I2d,
+            0x88 => Instruction::L2i,
+            0x89 => Instruction::L2f,
+            0x8A => Instruction::L2d,
+            0x8B => Instruction::F2i,
+            0x8C => Instruction::F2l,
+            0x8D => Instruction::F2d,
+            0x8E => Instruction::D2i,
+            0x8F => Instruction::D2l,
+            0x90 => Instruction::D2f,
+            0x91 => Instruction::I2b,
+            0x92 => Instruction::I2c,
+            0x93 => Instruction::I2s,
+            0x94 => Instruction::Lcmp,
+            0x95 => Instruction::Fcmpl,
+            0x96 => Instruction::Fcmpg,
+            0x97 => Instruction::Dcmpl,
+            0x98 => Instruction::Dcmpg,
+            0x99 => Instruction::Ifeq(self.u2()? as i16),
+            0x9A => Instruction::Ifne(self.u2()? as i16),
+            0x9B => Instruction::Iflt(self.u2()? as i16),
+            0x9C => Instruction::Ifge(self.u2()? as i16),
+            0x9D => Instruction::Ifgt(self.u2()? as i16),
+            0x9E => Instruction::Ifle(self.u2()? as i16),
+            0x9F => Instruction::IfIcmpeq(self.u2()? as i16),
+            0xA0 => Instruction::IfIcmpne(self.u2()? as i16),
+            0xA1 => Instruction::IfIcmplt(self.u2()? as i16),
+            0xA2 => Instruction::IfIcmpge(self.u2()? as i16),
+            0xA3 => Instruction::IfIcmpgt(self.u2()? as i16),
+            0xA4 => Instruction::IfIcmple(self.u2()? as i16),
+            0xA5 => Instruction::IfAcmpeq(self.u2()? as i16),
+            0xA6 => Instruction::IfAcmpne(self.u2()? as i16),
+            0xA7 => Instruction::Goto(self.u2()? as i16),
+            0xA8 => Instruction::Jsr(self.u2()? as i16),
+            0xA9 => Instruction::Ret(self.u1()?.into()),
+            0xAA => self.table_switch()?,
+            0xAB => self.lookup_switch()?,
+            0xAC => Instruction::Ireturn,
+            0xAD => Instruction::Lreturn,
+            0xAE => Instruction::Freturn,
+            0xAF => Instruction::Dreturn,
+            0xB0 => Instruction::Areturn,
+            0xB1 => Instruction::Return,
+            0xB2 => Instruction::GetStatic(self.u2()?),
+            0xB3 => Instruction::PutStatic(self.u2()?),
+            0xB4 => Instruction::GetField(self.u2()?),
+            0xB5 => Instruction::PutField(self.u2()?),
+            0xB6 => Instruction::InvokeVirtual(self.u2()?),
+            0xB7 => Instruction::InvokeSpecial(self.u2()?),
+            0xB8 => Instruction::InvokeStatic(self.u2()?),
+            0xB9 => self.invoke_interface()?,
+            0xBA => self.invoke_dynamic()?,
+            0xBB => Instruction::New(self.u2()?),
+            0xBC => self.new_array()?,
+            0xBD => Instruction::ANewArray(self.u2()?),
+            0xBE => Instruction::ArrayLength,
+            0xBF => Instruction::AThrow,
+            0xC0 => Instruction::CheckCast(self.u2()?),
+            0xC1 => Instruction::InstanceOf(self.u2()?),
+            0xC2 => Instruction::MonitorEnter,
+            0xC3 => Instruction::MonitorExit,
+            0xC5 => self.multi_a_new_array()?,
+            0xC6 => Instruction::IfNull(self.u2()? as i16),
+            0xC7 => Instruction::IfNonNull(self.u2()? as i16),
+            0xC8 => Instruction::GotoW(self.u4()? as i32),
+            0xC9 => Instruction::JsrW(self.u4()? as i32),
+            WIDE => self.wide()?,
+            _ => {
+                return Err(ParsingError::InvalidOpcode {
+                    pc: self.pc as u32,
+                    opcode,
+                })
+            }
+        })
+    }
+}
+
+impl<'code> Iterator for Instructions<'code> {
+    type Item = Result<(u32, Instruction), ParsingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.index >= self.code.len() {
+            return None;
+        }
+
+        self.pc = self.index;
+        let instruction = self.instruction();
+        self.failed = instruction.is_err();
+
+        Some(instruction.map(|instruction| (self.pc as u32, instruction)))
+    }
+}
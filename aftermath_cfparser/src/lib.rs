@@ -15,9 +15,25 @@ use mimalloc::MiMalloc;
 static GLOBAL: MiMalloc = MiMalloc;
 
 pub mod class_parser;
+pub mod constant_pool;
 pub mod consts;
+pub mod descriptor;
+pub mod diagnostics;
 pub mod errors;
+pub mod hierarchy;
+pub mod inference;
+pub mod instructions;
+pub mod module;
+pub mod mutf8;
+pub mod owned;
+pub mod record;
+pub mod signature;
+pub mod typecheck;
 pub mod verification;
+pub mod visitor;
+pub mod writer;
 
+// The regression test for issue 9 binds a result it never uses, and is kept as it was written.
 #[cfg(test)]
+#[allow(unused_variables)]
 mod tests;
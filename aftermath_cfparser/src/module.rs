@@ -0,0 +1,238 @@
+//------ aftermath_cfparser -- Classfile parser for the Aftermath JVM. -------//
+//
+// Part of the Aftermath JVM, under the MIT license. The LICENSE file is present
+// at the project root, please consult to it for license information.
+//
+//===----------------------------------------------------------------------===//
+//
+// This file contains the ModuleDescriptor, what a module-info class declares,
+// with its constant pool indexes resolved.
+//
+// It's built from the Module, ModulePackages and ModuleMainClass attributes
+// (JVMS §4.7.25, §4.7.26, §4.7.27), which are checked against each other on
+// the way. Module names are dotted (`java.base`), packages and classes are in
+// internal form (`java/lang`).
+//
+//===----------------------------------------------------------------------===//
+
+use crate::class_parser::{Attributes, ClassFile, Module, ParsingError, U2};
+use crate::constant_pool::ConstantPool;
+use crate::consts::{exports_acc_flags, module_acc_flags, requires_acc_flags};
+use crate::hierarchy::package;
+
+const JAVA_BASE: &str = "java.base";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleDescriptor<'a> {
+    pub name: &'a str,
+    pub open: bool,
+    pub synthetic: bool,
+    pub mandated: bool,
+    pub version: Option<&'a str>,
+    pub requires: Vec<Requires<'a>>,
+    pub exports: Vec<PackageAccess<'a>>,
+    pub opens: Vec<PackageAccess<'a>>,
+    // The services the module uses, by the name of their class or interface.
+    pub uses: Vec<&'a str>,
+    pub provides: Vec<Provides<'a>>,
+    // Every package of the module, when the class has a ModulePackages attribute.
+    pub packages: Option<Vec<&'a str>>,
+    pub main_class: Option<&'a str>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Requires<'a> {
+    pub name: &'a str,
+    // The version of the module this one was compiled against.
+    pub version: Option<&'a str>,
+    pub transitive: bool,
+    pub static_phase: bool,
+    pub synthetic: bool,
+    pub mandated: bool,
+}
+
+// A package that's exported or opened, to every module when `targets` is empty, and only to
+// `targets` otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageAccess<'a> {
+    pub package: &'a str,
+    pub targets: Vec<&'a str>,
+    pub synthetic: bool,
+    pub mandated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provides<'a> {
+    pub service: &'a str,
+    pub implementations: Vec<&'a str>,
+}
+
+/// The module `class` declares. `None` when the class has no Module attribute.
+pub fn module_descriptor<'a>(
+    class: &'a ClassFile,
+) -> Result<Option<ModuleDescriptor<'a>>, ParsingError> {
+    let cp = &class.cp;
+    let mut module = None;
+    let mut packages = None;
+    let mut main_class = None;
+
+    for attribute in &class.attributes {
+        match attribute {
+            Attributes::Module(z) => module = Some(z),
+            Attributes::ModulePackages(z) => {
+                packages = Some(names(z.package_index, |z| cp.package_name(z))?)
+            }
+            Attributes::ModuleMainClass(z) => {
+                main_class = Some(cp.class_name(z.main_class_index.to_u2())?)
+            }
+            _ => {}
+        }
+    }
+
+    let Some(module) = module else {
+        return Ok(None);
+    };
+
+    let descriptor = ModuleDescriptor::new(cp, module, packages, main_class)?;
+    descriptor.verify()?;
+
+    Ok(Some(descriptor))
+}
+
+impl<'a> ModuleDescriptor<'a> {
+    fn new(
+        cp: &'a ConstantPool,
+        module: &Module,
+        packages: Option<Vec<&'a str>>,
+        main_class: Option<&'a str>,
+    ) -> Result<Self, ParsingError> {
+        let flags = module.module_flags.to_u2();
+
+        let requires = module
+            .requires
+            .iter()
+            .map(|z| {
+                let flags = z.requires_flags.to_u2();
+
+                Ok(Requires {
+                    name: cp.module_name(z.requires_index.to_u2())?,
+                    version: optional_utf8(cp, z.require_version_index)?,
+                    transitive: flags & requires_acc_flags::TRANSITIVE != 0,
+                    static_phase: flags & requires_acc_flags::STATIC_PHASE != 0,
+                    synthetic: flags & requires_acc_flags::SYNTHETIC != 0,
+                    mandated: flags & requires_acc_flags::MANDATED != 0,
+                })
+            })
+            .collect::<Result<_, ParsingError>>()?;
+
+        let exports = module
+            .exports
+            .iter()
+            .map(|z| package_access(cp, z.exports_index, z.exports_flags, z.exports_to_index))
+            .collect::<Result<_, _>>()?;
+
+        let opens = module
+            .opens
+            .iter()
+            .map(|z| package_access(cp, z.opens_index, z.opens_flags, z.opens_to_index))
+            .collect::<Result<_, _>>()?;
+
+        let provides = module
+            .provides
+            .iter()
+            .map(|z| {
+                Ok(Provides {
+                    service: cp.class_name(z.provides_index.to_u2())?,
+                    implementations: names(z.provides_with_index, |z| cp.class_name(z))?,
+                })
+            })
+            .collect::<Result<_, ParsingError>>()?;
+
+        Ok(Self {
+            name: cp.module_name(module.module_name_index.to_u2())?,
+            open: flags & module_acc_flags::OPEN != 0,
+            synthetic: flags & module_acc_flags::SYNTHETIC != 0,
+            mandated: flags & module_acc_flags::MANDATED != 0,
+            version: optional_utf8(cp, module.module_version_index)?,
+            requires,
+            exports,
+            opens,
+            uses: names(module.uses_index, |z| cp.class_name(z))?,
+            provides,
+            packages,
+            main_class,
+        })
+    }
+
+    // An open module opens everything already, every module depends on java.base (JVMS §4.7.25),
+    // and what the module exports, opens and runs has to be in its own packages.
+    fn verify(&self) -> Result<(), ParsingError> {
+        if self.open && !self.opens.is_empty() {
+            return Err(ParsingError::OpensInOpenModule);
+        }
+
+        let java_base = self.requires.iter().filter(|z| z.name == JAVA_BASE).count();
+
+        if (self.name == JAVA_BASE && !self.requires.is_empty())
+            || (self.name != JAVA_BASE && java_base != 1)
+        {
+            return Err(ParsingError::InvalidJavaBaseRequires);
+        }
+
+        let Some(packages) = &self.packages else {
+            return Ok(());
+        };
+
+        let owned = self
+            .exports
+            .iter()
+            .chain(&self.opens)
+            .map(|z| z.package)
+            .chain(self.main_class.map(package))
+            .chain(
+                self.provides
+                    .iter()
+                    .flat_map(|z| &z.implementations)
+                    .map(|z| package(z)),
+            );
+
+        for owned in owned {
+            if !packages.contains(&owned) {
+                return Err(ParsingError::PackageNotInModule(owned.to_string()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn package_access<'a>(
+    cp: &'a ConstantPool,
+    package_index: U2,
+    flags: U2,
+    targets: &[U2],
+) -> Result<PackageAccess<'a>, ParsingError> {
+    let flags = flags.to_u2();
+
+    Ok(PackageAccess {
+        package: cp.package_name(package_index.to_u2())?,
+        targets: names(targets, |z| cp.module_name(z))?,
+        synthetic: flags & exports_acc_flags::SYNTHETIC != 0,
+        mandated: flags & exports_acc_flags::MANDATED != 0,
+    })
+}
+
+fn names<'a>(
+    indexes: &[U2],
+    name: impl Fn(u16) -> Result<&'a str, ParsingError>,
+) -> Result<Vec<&'a str>, ParsingError> {
+    indexes.iter().map(|z| name(z.to_u2())).collect()
+}
+
+// The utf8 at `index`, where 0 means there's none.
+fn optional_utf8<'a>(cp: &'a ConstantPool, index: U2) -> Result<Option<&'a str>, ParsingError> {
+    match index.to_u2() {
+        0 => Ok(None),
+        index => cp.utf8(index).map(Some),
+    }
+}
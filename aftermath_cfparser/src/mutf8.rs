@@ -0,0 +1,148 @@
+//------ aftermath_cfparser -- Classfile parser for the Aftermath JVM. -------//
+//
+// Part of the Aftermath JVM, under the MIT license. The LICENSE file is present
+// at the project root, please consult to it for license information.
+//
+//===----------------------------------------------------------------------===//
+//
+// This file contains a decoder and an encoder for the "Modified UTF-8"
+// encoding used by CONSTANT_Utf8 entries (JVMS §4.4.7).
+//
+// Modified UTF-8 differs from standard UTF-8 in two ways:
+// - NUL is encoded as the two bytes 0xC0 0x80, never as a single zero byte.
+// - Supplementary characters are encoded as a surrogate pair, with each
+//   surrogate written out as its own three byte sequence.
+//
+// Most strings in a class file don't contain either of those, so they are
+// also valid UTF-8 and can be borrowed straight from the class file.
+//
+//===----------------------------------------------------------------------===//
+
+use std::borrow::Cow;
+
+/// Decodes a Modified UTF-8 string, borrowing it when the bytes are also valid UTF-8.
+///
+/// On failure, the offset (relative to the start of `bytes`) of the offending byte is returned.
+/// Unpaired surrogates are rejected, as they can't be represented by a Rust `str`.
+pub fn decode(bytes: &[u8]) -> Result<Cow<'_, str>, usize> {
+    let mut index = 0;
+    let mut borrowable = true;
+
+    while index < bytes.len() {
+        let (unit, width) = code_unit(bytes, index)?;
+
+        if unit == 0 || (0xD800..=0xDFFF).contains(&unit) {
+            borrowable = false;
+        }
+
+        index += width;
+    }
+
+    if borrowable {
+        return std::str::from_utf8(bytes)
+            .map(Cow::Borrowed)
+            .map_err(|e| e.valid_up_to());
+    }
+
+    let mut output = String::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let (unit, width) = code_unit(bytes, index)?;
+
+        let ch = match unit {
+            0xD800..=0xDBFF => {
+                let (low, low_width) = match bytes.get(index + width) {
+                    Some(_) => code_unit(bytes, index + width)?,
+                    None => return Err(index),
+                };
+
+                if !(0xDC00..=0xDFFF).contains(&low) {
+                    return Err(index);
+                }
+
+                let code_point =
+                    0x10000 + ((u32::from(unit) - 0xD800) << 10) + (u32::from(low) - 0xDC00);
+
+                index += low_width;
+                char::from_u32(code_point).ok_or(index)?
+            }
+
+            0xDC00..=0xDFFF => return Err(index),
+
+            _ => char::from_u32(unit.into()).ok_or(index)?,
+        };
+
+        output.push(ch);
+        index += width;
+    }
+
+    Ok(Cow::Owned(output))
+}
+
+/// Decodes the UTF-16 code unit starting at `index`, returning it alongside its width in bytes.
+fn code_unit(bytes: &[u8], index: usize) -> Result<(u16, usize), usize> {
+    let continuation = |offset: usize| match bytes.get(index + offset) {
+        Some(byte) if byte & 0xC0 == 0x80 => Ok(u16::from(byte & 0x3F)),
+        _ => Err(index + offset),
+    };
+
+    let lead = bytes[index];
+
+    match lead {
+        // A zero byte never appears in Modified UTF-8.
+        0x00 => Err(index),
+
+        0x01..=0x7F => Ok((lead.into(), 1)),
+
+        0xC0..=0xDF => {
+            let unit = (u16::from(lead & 0x1F) << 6) | continuation(1)?;
+
+            // Overlong encodings are illegal, except for NUL which *must* be 0xC0 0x80.
+            if unit != 0 && unit < 0x80 {
+                return Err(index);
+            }
+
+            Ok((unit, 2))
+        }
+
+        0xE0..=0xEF => {
+            let unit = (u16::from(lead & 0x0F) << 12) | (continuation(1)? << 6) | continuation(2)?;
+
+            if unit < 0x800 {
+                return Err(index);
+            }
+
+            Ok((unit, 3))
+        }
+
+        // Stray continuation bytes, and the four byte forms of standard UTF-8.
+        _ => Err(index),
+    }
+}
+
+/// Encodes a string as Modified UTF-8, borrowing it when no re-encoding is needed.
+pub fn encode(string: &str) -> Cow<'_, [u8]> {
+    if !string.chars().any(|ch| ch == '\0' || ch.len_utf16() == 2) {
+        return Cow::Borrowed(string.as_bytes());
+    }
+
+    let mut output = Vec::with_capacity(string.len() + 2);
+
+    for unit in string.encode_utf16() {
+        match unit {
+            0x0001..=0x007F => output.push(unit as u8),
+            0x0000 | 0x0080..=0x07FF => {
+                output.push(0xC0 | (unit >> 6) as u8);
+                output.push(0x80 | (unit & 0x3F) as u8);
+            }
+            _ => {
+                output.push(0xE0 | (unit >> 12) as u8);
+                output.push(0x80 | ((unit >> 6) & 0x3F) as u8);
+                output.push(0x80 | (unit & 0x3F) as u8);
+            }
+        }
+    }
+
+    Cow::Owned(output)
+}
@@ -0,0 +1,90 @@
+//------ aftermath_cfparser -- Classfile parser for the Aftermath JVM. -------//
+//
+// Part of the Aftermath JVM, under the MIT license. The LICENSE file is present
+// at the project root, please consult to it for license information.
+//
+//===----------------------------------------------------------------------===//
+//
+// This file contains OwnedClassFile, a parsed class that owns the bytes it
+// was parsed from.
+//
+// A ClassFile borrows from its input, which makes it awkward to store in a
+// class loader cache or to send to another thread. OwnedClassFile keeps the
+// input alive next to the class that borrows from it, either behind an Arc
+// or as a memory-mapped file, so it has no lifetime and is Send + Sync.
+//
+//===----------------------------------------------------------------------===//
+
+use crate::class_parser::{ClassFile, Parser, ParsingError};
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub struct OwnedClassFile {
+    // Declared before `bytes` so that it's dropped first.
+    class: ClassFile<'static>,
+    bytes: Bytes,
+}
+
+#[derive(Debug)]
+enum Bytes {
+    Shared(Arc<[u8]>),
+    Mapped(Mmap),
+}
+
+impl OwnedClassFile {
+    pub fn parse(bytes: impl Into<Arc<[u8]>>) -> Result<Self, ParsingError> {
+        Self::new(Bytes::Shared(bytes.into()))
+    }
+
+    /// Reads `reader` to the end, then parses what was read.
+    pub fn from_reader(mut reader: impl Read) -> Result<Self, ParsingError> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+
+        Self::parse(bytes)
+    }
+
+    /// Memory maps the file at `path` and parses it in place.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated (by this process or any other) for as long as
+    /// the returned class is alive.
+    pub unsafe fn mmap(path: impl AsRef<Path>) -> Result<Self, ParsingError> {
+        let file = File::open(path)?;
+
+        Self::new(Bytes::Mapped(Mmap::map(&file)?))
+    }
+
+    fn new(bytes: Bytes) -> Result<Self, ParsingError> {
+        let slice = match &bytes {
+            Bytes::Shared(bytes) => Arc::as_ptr(bytes),
+            Bytes::Mapped(bytes) => &**bytes as *const [u8],
+        };
+
+        // SAFETY: Both the Arc and the mapping keep the bytes at a fixed address that doesn't
+        // change when `bytes` is moved, and they're never mutated. `class` is dropped before
+        // `bytes`, and is only ever handed out with its lifetime shortened to that of `&self`.
+        let slice: &'static [u8] = unsafe { &*slice };
+        let class = Parser::new(slice).parse()?;
+
+        Ok(Self { class, bytes })
+    }
+
+    /// The parsed class. The `'static` lifetime it's stored with never leaks out of here.
+    pub fn get(&self) -> &ClassFile<'_> {
+        &self.class
+    }
+
+    /// The bytes the class was parsed from.
+    pub fn bytes(&self) -> &[u8] {
+        match &self.bytes {
+            Bytes::Shared(bytes) => bytes,
+            Bytes::Mapped(bytes) => bytes,
+        }
+    }
+}
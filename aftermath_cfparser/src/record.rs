@@ -0,0 +1,129 @@
+//------ aftermath_cfparser -- Classfile parser for the Aftermath JVM. -------//
+//
+// Part of the Aftermath JVM, under the MIT license. The LICENSE file is present
+// at the project root, please consult to it for license information.
+//
+//===----------------------------------------------------------------------===//
+//
+// This file contains `record_components`, which maps each component of a
+// record class to its field and accessor method.
+//
+// The JVM itself only parses the Record attribute (JVMS §4.7.30). What makes
+// a record is checked here as the Java language has it (JLS §8.10): the class
+// is final and extends java/lang/Record, and each component has a private
+// final field and a public accessor, with no other instance fields.
+//
+//===----------------------------------------------------------------------===//
+
+use crate::class_parser::{
+    Attributes, ClassFile, FieldInfo, MethodInfo, ParsingError, RecordComponentInfo, U2,
+};
+use crate::constant_pool::ConstantPool;
+use crate::consts::{class_acc_flags, field_acc_flags, method_acc_flags};
+use crate::descriptor::FieldType;
+
+const RECORD: &str = "java/lang/Record";
+
+#[derive(Debug)]
+pub struct RecordComponent<'a, 'class> {
+    pub name: &'a str,
+    pub descriptor: FieldType<'a>,
+    pub info: &'a RecordComponentInfo<'class>,
+    // The private final field holding the component.
+    pub field: &'a FieldInfo<'class>,
+    // The public method returning the component, named after it.
+    pub accessor: &'a MethodInfo<'class>,
+}
+
+/// The components of `class` in the order they're declared, each with its field and accessor.
+/// `None` when the class has no Record attribute.
+pub fn record_components<'a, 'class>(
+    class: &'a ClassFile<'class>,
+) -> Result<Option<Vec<RecordComponent<'a, 'class>>>, ParsingError> {
+    let Some(record) = class.attributes.iter().find_map(|z| match z {
+        Attributes::Record(z) => Some(z),
+        _ => None,
+    }) else {
+        return Ok(None);
+    };
+
+    let cp = &class.cp;
+
+    if class.access_flags.to_u2() & class_acc_flags::FINAL == 0 {
+        return Err(ParsingError::RecordNotFinal);
+    }
+
+    if class.super_class.to_u2() == 0 || cp.class_name(class.super_class.to_u2())? != RECORD {
+        return Err(ParsingError::RecordSuperclassNotRecord);
+    }
+
+    let mut components = vec![];
+
+    for info in &record.components {
+        let name = cp.utf8(info.name_index.to_u2())?;
+        let descriptor = cp.utf8(info.descriptor_index.to_u2())?;
+
+        let field = class
+            .fields
+            .iter()
+            .find(|z| is_member(cp, z.name_index, z.descriptor_index, name, descriptor))
+            .filter(|z| {
+                let flags = z.access_flags.to_u2();
+                flags & field_acc_flags::STATIC == 0
+                    && flags & (field_acc_flags::PRIVATE | field_acc_flags::FINAL)
+                        == field_acc_flags::PRIVATE | field_acc_flags::FINAL
+            })
+            .ok_or_else(|| ParsingError::RecordComponentWithoutField(name.to_string()))?;
+
+        let accessor_descriptor = format!("(){}", descriptor);
+        let accessor = class
+            .methods
+            .iter()
+            .find(|z| {
+                is_member(
+                    cp,
+                    z.name_index,
+                    z.descriptor_index,
+                    name,
+                    &accessor_descriptor,
+                )
+            })
+            .filter(|z| {
+                let flags = z.access_flags.to_u2();
+                flags & method_acc_flags::PUBLIC != 0 && flags & method_acc_flags::STATIC == 0
+            })
+            .ok_or_else(|| ParsingError::RecordComponentWithoutAccessor(name.to_string()))?;
+
+        components.push(RecordComponent {
+            name,
+            descriptor: FieldType::parse(descriptor)?,
+            info,
+            field,
+            accessor,
+        });
+    }
+
+    for field in &class.fields {
+        if field.access_flags.to_u2() & field_acc_flags::STATIC == 0
+            && !components.iter().any(|z| std::ptr::eq(z.field, field))
+        {
+            let name = cp.utf8(field.name_index.to_u2())?;
+            return Err(ParsingError::RecordFieldWithoutComponent(name.to_string()));
+        }
+    }
+
+    Ok(Some(components))
+}
+
+// Whether a field or method is named `name` and has `descriptor`. Broken constant pool indexes
+// are left to the Verifier.
+fn is_member(
+    cp: &ConstantPool,
+    name_index: U2,
+    descriptor_index: U2,
+    name: &str,
+    descriptor: &str,
+) -> bool {
+    cp.utf8(name_index.to_u2()).ok() == Some(name)
+        && cp.utf8(descriptor_index.to_u2()).ok() == Some(descriptor)
+}
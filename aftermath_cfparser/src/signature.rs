@@ -0,0 +1,574 @@
+//------ aftermath_cfparser -- Classfile parser for the Aftermath JVM. -------//
+//
+// Part of the Aftermath JVM, under the MIT license. The LICENSE file is present
+// at the project root, please consult to it for license information.
+//
+//===----------------------------------------------------------------------===//
+//
+// This file contains a parser for the generic signatures stored in Signature
+// attributes (JVMS §4.7.9.1), and renders them back as Java-like source.
+//
+//===----------------------------------------------------------------------===//
+
+use crate::class_parser::ParsingError;
+use crate::errors::SignatureError;
+use std::fmt::{self, Display};
+
+// Arrays can't have more than 255 dimensions (JVMS §4.3.2).
+const MAX_ARRAY_DIMENSIONS: usize = 255;
+// Type arguments nesting deeper than this are rejected, before the parser runs out of stack.
+const MAX_TYPE_ARGUMENT_DEPTH: usize = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BaseType {
+    Byte,
+    Char,
+    Double,
+    Float,
+    Int,
+    Long,
+    Short,
+    Boolean,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeSignature<'a> {
+    Base(BaseType),
+    Class(ClassTypeSignature<'a>),
+    TypeVariable(&'a str),
+    Array(Box<TypeSignature<'a>>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassTypeSignature<'a> {
+    // The package in its internal form (`java/util`), if any.
+    pub package: Option<&'a str>,
+    pub class: SimpleClassTypeSignature<'a>,
+    // Inner classes, as in `Outer<T>.Inner<U>`.
+    pub suffixes: Vec<SimpleClassTypeSignature<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimpleClassTypeSignature<'a> {
+    pub name: &'a str,
+    pub type_arguments: Vec<TypeArgument<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeArgument<'a> {
+    // `?`
+    Any,
+    Exact(TypeSignature<'a>),
+    // `? extends T`
+    Extends(TypeSignature<'a>),
+    // `? super T`
+    Super(TypeSignature<'a>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeParameter<'a> {
+    pub name: &'a str,
+    // Empty class bounds are legal, as in `<T::Ljava/lang/Comparable<TT;>;>`.
+    pub class_bound: Option<TypeSignature<'a>>,
+    pub interface_bounds: Vec<TypeSignature<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassSignature<'a> {
+    pub type_parameters: Vec<TypeParameter<'a>>,
+    pub superclass: ClassTypeSignature<'a>,
+    pub interfaces: Vec<ClassTypeSignature<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodSignature<'a> {
+    pub type_parameters: Vec<TypeParameter<'a>>,
+    pub parameters: Vec<TypeSignature<'a>>,
+    // `None` for `void` methods.
+    pub result: Option<TypeSignature<'a>>,
+    pub throws: Vec<TypeSignature<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldSignature<'a> {
+    pub field_type: TypeSignature<'a>,
+}
+
+struct SignatureParser<'a> {
+    signature: &'a str,
+    index: usize,
+    // How many type argument lists the parser is in.
+    depth: usize,
+}
+
+impl<'a> ClassSignature<'a> {
+    pub fn parse(signature: &'a str) -> Result<Self, ParsingError> {
+        let mut parser = SignatureParser::new(signature);
+
+        let type_parameters = parser.type_parameters()?;
+        let superclass = parser.class_type_signature()?;
+        let mut interfaces = vec![];
+
+        while parser.index < signature.len() {
+            interfaces.push(parser.class_type_signature()?);
+        }
+
+        Ok(Self {
+            type_parameters,
+            superclass,
+            interfaces,
+        })
+    }
+}
+
+impl<'a> MethodSignature<'a> {
+    pub fn parse(signature: &'a str) -> Result<Self, ParsingError> {
+        let mut parser = SignatureParser::new(signature);
+
+        let type_parameters = parser.type_parameters()?;
+
+        if parser.peek()? != b'(' {
+            return Err(parser.error(SignatureError::MissingParameterList));
+        }
+
+        parser.index += 1;
+        let mut parameters = vec![];
+
+        while parser.peek()? != b')' {
+            parameters.push(parser.java_type_signature()?);
+        }
+
+        parser.index += 1;
+
+        let result = if parser.peek()? == b'V' {
+            parser.index += 1;
+            None
+        } else {
+            Some(parser.java_type_signature()?)
+        };
+
+        let mut throws = vec![];
+
+        while parser.index < signature.len() {
+            if parser.peek()? != b'^' {
+                return Err(parser.error(SignatureError::TrailingCharacters));
+            }
+
+            parser.index += 1;
+
+            throws.push(match parser.peek()? {
+                b'L' => TypeSignature::Class(parser.class_type_signature()?),
+                b'T' => parser.type_variable()?,
+                _ => return Err(parser.error(SignatureError::InvalidType)),
+            });
+        }
+
+        Ok(Self {
+            type_parameters,
+            parameters,
+            result,
+            throws,
+        })
+    }
+}
+
+impl<'a> FieldSignature<'a> {
+    pub fn parse(signature: &'a str) -> Result<Self, ParsingError> {
+        let mut parser = SignatureParser::new(signature);
+        let field_type = parser.reference_type_signature()?;
+
+        parser.end()?;
+
+        Ok(Self { field_type })
+    }
+}
+
+impl<'a> SignatureParser<'a> {
+    fn new(signature: &'a str) -> Self {
+        Self {
+            signature,
+            index: 0,
+            depth: 0,
+        }
+    }
+
+    fn error(&self, kind: SignatureError) -> ParsingError {
+        ParsingError::InvalidSignature {
+            signature: self.signature.to_string(),
+            offset: self.index,
+            kind,
+        }
+    }
+
+    fn peek(&self) -> Result<u8, ParsingError> {
+        self.signature
+            .as_bytes()
+            .get(self.index)
+            .copied()
+            .ok_or_else(|| self.error(SignatureError::UnexpectedEnd))
+    }
+
+    fn expect(&mut self, byte: u8, kind: SignatureError) -> Result<(), ParsingError> {
+        if self.peek()? != byte {
+            return Err(self.error(kind));
+        }
+
+        self.index += 1;
+        Ok(())
+    }
+
+    fn end(&self) -> Result<(), ParsingError> {
+        if self.index != self.signature.len() {
+            return Err(self.error(SignatureError::TrailingCharacters));
+        }
+
+        Ok(())
+    }
+
+    fn identifier(&mut self) -> Result<&'a str, ParsingError> {
+        let start = self.index;
+        let length = self.signature[start..]
+            .find(['.', ';', '[', '/', '<', '>', ':'])
+            .unwrap_or(self.signature.len() - start);
+
+        if length == 0 {
+            return Err(self.error(SignatureError::InvalidIdentifier));
+        }
+
+        self.index += length;
+        Ok(&self.signature[start..self.index])
+    }
+
+    fn type_parameters(&mut self) -> Result<Vec<TypeParameter<'a>>, ParsingError> {
+        let mut type_parameters = vec![];
+
+        if self.peek()? != b'<' {
+            return Ok(type_parameters);
+        }
+
+        self.index += 1;
+
+        loop {
+            let name = self.identifier()?;
+            self.expect(b':', SignatureError::MissingBound)?;
+
+            // The class bound may be left out, as in `<T:>` or `<T::Ljava/lang/Runnable;>`.
+            let class_bound = match self.peek()? {
+                b'L' | b'T' | b'[' => Some(self.reference_type_signature()?),
+                _ => None,
+            };
+
+            let mut interface_bounds = vec![];
+
+            while self.peek()? == b':' {
+                self.index += 1;
+                interface_bounds.push(self.reference_type_signature()?);
+            }
+
+            type_parameters.push(TypeParameter {
+                name,
+                class_bound,
+                interface_bounds,
+            });
+
+            if self.peek()? == b'>' {
+                self.index += 1;
+                return Ok(type_parameters);
+            }
+        }
+    }
+
+    fn java_type_signature(&mut self) -> Result<TypeSignature<'a>, ParsingError> {
+        let base = match self.peek()? {
+            b'B' => BaseType::Byte,
+            b'C' => BaseType::Char,
+            b'D' => BaseType::Double,
+            b'F' => BaseType::Float,
+            b'I' => BaseType::Int,
+            b'J' => BaseType::Long,
+            b'S' => BaseType::Short,
+            b'Z' => BaseType::Boolean,
+            _ => return self.reference_type_signature(),
+        };
+
+        self.index += 1;
+        Ok(TypeSignature::Base(base))
+    }
+
+    fn reference_type_signature(&mut self) -> Result<TypeSignature<'a>, ParsingError> {
+        match self.peek()? {
+            b'L' => Ok(TypeSignature::Class(self.class_type_signature()?)),
+            b'T' => self.type_variable(),
+            b'[' => {
+                let start = self.index;
+
+                while self.peek()? == b'[' {
+                    self.index += 1;
+                }
+
+                let dimensions = self.index - start;
+
+                if dimensions > MAX_ARRAY_DIMENSIONS {
+                    self.index = start;
+                    return Err(self.error(SignatureError::TooManyDimensions));
+                }
+
+                let mut signature = self.java_type_signature()?;
+
+                for _ in 0..dimensions {
+                    signature = TypeSignature::Array(Box::new(signature));
+                }
+
+                Ok(signature)
+            }
+            _ => Err(self.error(SignatureError::InvalidType)),
+        }
+    }
+
+    fn type_variable(&mut self) -> Result<TypeSignature<'a>, ParsingError> {
+        self.expect(b'T', SignatureError::InvalidType)?;
+        let name = self.identifier()?;
+        self.expect(b';', SignatureError::InvalidIdentifier)?;
+
+        Ok(TypeSignature::TypeVariable(name))
+    }
+
+    fn class_type_signature(&mut self) -> Result<ClassTypeSignature<'a>, ParsingError> {
+        self.expect(b'L', SignatureError::InvalidType)?;
+
+        let start = self.index;
+        let mut name = self.identifier()?;
+        let mut package = None;
+
+        while self.peek()? == b'/' {
+            package = Some(&self.signature[start..self.index]);
+            self.index += 1;
+            name = self.identifier()?;
+        }
+
+        let class = SimpleClassTypeSignature {
+            name,
+            type_arguments: self.type_arguments()?,
+        };
+
+        let mut suffixes = vec![];
+
+        while self.peek()? == b'.' {
+            self.index += 1;
+
+            suffixes.push(SimpleClassTypeSignature {
+                name: self.identifier()?,
+                type_arguments: self.type_arguments()?,
+            });
+        }
+
+        self.expect(b';', SignatureError::InvalidIdentifier)?;
+
+        Ok(ClassTypeSignature {
+            package,
+            class,
+            suffixes,
+        })
+    }
+
+    fn type_arguments(&mut self) -> Result<Vec<TypeArgument<'a>>, ParsingError> {
+        let mut type_arguments = vec![];
+
+        if self.peek()? != b'<' {
+            return Ok(type_arguments);
+        }
+
+        if self.depth == MAX_TYPE_ARGUMENT_DEPTH {
+            return Err(self.error(SignatureError::TooDeeplyNested));
+        }
+
+        self.index += 1;
+        self.depth += 1;
+
+        loop {
+            type_arguments.push(match self.peek()? {
+                b'*' => {
+                    self.index += 1;
+                    TypeArgument::Any
+                }
+                b'+' => {
+                    self.index += 1;
+                    TypeArgument::Extends(self.reference_type_signature()?)
+                }
+                b'-' => {
+                    self.index += 1;
+                    TypeArgument::Super(self.reference_type_signature()?)
+                }
+                _ => TypeArgument::Exact(self.reference_type_signature()?),
+            });
+
+            if self.peek()? == b'>' {
+                self.index += 1;
+                self.depth -= 1;
+                return Ok(type_arguments);
+            }
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+fn comma_separated<T: Display>(f: &mut fmt::Formatter<'_>, items: &[T]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i != 0 {
+            write!(f, ", ")?;
+        }
+
+        write!(f, "{}", item)?;
+    }
+
+    Ok(())
+}
+
+fn type_parameters(f: &mut fmt::Formatter<'_>, parameters: &[TypeParameter]) -> fmt::Result {
+    if !parameters.is_empty() {
+        write!(f, "<")?;
+        comma_separated(f, parameters)?;
+        write!(f, "> ")?;
+    }
+
+    Ok(())
+}
+
+impl Display for BaseType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BaseType::Byte => "byte",
+            BaseType::Char => "char",
+            BaseType::Double => "double",
+            BaseType::Float => "float",
+            BaseType::Int => "int",
+            BaseType::Long => "long",
+            BaseType::Short => "short",
+            BaseType::Boolean => "boolean",
+        })
+    }
+}
+
+impl Display for TypeSignature<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeSignature::Base(base) => write!(f, "{}", base),
+            TypeSignature::Class(class) => write!(f, "{}", class),
+            TypeSignature::TypeVariable(name) => write!(f, "{}", name),
+            TypeSignature::Array(component) => write!(f, "{}[]", component),
+        }
+    }
+}
+
+impl Display for ClassTypeSignature<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(package) = self.package {
+            for segment in package.split('/') {
+                write!(f, "{}.", segment)?;
+            }
+        }
+
+        write!(f, "{}", self.class)?;
+
+        for suffix in &self.suffixes {
+            write!(f, ".{}", suffix)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for SimpleClassTypeSignature<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+
+        if !self.type_arguments.is_empty() {
+            write!(f, "<")?;
+            comma_separated(f, &self.type_arguments)?;
+            write!(f, ">")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for TypeArgument<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeArgument::Any => write!(f, "?"),
+            TypeArgument::Exact(signature) => write!(f, "{}", signature),
+            TypeArgument::Extends(signature) => write!(f, "? extends {}", signature),
+            TypeArgument::Super(signature) => write!(f, "? super {}", signature),
+        }
+    }
+}
+
+impl Display for TypeParameter<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+
+        // `T extends Object` is how javac encodes an unbounded `T`, so it isn't shown.
+        let class_bound = self.class_bound.as_ref().filter(|bound| {
+            !matches!(bound, TypeSignature::Class(ClassTypeSignature {
+                package: Some("java/lang"),
+                class: SimpleClassTypeSignature { name: "Object", type_arguments },
+                suffixes,
+            }) if type_arguments.is_empty() && suffixes.is_empty())
+        });
+
+        let mut bounds = class_bound.into_iter().chain(&self.interface_bounds);
+
+        if let Some(bound) = bounds.next() {
+            write!(f, " extends {}", bound)?;
+        }
+
+        for bound in bounds {
+            write!(f, " & {}", bound)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for ClassSignature<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        type_parameters(f, &self.type_parameters)?;
+        write!(f, "extends {}", self.superclass)?;
+
+        if !self.interfaces.is_empty() {
+            write!(f, " implements ")?;
+            comma_separated(f, &self.interfaces)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for MethodSignature<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        type_parameters(f, &self.type_parameters)?;
+
+        match &self.result {
+            Some(result) => write!(f, "{}", result)?,
+            None => write!(f, "void")?,
+        }
+
+        write!(f, " (")?;
+        comma_separated(f, &self.parameters)?;
+        write!(f, ")")?;
+
+        if !self.throws.is_empty() {
+            write!(f, " throws ")?;
+            comma_separated(f, &self.throws)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for FieldSignature<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.field_type)
+    }
+}
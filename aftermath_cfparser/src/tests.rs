@@ -9,7 +9,29 @@
 //
 //===----------------------------------------------------------------------===//
 
-use crate::class_parser::{self, ParsingError};
+use crate::class_parser::{
+    self, AttrCode, Attributes, ClassFile, CpNode, ExceptionTableAttrCode, FieldInfo, ParsingError,
+};
+use crate::constant_pool::{Constant, ConstantPool, MemberKind};
+use crate::consts::{class_acc_flags, field_acc_flags, method_acc_flags, module_acc_flags};
+use crate::descriptor::{FieldType, MethodDescriptor};
+use crate::diagnostics::Severity;
+use crate::errors::{Attributes as ErrorAttributes, CpNodeError, DescriptorError, SignatureError};
+use crate::hierarchy::HierarchyValidator;
+use crate::inference::type_infer_method;
+use crate::instructions::{Instruction, Instructions};
+use crate::module::{module_descriptor, PackageAccess, Provides, Requires};
+use crate::mutf8;
+use crate::owned::OwnedClassFile;
+use crate::record::record_components;
+use crate::signature::{
+    ClassSignature, FieldSignature, MethodSignature, TypeArgument, TypeSignature,
+};
+use crate::typecheck::{type_check_method, ClassHierarchy};
+use crate::verification::{parse_and_verify, verify_version, Verifier, VerifyLevel};
+use crate::visitor::{AnnotationVisitor, ClassVisitor, MethodVisitor};
+use crate::writer::ClassWriter;
+use std::borrow::Cow;
 
 #[test]
 pub fn invalid_magic() {
@@ -22,7 +44,7 @@ pub fn invalid_magic() {
     match parse {
         Ok(_) => panic!("Test shouldn't have passed, Expected an Magic error."),
         Err(e) => {
-            if !matches!(e, ParsingError::Magic) {
+            if !matches!(e.kind(), ParsingError::Magic) {
                 panic!(
                     "Test shouldn't have failed with this error.
                 Expected error -> Magic
@@ -49,3 +71,2174 @@ pub fn issue_10_integer_underflow_while_subtracting() {
 
     let _ = parser.parse();
 }
+
+/// Builds a minimal `Foo` class file with a single class-level attribute.
+fn class_with_attribute(name: &str, length: u32, info: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 61, 0, 4];
+
+    bytes.extend([1, 0, 3]);
+    bytes.extend(b"Foo");
+    bytes.extend([7, 0, 1]);
+    bytes.push(1);
+    bytes.extend((name.len() as u16).to_be_bytes());
+    bytes.extend(name.as_bytes());
+
+    // access_flags, this_class, super_class, interfaces, fields, methods.
+    bytes.extend([0, 0x21, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0]);
+    bytes.extend([0, 1, 0, 3]);
+    bytes.extend(length.to_be_bytes());
+    bytes.extend(info);
+
+    bytes
+}
+
+/// Builds a class of version 61 whose constant pool holds the classes `A` (#2), `B` (#4), `C`
+/// (#6), `I` (#8) and `java/lang/Object` (#10), then the Utf8s `m` (#11), `()V` (#12),
+/// `PermittedSubclasses` (#13), `NestHost` (#14) and `NestMembers` (#15). `method` is the access
+/// flags of a method `m()V`, and each attribute is its name and the u2s it holds.
+fn class_in_hierarchy(
+    access_flags: u16,
+    this_class: u16,
+    super_class: u16,
+    interfaces: &[u16],
+    method: Option<u16>,
+    attributes: &[(u16, &[u16])],
+) -> Vec<u8> {
+    let u2s = |bytes: &mut Vec<u8>, values: &[u16]| {
+        for value in values {
+            bytes.extend(value.to_be_bytes());
+        }
+    };
+    let mut bytes = vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 61, 0, 16];
+
+    for (i, name) in ["A", "B", "C", "I", "java/lang/Object"].iter().enumerate() {
+        bytes.push(1);
+        u2s(&mut bytes, &[name.len() as u16]);
+        bytes.extend(name.as_bytes());
+        bytes.push(7);
+        u2s(&mut bytes, &[i as u16 * 2 + 1]);
+    }
+
+    for name in ["m", "()V", "PermittedSubclasses", "NestHost", "NestMembers"] {
+        bytes.push(1);
+        u2s(&mut bytes, &[name.len() as u16]);
+        bytes.extend(name.as_bytes());
+    }
+
+    u2s(&mut bytes, &[access_flags, this_class, super_class]);
+    u2s(&mut bytes, &[interfaces.len() as u16]);
+    u2s(&mut bytes, interfaces);
+    u2s(&mut bytes, &[0]);
+
+    match method {
+        Some(flags) => u2s(&mut bytes, &[1, flags, 11, 12, 0]),
+        None => u2s(&mut bytes, &[0]),
+    }
+
+    u2s(&mut bytes, &[attributes.len() as u16]);
+
+    for (name, info) in attributes {
+        u2s(&mut bytes, &[*name, 0]);
+        u2s(&mut bytes, &[info.len() as u16 * 2]);
+        u2s(&mut bytes, info);
+    }
+
+    bytes
+}
+
+/// Parses hello_world and hands the Code attribute of one of its methods, `<init>` (0) or
+/// `static main(String[])` (1), to `change`. Both have a max_stack of 2. In its constant pool, #7
+/// is the Fieldref `System.out`, #13 the String "Hello, World!" and #15 the Methodref `println`.
+fn hello_world_with_code<'a>(
+    method: usize,
+    change: impl FnOnce(&mut AttrCode<'a>),
+) -> ClassFile<'a> {
+    let bytes = include_bytes!("../../class_basket/hello_world.class");
+    let mut class: ClassFile<'a> = class_parser::Parser::new(bytes).parse().unwrap();
+
+    let Some(Attributes::Code(code)) = class.methods[method].attributes.first_mut() else {
+        panic!("Expected the method to start with its code");
+    };
+    change(code);
+
+    class
+}
+
+#[test]
+pub fn unknown_attributes_are_preserved() {
+    let bytes = class_with_attribute("SourceID", 4, &[1, 2, 3, 4]);
+    let class = class_parser::Parser::new(&bytes).parse().unwrap();
+
+    match class.attributes.as_slice() {
+        [Attributes::Unknown { name, info }] => {
+            assert_eq!(*name, "SourceID");
+            assert_eq!(*info, &[1, 2, 3, 4]);
+        }
+        attributes => panic!(
+            "Expected a single unknown attribute, found {:?}",
+            attributes
+        ),
+    }
+}
+
+#[test]
+pub fn attribute_length_mismatch() {
+    let bytes = class_with_attribute("SourceFile", 4, &[0, 1, 0, 0]);
+    let parse = class_parser::Parser::new(&bytes).parse();
+
+    match parse.as_ref().map_err(ParsingError::kind) {
+        Err(ParsingError::AttributeLengthMismatch {
+            name,
+            declared,
+            consumed,
+        }) => {
+            assert_eq!(name, "SourceFile");
+            assert_eq!(*declared, 4);
+            assert_eq!(*consumed, 2);
+        }
+        parse => panic!(
+            "Expected an AttributeLengthMismatch error, found {:?}",
+            parse
+        ),
+    }
+}
+
+#[test]
+pub fn attribute_body_is_bounded() {
+    // `SourceFile` needs 2 bytes, but only 1 was declared.
+    let bytes = class_with_attribute("SourceFile", 1, &[0, 1]);
+    let parse = class_parser::Parser::new(&bytes).parse();
+
+    assert!(matches!(
+        parse.as_ref().map_err(ParsingError::kind),
+        Err(ParsingError::OutOfBounds)
+    ));
+}
+
+#[test]
+pub fn class_basket_parses() {
+    for bytes in [
+        &include_bytes!("../../class_basket/complex_math.class")[..],
+        include_bytes!("../../class_basket/empty.class"),
+        include_bytes!("../../class_basket/hello_world.class"),
+        include_bytes!("../../class_basket/large.class"),
+        include_bytes!("../../class_basket/module-info.class"),
+    ] {
+        class_parser::Parser::new(bytes).parse().unwrap();
+    }
+}
+
+#[test]
+pub fn mutf8_decoding() {
+    assert!(matches!(
+        mutf8::decode(b"java/lang/Object"),
+        Ok(Cow::Borrowed("java/lang/Object"))
+    ));
+    assert!(matches!(
+        mutf8::decode("\u{e9}t\u{e9}".as_bytes()),
+        Ok(Cow::Borrowed(_))
+    ));
+
+    // NUL is encoded as 0xC0 0x80.
+    assert_eq!(mutf8::decode(&[b'a', 0xC0, 0x80, b'b']).unwrap(), "a\0b");
+
+    // U+1F600 is encoded as the surrogate pair D83D DE00.
+    let emoji = [0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80];
+    assert_eq!(mutf8::decode(&emoji).unwrap(), "\u{1F600}");
+
+    assert_eq!(mutf8::decode(&[b'a', 0]), Err(1));
+    assert_eq!(mutf8::decode(&[b'a', 0xF0, 0x9F, 0x98, 0x80]), Err(1));
+    assert_eq!(mutf8::decode(&[b'a', 0xC1, 0x81]), Err(1));
+    assert_eq!(mutf8::decode(&[0xE2, 0x82]), Err(2));
+    assert_eq!(mutf8::decode(&emoji[..3]), Err(0));
+}
+
+#[test]
+pub fn mutf8_constant_pool_nodes() {
+    let mut bytes = class_with_attribute("SourceID", 0, &[]);
+    // Replace `Foo` with `F\0o`, that is F 0xC0 0x80 o.
+    bytes.splice(11..16, [0, 4, b'F', 0xC0, 0x80, b'o']);
+    let class = class_parser::Parser::new(&bytes).parse().unwrap();
+
+    match &class.cp[0] {
+        CpNode::Utf8(utf8) => assert_eq!(utf8.bytes, "F\0o"),
+        node => panic!("Expected a Utf8 node, found {:?}", node),
+    }
+
+    bytes[14] = 0xC1;
+    let parse = class_parser::Parser::new(&bytes).parse();
+    assert!(matches!(
+        parse.as_ref().map_err(ParsingError::kind),
+        Err(ParsingError::InvalidMutf8 { offset: 14 })
+    ));
+}
+
+#[test]
+pub fn constant_pool_accessors() {
+    let class = class_parser::Parser::new(include_bytes!("../../class_basket/hello_world.class"))
+        .parse()
+        .unwrap();
+    let cp = &class.cp;
+
+    assert_eq!(
+        cp.class_name(class.this_class.to_u2()).unwrap(),
+        "hello_world"
+    );
+
+    let println = (1..=cp.len() as u16)
+        .filter_map(|index| cp.member_ref(index).ok())
+        .find(|member| member.name == "println")
+        .unwrap();
+
+    assert_eq!(println.kind, MemberKind::Method);
+    assert_eq!(println.class_name, "java/io/PrintStream");
+    assert_eq!(println.descriptor, "(Ljava/lang/String;)V");
+
+    assert!(
+        (1..=cp.len() as u16).any(|index| matches!(cp.string_value(index), Ok("Hello, World!")))
+    );
+
+    assert!(matches!(
+        cp.utf8(0),
+        Err(ParsingError::InvalidCpIndex {
+            expected: [CpNodeError::Utf8],
+            index: 0
+        })
+    ));
+    assert!(matches!(
+        cp.class_name(cp.len() as u16 + 1),
+        Err(ParsingError::InvalidCpIndex { .. })
+    ));
+}
+
+#[test]
+pub fn numeric_constants() {
+    let cp = ConstantPool::new(vec![
+        CpNode::Integer(class_parser::Integer { bytes: 0xFFFF_FFFE }),
+        CpNode::Float(class_parser::Float { bytes: 0x7F80_0001 }),
+        CpNode::Long(class_parser::Long {
+            high_bytes: 0x8000_0000,
+            low_bytes: 0x0000_0001,
+        }),
+        CpNode::None,
+        CpNode::Double(class_parser::Double {
+            high_bytes: 0x7FF0_0000,
+            low_bytes: 0x0000_0001,
+        }),
+        CpNode::None,
+    ]);
+
+    assert_eq!(cp.constant(1).unwrap(), Constant::Integer(-2));
+
+    // A signalling NaN has to keep its exact bit pattern.
+    match cp.constant(2).unwrap() {
+        Constant::Float(value) => assert_eq!(value.to_bits(), 0x7F80_0001),
+        constant => panic!("Expected a Float, found {:?}", constant),
+    }
+
+    assert_eq!(cp.constant_value(3).unwrap(), Constant::Long(i64::MIN + 1));
+
+    match cp.constant(5).unwrap() {
+        Constant::Double(value) => assert_eq!(value.to_bits(), 0x7FF0_0000_0000_0001),
+        constant => panic!("Expected a Double, found {:?}", constant),
+    }
+
+    assert!(matches!(
+        cp.constant(4),
+        Err(ParsingError::InvalidCpIndex { index: 4, .. })
+    ));
+}
+
+#[test]
+pub fn constant_pool_references() {
+    // #1 is the Utf8 `I`, #2 the Class `I`, #5 the NameAndType `m` with `descriptor`, #6 a
+    // Methodref and #7 an InterfaceMethodref to it, and #8 the MethodHandle.
+    let verify = |major_v: u16, reference_kind: u8, reference_index: u16, descriptor: &str| {
+        let bytes = include_bytes!("../../class_basket/hello_world.class");
+        let mut class = class_parser::Parser::new(bytes).parse().unwrap();
+        let utf8 = |bytes: &str| {
+            CpNode::Utf8(class_parser::Utf8 {
+                bytes: Cow::Owned(bytes.to_string()),
+            })
+        };
+
+        class.major_v = major_v.into();
+        class.cp = ConstantPool::new(vec![
+            utf8("I"),
+            CpNode::Class(class_parser::Class {
+                name_index: 1.into(),
+            }),
+            utf8("m"),
+            utf8(descriptor),
+            CpNode::NameAndType(class_parser::NameAndType {
+                name_index: 3.into(),
+                descriptor_index: 4.into(),
+            }),
+            CpNode::MethodRef(class_parser::Methodref {
+                class_index: 2.into(),
+                name_and_type_index: 5.into(),
+            }),
+            CpNode::InterfaceMethodRef(class_parser::InterfaceMethodref {
+                class_index: 2.into(),
+                name_and_type_index: 5.into(),
+            }),
+            CpNode::MethodHandle(class_parser::MethodHandle {
+                reference_kind,
+                reference_index: reference_index.into(),
+            }),
+        ]);
+
+        Verifier::new(class).verify_cp()
+    };
+
+    assert!(verify(51, 6, 6, "()V").is_ok());
+    assert!(verify(52, 6, 7, "()V").is_ok());
+    assert!(verify(52, 7, 7, "()V").is_ok());
+    assert!(verify(61, 9, 7, "()V").is_ok());
+    assert!(verify(61, 5, 6, "I").is_ok());
+
+    // Interface methods can only be invoked through a MethodHandle from Java 8 on.
+    assert!(matches!(
+        verify(51, 6, 7, "()V"),
+        Err(ParsingError::MethodHandle6or7NotPointingToMethod)
+    ));
+    assert!(matches!(
+        verify(61, 7, 5, "()V"),
+        Err(ParsingError::MethodHandle6or7NotPointingToMethod)
+    ));
+    assert!(matches!(
+        verify(61, 9, 6, "()V"),
+        Err(ParsingError::MethodHandle9NotPointingToInterfaceMethodRef)
+    ));
+
+    for descriptor in ["", "V", "x", "(I", "()"] {
+        assert!(matches!(
+            verify(61, 6, 6, descriptor),
+            Err(ParsingError::InvalidDescriptor { .. })
+        ));
+    }
+}
+
+#[test]
+pub fn descriptors() {
+    let descriptor = MethodDescriptor::parse("([Ljava/lang/String;IJ)V").unwrap();
+
+    assert_eq!(
+        descriptor.parameters,
+        [
+            FieldType::Array(Box::new(FieldType::Object("java/lang/String"))),
+            FieldType::Int,
+            FieldType::Long,
+        ]
+    );
+    assert_eq!(descriptor.return_type, None);
+    assert_eq!(descriptor.parameter_slots(), 4);
+    assert_eq!(descriptor.to_string(), "([Ljava/lang/String;IJ)V");
+
+    let descriptor = MethodDescriptor::parse("(D[[Z)Ljava/lang/Object;").unwrap();
+    assert_eq!(descriptor.parameter_slots(), 3);
+    assert_eq!(descriptor.return_slots(), 1);
+    assert_eq!(FieldType::parse("[[[D").unwrap().array_dimensions(), 3);
+
+    for (descriptor, offset, kind) in [
+        ("I)V", 0, DescriptorError::MissingParameterList),
+        ("(I", 2, DescriptorError::UnexpectedEnd),
+        ("(Q)V", 1, DescriptorError::InvalidType),
+        ("(V)V", 1, DescriptorError::InvalidType),
+        (
+            "(Ljava/lang/String)V",
+            1,
+            DescriptorError::UnterminatedClassName,
+        ),
+        (
+            "(Ljava.lang.String;)V",
+            6,
+            DescriptorError::InvalidClassName,
+        ),
+        ("(Ljava//String;)V", 7, DescriptorError::InvalidClassName),
+        ("()VV", 3, DescriptorError::TrailingCharacters),
+    ] {
+        match MethodDescriptor::parse(descriptor) {
+            Err(ParsingError::InvalidDescriptor {
+                offset: found_offset,
+                kind: found_kind,
+                ..
+            }) => assert_eq!((found_offset, found_kind), (offset, kind), "{}", descriptor),
+            parse => panic!("Expected {} to fail, found {:?}", descriptor, parse),
+        }
+    }
+
+    let too_deep = "[".repeat(256) + "I";
+    assert!(matches!(
+        FieldType::parse(&too_deep),
+        Err(ParsingError::InvalidDescriptor {
+            kind: DescriptorError::TooManyDimensions,
+            ..
+        })
+    ));
+    assert!(FieldType::parse(&too_deep[1..]).is_ok());
+}
+
+#[test]
+pub fn signatures() {
+    let signature = ClassSignature::parse(
+        "<K:Ljava/lang/Object;V::Ljava/lang/Comparable<-TV;>;>Ljava/util/AbstractMap<TK;TV;>;Ljava/io/Serializable;",
+    )
+    .unwrap();
+
+    assert_eq!(signature.type_parameters.len(), 2);
+    assert_eq!(signature.type_parameters[1].class_bound, None);
+    assert_eq!(signature.superclass.package, Some("java/util"));
+    assert_eq!(
+        signature.to_string(),
+        "<K, V extends java.lang.Comparable<? super V>> extends java.util.AbstractMap<K, V> implements java.io.Serializable"
+    );
+
+    let signature = MethodSignature::parse(
+        "<T:Ljava/lang/Throwable;>(Ljava/util/List<+[I>;Lpkg/Outer<TT;>.Inner<*>;J)[TT;^TT;^Ljava/io/IOException;",
+    )
+    .unwrap();
+
+    assert_eq!(signature.parameters.len(), 3);
+    match &signature.parameters[1] {
+        TypeSignature::Class(class) => {
+            assert_eq!(class.class.name, "Outer");
+            assert_eq!(class.suffixes[0].type_arguments, [TypeArgument::Any]);
+        }
+        parameter => panic!("Expected a class type, found {:?}", parameter),
+    }
+    assert_eq!(
+        signature.to_string(),
+        "<T extends java.lang.Throwable> T[] (java.util.List<? extends int[]>, pkg.Outer<T>.Inner<?>, long) throws T, java.io.IOException"
+    );
+
+    assert_eq!(
+        FieldSignature::parse("Ljava/util/Map$Entry<Ljava/lang/String;*>;")
+            .unwrap()
+            .to_string(),
+        "java.util.Map$Entry<java.lang.String, ?>"
+    );
+
+    for (signature, offset, kind) in [
+        ("I", 0, SignatureError::InvalidType),
+        ("Ljava/util/List<>;", 16, SignatureError::InvalidType),
+        ("TT;X", 3, SignatureError::TrailingCharacters),
+        ("Ljava//List;", 6, SignatureError::InvalidIdentifier),
+        ("Ljava/util/List", 15, SignatureError::UnexpectedEnd),
+    ] {
+        match FieldSignature::parse(signature) {
+            Err(ParsingError::InvalidSignature {
+                offset: found_offset,
+                kind: found_kind,
+                ..
+            }) => assert_eq!((found_offset, found_kind), (offset, kind), "{}", signature),
+            parse => panic!("Expected {} to fail, found {:?}", signature, parse),
+        }
+    }
+
+    assert!(MethodSignature::parse("<T>()V").is_err());
+    assert!(MethodSignature::parse("()V^I").is_err());
+
+    // The class bound can be left out entirely.
+    let signature = ClassSignature::parse("<T:>Ljava/lang/Object;").unwrap();
+    assert_eq!(signature.type_parameters[0].class_bound, None);
+    assert!(signature.type_parameters[0].interface_bounds.is_empty());
+
+    // Deep nesting fits in a single CONSTANT_Utf8, and is rejected before running out of stack.
+    let too_deep = "[".repeat(60000) + "I";
+    assert!(matches!(
+        FieldSignature::parse(&too_deep),
+        Err(ParsingError::InvalidSignature {
+            offset: 0,
+            kind: SignatureError::TooManyDimensions,
+            ..
+        })
+    ));
+    assert!(FieldSignature::parse(&too_deep[59745..]).is_ok());
+
+    let too_deep = "La<".repeat(60000) + "TT;" + &">;".repeat(60000);
+    assert!(matches!(
+        FieldSignature::parse(&too_deep),
+        Err(ParsingError::InvalidSignature {
+            offset: 767,
+            kind: SignatureError::TooDeeplyNested,
+            ..
+        })
+    ));
+
+    let deepest = "La<".repeat(255) + "TT;" + &">;".repeat(255);
+    assert!(FieldSignature::parse(&deepest).is_ok());
+}
+
+#[test]
+pub fn instruction_decoding() {
+    let class = class_parser::Parser::new(include_bytes!("../../class_basket/hello_world.class"))
+        .parse()
+        .unwrap();
+
+    let main = class
+        .methods
+        .iter()
+        .find(|method| matches!(class.cp.utf8(method.name_index.to_u2()), Ok("main")))
+        .unwrap();
+
+    let code = match &main.attributes[0] {
+        Attributes::Code(code) => code,
+        attribute => panic!("Expected a Code attribute, found {:?}", attribute),
+    };
+
+    let mnemonics = code
+        .instructions()
+        .map(|instruction| instruction.map(|(pc, instruction)| (pc, instruction.mnemonic())))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+    assert_eq!(
+        mnemonics,
+        [
+            (0, "getstatic"),
+            (3, "ldc"),
+            (5, "invokevirtual"),
+            (8, "return")
+        ]
+    );
+
+    #[rustfmt::skip]
+    let code = [
+        0xC4, 0x84, 0x01, 0x00, 0xFF, 0xFE, // wide iinc 256 -2
+        0xAA, 0x00,                         // tableswitch, 1 padding byte
+        0x00, 0x00, 0x00, 0x10,             // default
+        0x00, 0x00, 0x00, 0x01,             // low
+        0x00, 0x00, 0x00, 0x02,             // high
+        0xFF, 0xFF, 0xFF, 0xFA,
+        0x00, 0x00, 0x00, 0x08,
+        0xB9, 0x00, 0x07, 0x02, 0x00,       // invokeinterface #7 2
+        0xBA, 0x00, 0x09, 0x00, 0x00,       // invokedynamic #9
+    ];
+
+    let instructions = Instructions::new(&code)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+    assert_eq!(
+        instructions[0],
+        (
+            0,
+            Instruction::Iinc {
+                index: 256,
+                value: -2
+            }
+        )
+    );
+    assert_eq!(
+        instructions[1],
+        (
+            6,
+            Instruction::TableSwitch {
+                default: 16,
+                low: 1,
+                high: 2,
+                offsets: vec![-6, 8]
+            }
+        )
+    );
+    assert_eq!(instructions[1].1.branch_targets(6), [22, 0, 14]);
+    assert_eq!(
+        instructions[2],
+        (28, Instruction::InvokeInterface { index: 7, count: 2 })
+    );
+    assert_eq!(instructions[3], (33, Instruction::InvokeDynamic(9)));
+
+    // Every truncation of the code array has to fail cleanly.
+    for length in [1, 3, 7, 8, 12, 20, 27, 30, 36] {
+        let last = Instructions::new(&code[..length]).last().unwrap();
+        assert!(
+            matches!(last, Err(ParsingError::TruncatedInstruction { .. })),
+            "{}",
+            length
+        );
+    }
+
+    assert!(matches!(
+        Instructions::new(&[0x00, 0xCB]).last(),
+        Some(Err(ParsingError::InvalidOpcode {
+            pc: 1,
+            opcode: 0xCB
+        }))
+    ));
+    assert!(matches!(
+        Instructions::new(&[0xBA, 0x00, 0x01, 0x00, 0x01]).last(),
+        Some(Err(ParsingError::InvalidInstructionOperand { pc: 0, .. }))
+    ));
+}
+
+#[test]
+pub fn class_basket_round_trips() {
+    for bytes in [
+        &include_bytes!("../../class_basket/complex_math.class")[..],
+        include_bytes!("../../class_basket/empty.class"),
+        include_bytes!("../../class_basket/hello_world.class"),
+        include_bytes!("../../class_basket/large.class"),
+        include_bytes!("../../class_basket/module-info.class"),
+    ] {
+        let class = class_parser::Parser::new(bytes).parse().unwrap();
+        assert_eq!(class.to_bytes().unwrap(), bytes);
+    }
+
+    let bytes = class_with_attribute("Custom", 3, &[1, 2, 3]);
+    let class = class_parser::Parser::new(&bytes).parse().unwrap();
+
+    let mut written = vec![];
+    class.write_to(&mut written).unwrap();
+    assert_eq!(written, bytes);
+}
+
+#[test]
+pub fn written_structures_round_trip() {
+    let mut bytes = vec![0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 49, 0, 13];
+
+    // A Long (#1) and a Double (#3) each followed by their unusable slot, then the Utf8 `Foo`
+    // (#5), the class `Foo` (#6), the Utf8s `m` (#7), `()V` (#8), `Code` (#9) and `Custom` (#10),
+    // and a Double (#11) whose unusable slot ends the constant pool.
+    bytes.extend([5, 0, 0, 0, 1, 0, 0, 0, 2]);
+    bytes.extend([6, 0x3F, 0xF8, 0, 0, 0, 0, 0, 0]);
+    bytes.extend([1, 0, 3]);
+    bytes.extend(b"Foo");
+    bytes.extend([7, 0, 5]);
+    for name in ["m", "()V", "Code", "Custom"] {
+        bytes.push(1);
+        bytes.extend((name.len() as u16).to_be_bytes());
+        bytes.extend(name.as_bytes());
+    }
+    bytes.extend([6, 0xBF, 0xF0, 0, 0, 0, 0, 0, 0]);
+
+    // access_flags, this_class, super_class, interfaces, fields, and a method `static m()V`.
+    bytes.extend([
+        0, 0x21, 0, 6, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0x08, 0, 7, 0, 8, 0, 2,
+    ]);
+
+    // `wide iload 1` and `wide iinc 1 5` whose operands fit in the short form, and switches needing
+    // 2 bytes of padding, all branching to the `return` at 54.
+    let code = [
+        // wide iload 1, iconst_0
+        0xC4, 0x15, 0x00, 0x01, 0x03,
+        // tableswitch at 5: padding, default, low 0, high 1, and its 2 offsets.
+        0xAA, 0, 0, 0, 0, 0, 49, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 49, 0, 0, 0, 49,
+        // iconst_0, then lookupswitch at 29: padding, default, and the pair for 0.
+        0x03, 0xAB, 0, 0, 0, 0, 0, 25, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 25,
+        // wide iinc 1 5, return
+        0xC4, 0x84, 0x00, 0x01, 0x00, 0x05, 0xB1,
+    ];
+    // A Code attribute holding an unknown attribute, then an empty unknown attribute.
+    bytes.extend([0, 9]);
+    bytes.extend((12 + code.len() as u32 + 8).to_be_bytes());
+    bytes.extend([0, 1, 0, 2]);
+    bytes.extend((code.len() as u32).to_be_bytes());
+    bytes.extend(code);
+    bytes.extend([0, 0, 0, 1, 0, 10, 0, 0, 0, 2, 7, 8]);
+    bytes.extend([0, 10, 0, 0, 0, 0]);
+
+    // An unknown attribute on the class.
+    bytes.extend([0, 1, 0, 10, 0, 0, 0, 3, 1, 2, 3]);
+
+    let class = class_parser::Parser::new(&bytes).parse().unwrap();
+    assert!(matches!(class.cp.get(2), Some(CpNode::None)));
+    assert!(matches!(class.cp.get(12), Some(CpNode::None)));
+
+    for bytes in [bytes, class_with_attribute("Custom", 3, &[1, 2, 3])] {
+        let class = class_parser::Parser::new(&bytes).parse().unwrap();
+        let written = class.to_bytes().unwrap();
+        let reparsed = class_parser::Parser::new(&written).parse().unwrap();
+        assert_eq!(reparsed.cp.len(), class.cp.len());
+        assert_eq!(reparsed.to_bytes().unwrap(), bytes);
+
+        let mut writer = ClassWriter::new();
+        class_parser::Parser::new(&written)
+            .accept(&mut writer)
+            .unwrap();
+        assert_eq!(writer.to_bytes().unwrap(), bytes);
+    }
+}
+
+#[test]
+pub fn mutf8_encoding() {
+    assert!(matches!(
+        mutf8::encode("java/lang/Object"),
+        Cow::Borrowed(b"java/lang/Object")
+    ));
+    assert_eq!(&*mutf8::encode("a\0b"), &[b'a', 0xC0, 0x80, b'b']);
+    assert_eq!(
+        &*mutf8::encode("\u{1F600}"),
+        &[0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80]
+    );
+
+    for string in ["", "\u{e9}t\u{e9}", "a\0\u{1F600}\u{ffff}"] {
+        assert_eq!(mutf8::decode(&mutf8::encode(string)).unwrap(), string);
+    }
+}
+
+#[test]
+pub fn owned_class_file() {
+    fn assert_send_sync<T: Send + Sync + 'static>() {}
+    assert_send_sync::<OwnedClassFile>();
+    assert_send_sync::<ParsingError>();
+
+    let bytes = include_bytes!("../../class_basket/hello_world.class").to_vec();
+    let class = OwnedClassFile::parse(bytes).unwrap();
+
+    let name = std::thread::spawn(move || {
+        let this_class = class.get().this_class.to_u2();
+        class.get().cp.class_name(this_class).unwrap().to_string()
+    })
+    .join()
+    .unwrap();
+
+    assert!(!name.is_empty());
+    assert!(matches!(
+        OwnedClassFile::parse(vec![0xCA, 0xFE])
+            .as_ref()
+            .map_err(ParsingError::kind),
+        Err(ParsingError::OutOfBounds)
+    ));
+}
+
+#[test]
+pub fn error_locations() {
+    // `SourceFile` needs 2 bytes, but only 1 was declared.
+    let bytes = class_with_attribute("SourceFile", 1, &[0, 1]);
+    let error = class_parser::Parser::new(&bytes).parse().unwrap_err();
+    let location = error.location().unwrap();
+
+    assert!(matches!(error.kind(), ParsingError::OutOfBounds));
+    assert_eq!(location.path, "attributes[SourceFile]");
+    assert_eq!(location.offset, bytes.len() - 2);
+    assert!(location.excerpt_offset <= location.offset);
+    assert_eq!(
+        location.excerpt,
+        &bytes[location.excerpt_offset..location.excerpt_offset + location.excerpt.len()]
+    );
+
+    let rendered = error.to_string();
+    assert!(rendered.contains("attributes[SourceFile]"));
+    assert!(rendered.contains(&format!("[{:02x}]", bytes[location.offset])));
+
+    // An unknown constant pool tag in the second node.
+    let mut bytes = class_with_attribute("SourceID", 0, &[]);
+    bytes[16] = 2;
+    let error = class_parser::Parser::new(&bytes).parse().unwrap_err();
+
+    assert!(matches!(error.kind(), ParsingError::ConstantPoolTag(2)));
+    assert_eq!(error.location().unwrap().path, "constant_pool[2]");
+    assert_eq!(error.location().unwrap().offset, 16);
+
+    // Errors point at the start of the field, with the excerpt starting there too.
+    let mut bytes = class_with_attribute("SourceID", 0, &[]);
+    bytes[0] = 0;
+    let error = class_parser::Parser::new(&bytes).parse().unwrap_err();
+    let location = error.location().unwrap();
+
+    assert!(matches!(error.kind(), ParsingError::Magic));
+    assert_eq!((location.offset, location.excerpt_offset), (0, 0));
+    assert!(error
+        .to_string()
+        .contains("at byte offset 0x0\n   00000000 |[00]"));
+}
+
+#[test]
+pub fn lazy_parsing() {
+    for bytes in [
+        &include_bytes!("../../class_basket/complex_math.class")[..],
+        include_bytes!("../../class_basket/empty.class"),
+        include_bytes!("../../class_basket/hello_world.class"),
+        include_bytes!("../../class_basket/large.class"),
+        include_bytes!("../../class_basket/module-info.class"),
+    ] {
+        let eager = class_parser::Parser::new(bytes).parse().unwrap();
+        let lazy = class_parser::Parser::new(bytes).parse_lazy().unwrap();
+        let lazy_cp = &lazy.cp;
+
+        assert_eq!(lazy.methods.len(), eager.methods.len());
+
+        for (lazy, eager) in lazy.methods.iter().zip(&eager.methods) {
+            assert_eq!(lazy.attributes.len(), eager.attributes.len());
+            assert_eq!(
+                lazy.attributes.get(lazy_cp).unwrap().len(),
+                eager.attributes.len()
+            );
+        }
+
+        let lazy = class_parser::Parser::new(bytes).parse_lazy().unwrap();
+        assert_eq!(
+            format!("{:?}", lazy.into_class_file().unwrap()),
+            format!("{:?}", eager)
+        );
+    }
+
+    // Attributes running past the end of the class are caught up front, anything else only once
+    // it's decoded.
+    let bytes = class_with_attribute("SourceFile", 4, &[0, 1]);
+    assert!(class_parser::Parser::new(&bytes).parse_lazy().is_err());
+
+    let bytes = class_with_attribute("SourceFile", 2, &[0, 9]);
+    let lazy = class_parser::Parser::new(&bytes).parse_lazy().unwrap();
+    assert_eq!(lazy.attributes.raw(), &[0, 3, 0, 0, 0, 2, 0, 9]);
+
+    let bytes = class_with_attribute("LineNumberTable", 2, &[0, 9]);
+    let lazy = class_parser::Parser::new(&bytes).parse_lazy().unwrap();
+    let error = lazy.attributes.get(&lazy.cp).unwrap_err();
+
+    assert!(matches!(error.kind(), ParsingError::OutOfBounds));
+    assert_eq!(
+        error.location().unwrap().path,
+        "attributes[LineNumberTable]"
+    );
+}
+
+#[test]
+pub fn owned_class_file_sources() {
+    let path = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../class_basket/hello_world.class"
+    );
+    let bytes = std::fs::read(path).unwrap();
+
+    let read = OwnedClassFile::from_reader(std::fs::File::open(path).unwrap()).unwrap();
+    let mapped = unsafe { OwnedClassFile::mmap(path) }.unwrap();
+
+    assert_eq!(read.bytes(), bytes);
+    assert_eq!(mapped.bytes(), bytes);
+    assert_eq!(format!("{:?}", read.get()), format!("{:?}", mapped.get()));
+
+    let missing = concat!(env!("CARGO_MANIFEST_DIR"), "/../class_basket/missing.class");
+    assert!(matches!(
+        unsafe { OwnedClassFile::mmap(missing) },
+        Err(ParsingError::IoError(_))
+    ));
+    assert!(matches!(
+        OwnedClassFile::from_reader(&[0xCA, 0xFE, 0xBA, 0xBE][..])
+            .as_ref()
+            .map_err(ParsingError::kind),
+        Err(ParsingError::OutOfBounds)
+    ));
+}
+
+#[derive(Default)]
+struct RecordingVisitor {
+    methods: Vec<String>,
+    instructions: Vec<&'static str>,
+    lines: usize,
+    events: Vec<String>,
+}
+
+impl<'class> ClassVisitor<'class> for RecordingVisitor {
+    fn visit_method(
+        &mut self,
+        _: class_parser::U2,
+        name_index: class_parser::U2,
+        _: class_parser::U2,
+        cp: &ConstantPool<'class>,
+    ) -> Option<&mut dyn MethodVisitor<'class>> {
+        self.methods
+            .push(cp.utf8(name_index.to_u2()).unwrap().to_string());
+        Some(self)
+    }
+
+    fn visit_annotation(
+        &mut self,
+        type_index: class_parser::U2,
+        visible: bool,
+        _: &ConstantPool<'class>,
+    ) -> Option<&mut dyn AnnotationVisitor> {
+        self.events
+            .push(format!("annotation {} {}", type_index.to_u2(), visible));
+        Some(self)
+    }
+
+    fn visit_attribute(&mut self, attribute: Attributes<'class>, _: &ConstantPool<'class>) {
+        self.events.push(format!("attribute {:?}", attribute));
+    }
+
+    fn visit_attributes(&mut self, _: &ConstantPool<'class>) -> bool {
+        true
+    }
+
+    fn visit_end(&mut self) {
+        self.events.push("end".to_string());
+    }
+}
+
+impl<'class> MethodVisitor<'class> for RecordingVisitor {
+    fn visit_code(
+        &mut self,
+        _: class_parser::U2,
+        _: class_parser::U2,
+        _: &ConstantPool<'class>,
+    ) -> bool {
+        true
+    }
+
+    fn visit_instruction(
+        &mut self,
+        _: u32,
+        instruction: &Instruction,
+        _: bool,
+        _: &ConstantPool<'class>,
+    ) {
+        self.instructions.push(instruction.mnemonic());
+    }
+
+    fn visit_line_number(&mut self, _: class_parser::U2, _: class_parser::U2) {
+        self.lines += 1;
+    }
+}
+
+impl AnnotationVisitor for RecordingVisitor {
+    fn visit_value(
+        &mut self,
+        name_index: Option<class_parser::U2>,
+        value: &class_parser::ElementValue,
+    ) {
+        self.events.push(format!(
+            "value {:?} {:?}",
+            name_index.map(class_parser::U2::to_u2),
+            value
+        ));
+    }
+
+    fn visit_array(
+        &mut self,
+        name_index: Option<class_parser::U2>,
+    ) -> Option<&mut dyn AnnotationVisitor> {
+        self.events.push(format!(
+            "array {:?}",
+            name_index.map(class_parser::U2::to_u2)
+        ));
+        Some(self)
+    }
+
+    fn visit_end(&mut self) {
+        self.events.push("annotation end".to_string());
+    }
+}
+
+#[test]
+pub fn visitors() {
+    let bytes = include_bytes!("../../class_basket/hello_world.class");
+    let mut visitor = RecordingVisitor::default();
+    class_parser::Parser::new(bytes)
+        .accept(&mut visitor)
+        .unwrap();
+
+    assert_eq!(visitor.methods, ["<init>", "main"]);
+    assert!(visitor.instructions.contains(&"invokevirtual"));
+    assert_eq!(visitor.instructions.last(), Some(&"return"));
+    assert!(visitor.lines > 0);
+    assert_eq!(visitor.events.last().map(String::as_str), Some("end"));
+
+    // @Foo(x = 1, y = { Foo.class })
+    let bytes = class_with_attribute(
+        "RuntimeVisibleAnnotations",
+        19,
+        &[
+            0, 1, 0, 1, 0, 2, 0, 3, b'I', 0, 1, 0, 3, b'[', 0, 1, b'c', 0, 1,
+        ],
+    );
+    let mut visitor = RecordingVisitor::default();
+    class_parser::Parser::new(&bytes)
+        .accept(&mut visitor)
+        .unwrap();
+
+    assert_eq!(
+        visitor.events,
+        [
+            "annotation 1 true",
+            "value Some(3) ConstValueIndex(ConstValue { tag: 73, const_value_index: 1 })",
+            "array Some(3)",
+            "value None ClassInfoIndex(1)",
+            "annotation end",
+            "annotation end",
+            "end",
+        ]
+    );
+
+    // Skipped members aren't decoded.
+    struct Nothing;
+    impl ClassVisitor<'_> for Nothing {}
+
+    let bytes = include_bytes!("../../class_basket/large.class");
+    class_parser::Parser::new(bytes)
+        .accept(&mut Nothing)
+        .unwrap();
+
+    // Neither are the Code attributes of visited methods, nor the class attributes.
+    #[derive(Default)]
+    struct Methods(usize);
+    impl<'class> ClassVisitor<'class> for Methods {
+        fn visit_method(
+            &mut self,
+            _: class_parser::U2,
+            _: class_parser::U2,
+            _: class_parser::U2,
+            _: &ConstantPool<'class>,
+        ) -> Option<&mut dyn MethodVisitor<'class>> {
+            self.0 += 1;
+            Some(self)
+        }
+
+        fn visit_attribute(&mut self, _: Attributes<'class>, _: &ConstantPool<'class>) {
+            panic!("class attributes weren't asked for");
+        }
+    }
+    impl<'class> MethodVisitor<'class> for Methods {
+        fn visit_instruction(
+            &mut self,
+            _: u32,
+            _: &Instruction,
+            _: bool,
+            _: &ConstantPool<'class>,
+        ) {
+            panic!("code wasn't asked for");
+        }
+    }
+
+    let mut visitor = Methods::default();
+    class_parser::Parser::new(bytes)
+        .accept(&mut visitor)
+        .unwrap();
+    assert!(visitor.0 > 0);
+}
+
+#[test]
+pub fn class_writer_round_trips() {
+    for bytes in [
+        &include_bytes!("../../class_basket/complex_math.class")[..],
+        include_bytes!("../../class_basket/empty.class"),
+        include_bytes!("../../class_basket/hello_world.class"),
+        include_bytes!("../../class_basket/large.class"),
+        include_bytes!("../../class_basket/module-info.class"),
+        include_bytes!("../../class_basket/point.class"),
+        include_bytes!("../../class_basket/modular/module-info.class"),
+    ] {
+        let mut writer = ClassWriter::new();
+        class_parser::Parser::new(bytes)
+            .accept(&mut writer)
+            .unwrap();
+        assert_eq!(writer.to_bytes().unwrap(), bytes);
+    }
+
+    // @Foo(x = 1, y = { Foo.class })
+    let bytes = class_with_attribute(
+        "RuntimeVisibleAnnotations",
+        19,
+        &[
+            0, 1, 0, 1, 0, 2, 0, 3, b'I', 0, 1, 0, 3, b'[', 0, 1, b'c', 0, 1,
+        ],
+    );
+    let mut writer = ClassWriter::new();
+    class_parser::Parser::new(&bytes)
+        .accept(&mut writer)
+        .unwrap();
+    assert_eq!(writer.to_bytes().unwrap(), bytes);
+
+    // `wide iload 0`, `wide iinc 0 1` and `wide ret 0` keep their prefix, though their operands
+    // fit without it, or every pc after them would move.
+    let class = hello_world_with_code(1, |code| {
+        code.code = &[
+            0xC4, 0x15, 0x00, 0x00, 0x57, 0xC4, 0x84, 0x00, 0x00, 0x00, 0x01, 0xC4, 0xA9, 0x00,
+            0x00,
+        ];
+    });
+    let bytes = class.to_bytes().unwrap();
+
+    let mut writer = ClassWriter::new();
+    class_parser::Parser::new(&bytes)
+        .accept(&mut writer)
+        .unwrap();
+    assert_eq!(writer.to_bytes().unwrap(), bytes);
+}
+
+#[test]
+pub fn class_versions() {
+    assert!(verify_version(0, 45, None).is_ok());
+    // Anything goes for the minor version before Java 12.
+    assert!(verify_version(3, 55, None).is_ok());
+    assert!(verify_version(0, 67, None).is_ok());
+
+    assert!(matches!(
+        verify_version(0, 44, None),
+        Err(ParsingError::InvalidMajorV)
+    ));
+    assert!(matches!(
+        verify_version(0, 68, None),
+        Err(ParsingError::InvalidMajorV)
+    ));
+    assert!(matches!(
+        verify_version(1, 61, None),
+        Err(ParsingError::InvalidMinorV)
+    ));
+
+    assert!(verify_version(0xFFFF, 67, Some(67)).is_ok());
+    assert!(matches!(
+        verify_version(0xFFFF, 67, None),
+        Err(ParsingError::PreviewNotEnabled { major_v: 67 })
+    ));
+    assert!(matches!(
+        verify_version(0xFFFF, 66, Some(67)),
+        Err(ParsingError::PreviewVersionMismatch {
+            major_v: 66,
+            enabled: 67
+        })
+    ));
+}
+
+#[test]
+pub fn attribute_constant_pool_indexes() {
+    for bytes in [
+        &include_bytes!("../../class_basket/complex_math.class")[..],
+        include_bytes!("../../class_basket/empty.class"),
+        include_bytes!("../../class_basket/hello_world.class"),
+        include_bytes!("../../class_basket/large.class"),
+        include_bytes!("../../class_basket/module-info.class"),
+    ] {
+        let class = class_parser::Parser::new(bytes).parse().unwrap();
+        Verifier::new(class).verify().unwrap();
+    }
+
+    let verify = |name: &str, info: &[u8]| {
+        let bytes = class_with_attribute(name, info.len() as u32, info);
+        let class = class_parser::Parser::new(&bytes).parse().unwrap();
+        Verifier::new(class).verify_class_attributes()
+    };
+
+    // #1 is the Utf8 `Foo`, #2 the Class `Foo`.
+    assert!(verify("SourceFile", &[0, 1]).is_ok());
+    assert!(verify("NestHost", &[0, 2]).is_ok());
+    assert!(verify("InnerClasses", &[0, 1, 0, 2, 0, 0, 0, 0, 0, 0]).is_ok());
+
+    assert!(matches!(
+        verify("SourceFile", &[0, 2]),
+        Err(ParsingError::InvalidIndexFromAttributeToNode(
+            ErrorAttributes::SourceFile,
+            CpNodeError::Utf8,
+            "sourcefile_index"
+        ))
+    ));
+    assert!(matches!(
+        verify("NestHost", &[0, 9]),
+        Err(ParsingError::InvalidIndexFromAttributeToNode(
+            ErrorAttributes::NestHost,
+            CpNodeError::Class,
+            "host_class_index"
+        ))
+    ));
+    assert!(matches!(
+        verify("InnerClasses", &[0, 1, 0, 2, 0, 1, 0, 0, 0, 0]),
+        Err(ParsingError::InvalidIndexFromAttributeToNode(
+            ErrorAttributes::InnerClass,
+            CpNodeError::Class,
+            "outer_class_info_index"
+        ))
+    ));
+    // @Foo(x = Foo.class), where the class is given as a Class node instead of a descriptor.
+    assert!(matches!(
+        verify(
+            "RuntimeInvisibleAnnotations",
+            &[0, 1, 0, 1, 0, 1, 0, 1, b'c', 0, 2]
+        ),
+        Err(ParsingError::InvalidIndexFromAttributeToNode(
+            ErrorAttributes::RuntimeInvisibleAnnotations,
+            CpNodeError::Utf8,
+            "class_info_index"
+        ))
+    ));
+    assert!(matches!(
+        verify("BootstrapMethods", &[0, 1, 0, 2, 0, 0]),
+        Err(ParsingError::InvalidIndexFromAttributeToNode(
+            ErrorAttributes::BootstrapMethods,
+            CpNodeError::MethodHandle,
+            "bootstrap_method_ref"
+        ))
+    ));
+}
+
+#[test]
+pub fn verify_levels() {
+    for bytes in [
+        &include_bytes!("../../class_basket/hello_world.class")[..],
+        include_bytes!("../../class_basket/large.class"),
+        include_bytes!("../../class_basket/module-info.class"),
+        include_bytes!("../../class_basket/modular/module-info.class"),
+    ] {
+        assert!(parse_and_verify(bytes, VerifyLevel::Full).is_ok());
+    }
+
+    // A module can only have its own attributes, and exactly one Module attribute.
+    let bytes = include_bytes!("../../class_basket/modular/module-info.class");
+    let mut class = class_parser::Parser::new(bytes).parse().unwrap();
+    class
+        .attributes
+        .push(Attributes::Signature(class_parser::Signature {
+            signature_index: 2.into(),
+        }));
+
+    assert!(matches!(
+        Verifier::new(class).verify(),
+        Err(ParsingError::InvalidAttributesAsModule)
+    ));
+
+    let mut class = class_parser::Parser::new(bytes).parse().unwrap();
+    class
+        .attributes
+        .retain(|z| !matches!(z, Attributes::Module(_)));
+
+    assert!(matches!(
+        Verifier::new(class).verify(),
+        Err(ParsingError::InvalidAttributesAsModule)
+    ));
+
+    // A SourceFile pointing at the Class node, on a class with major version 70.
+    let mut bytes = class_with_attribute("SourceFile", 2, &[0, 2]);
+    bytes[7] = 70;
+
+    assert!(parse_and_verify(&bytes, VerifyLevel::None).is_ok());
+
+    let problems = parse_and_verify(&bytes, VerifyLevel::FormatCheck).unwrap_err();
+    let found: Vec<_> = problems
+        .iter()
+        .map(|z| (z.severity, z.code(), z.path.as_str()))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (Severity::Error, "E0017", ""),
+            (Severity::Error, "E0037", "attributes[SourceFile]"),
+        ]
+    );
+
+    // Warnings are handed back along with the class.
+    let bytes = class_with_attribute("Custom", 1, &[0]);
+    let (_, diagnostics) = parse_and_verify(&bytes, VerifyLevel::Full).unwrap();
+    assert_eq!(diagnostics.warnings().count(), 1);
+
+    // Errors from the parser itself are handed back as is, where they were found.
+    let problems = parse_and_verify(&bytes[..20], VerifyLevel::Full).unwrap_err();
+    let found: Vec<_> = problems
+        .iter()
+        .map(|z| (z.code(), z.path.as_str()))
+        .collect();
+    assert_eq!(found, [("E0047", "constant_pool[3]")]);
+}
+
+#[test]
+pub fn verification_diagnostics() {
+    // A SourceFile pointing at the Class node, on a class with major version 70.
+    let mut bytes = class_with_attribute("SourceFile", 2, &[0, 2]);
+    bytes[7] = 70;
+
+    let class = class_parser::Parser::new(&bytes).parse().unwrap();
+    let diagnostics = Verifier::new(class).diagnostics();
+    let found: Vec<_> = diagnostics
+        .iter()
+        .map(|z| (z.severity, z.code(), z.path.as_str()))
+        .collect();
+
+    assert_eq!(
+        found,
+        [
+            (Severity::Error, "E0017", ""),
+            (Severity::Error, "E0037", "attributes[SourceFile]"),
+        ]
+    );
+    assert!(diagnostics.into_result().is_err());
+
+    // Unknown attributes are only a warning.
+    let bytes = class_with_attribute("Custom", 1, &[0]);
+    let class = class_parser::Parser::new(&bytes).parse().unwrap();
+    let diagnostics = Verifier::new(class).diagnostics().into_result().unwrap();
+
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics.warnings().count(), 1);
+    assert!(matches!(
+        &diagnostics.iter().next().unwrap().error,
+        ParsingError::UnknownAttribute(name) if name == "Custom"
+    ));
+
+    // An exception handler covering bytes past the end of the code.
+    let class = hello_world_with_code(1, |code| {
+        code.exception_table.push(ExceptionTableAttrCode {
+            start_pc: 0.into(),
+            end_pc: (code.code.len() as u16 + 1).into(),
+            handler_pc: 0.into(),
+            catch_type: 0.into(),
+        });
+    });
+
+    let diagnostics = Verifier::new(class).diagnostics();
+    let found: Vec<_> = diagnostics
+        .errors()
+        .map(|z| (z.code(), z.path.as_str()))
+        .collect();
+
+    assert_eq!(found, [("E0041", "methods[1].attributes[Code]")]);
+    assert!(diagnostics
+        .to_string()
+        .ends_with("--> in methods[1].attributes[Code]\n1 error(s), 0 warning(s)"));
+
+    // A record component Signature pointing at the Class node #2 is reported once.
+    let bytes = include_bytes!("../../class_basket/point.class");
+    let mut class = class_parser::Parser::new(bytes).parse().unwrap();
+
+    let Some(Attributes::Record(record)) = class
+        .attributes
+        .iter_mut()
+        .find(|z| matches!(z, Attributes::Record(_)))
+    else {
+        panic!("Expected point to be a record");
+    };
+    record.components[0]
+        .attributes
+        .push(Attributes::Signature(class_parser::Signature {
+            signature_index: 2.into(),
+        }));
+
+    let diagnostics = Verifier::new(class).diagnostics();
+    let found: Vec<_> = diagnostics
+        .errors()
+        .map(|z| (z.code(), z.path.as_str()))
+        .collect();
+
+    assert_eq!(
+        found,
+        [(
+            "E0037",
+            "attributes[Record].components[0].attributes[Signature]"
+        )]
+    );
+}
+
+#[test]
+pub fn member_access_flags() {
+    use method_acc_flags::{ABSTRACT, PRIVATE, PUBLIC, STATIC};
+
+    // hello_world has `<init>` and `public static main`, both with code. #11 is the Utf8 `out`
+    // and #12 `Ljava/io/PrintStream;`.
+    let errors = |change: &dyn Fn(&mut ClassFile)| {
+        let bytes = include_bytes!("../../class_basket/hello_world.class");
+        let mut class = class_parser::Parser::new(bytes).parse().unwrap();
+        change(&mut class);
+
+        Verifier::new(class)
+            .diagnostics()
+            .errors()
+            .map(|z| (z.code(), z.path.clone()))
+            .collect::<Vec<_>>()
+    };
+    let field = |access_flags: u16| FieldInfo {
+        access_flags: access_flags.into(),
+        name_index: 11.into(),
+        descriptor_index: 12.into(),
+        attributes: vec![],
+    };
+
+    assert_eq!(errors(&|_| {}), []);
+    assert_eq!(
+        errors(&|class| class.methods[1].access_flags = (PUBLIC | PRIVATE).into()),
+        [("E0054", "methods[1]".to_string())]
+    );
+    assert_eq!(
+        errors(&|class| class.methods[0].access_flags = STATIC.into()),
+        [("E0059", "methods[0]".to_string())]
+    );
+    assert_eq!(
+        errors(&|class| class.methods[1].access_flags = (PUBLIC | STATIC | ABSTRACT).into()),
+        [
+            ("E0058", "methods[1]".to_string()),
+            ("E0061", "methods[1]".to_string())
+        ]
+    );
+    assert_eq!(
+        errors(&|class| class.methods[0].attributes.clear()),
+        [("E0062", "methods[0]".to_string())]
+    );
+    assert_eq!(
+        errors(&|class| {
+            class
+                .fields
+                .push(field(field_acc_flags::FINAL | field_acc_flags::VOLATILE))
+        }),
+        [("E0055", "fields[0]".to_string())]
+    );
+
+    // As an interface, the field has to be public static final, and `<init>` is neither public
+    // nor private.
+    assert_eq!(
+        errors(&|class| {
+            class.access_flags = (class_acc_flags::INTERFACE | class_acc_flags::ABSTRACT).into();
+            class.fields.push(field(field_acc_flags::PUBLIC));
+        }),
+        [
+            ("E0056", "fields[0]".to_string()),
+            ("E0057", "methods[0]".to_string())
+        ]
+    );
+}
+
+#[test]
+pub fn type_checking() {
+    // Claims that PrintStream is a Throwable.
+    struct Hierarchy;
+
+    impl ClassHierarchy for Hierarchy {
+        fn superclass(&self, class: &str) -> Option<&str> {
+            (class == "java/io/PrintStream").then_some("java/lang/Throwable")
+        }
+
+        fn is_interface(&self, _: &str) -> Option<bool> {
+            None
+        }
+    }
+
+    // Knows that PrintStream and Throwable are classes, and that PrintStream isn't a Throwable.
+    struct Known;
+
+    impl ClassHierarchy for Known {
+        fn superclass(&self, class: &str) -> Option<&str> {
+            (class == "java/io/PrintStream").then_some("java/lang/Object")
+        }
+
+        fn is_interface(&self, class: &str) -> Option<bool> {
+            ["java/io/PrintStream", "java/lang/Throwable"]
+                .contains(&class)
+                .then_some(false)
+        }
+    }
+
+    // Swaps the code of one of hello_world's methods.
+    let check = |method: usize, code: &'static [u8], hierarchy: &dyn ClassHierarchy| {
+        let class = hello_world_with_code(method, |z| z.code = code);
+        type_check_method(&class, &class.methods[method], hierarchy)
+    };
+    let none: Vec<ClassFile> = vec![];
+
+    assert!(check(1, &[0xB1], &none).is_ok());
+    assert!(matches!(
+        check(1, &[0x2A, 0x74, 0x57, 0xB1], &none),
+        Err(ParsingError::TypeMismatch { pc: 1, expected, found })
+            if expected == "int" && found == "[Ljava/lang/String;"
+    ));
+    assert!(matches!(
+        check(1, &[0x03, 0x99, 0x00, 0x04, 0xB1, 0xB1], &none),
+        Err(ParsingError::InvalidBytecode { pc: 1, .. })
+    ));
+    assert!(matches!(
+        check(1, &[0x03, 0x57], &none),
+        Err(ParsingError::InvalidBytecode { pc: 1, .. })
+    ));
+    assert!(matches!(
+        check(1, &[0x03, 0x03, 0x03], &none),
+        Err(ParsingError::InvalidBytecode { pc: 2, .. })
+    ));
+    assert!(matches!(
+        check(1, &[0x09, 0x57, 0x57, 0xB1], &none),
+        Err(ParsingError::InvalidBytecode { pc: 1, .. })
+    ));
+    // The constructor has to call super() before returning.
+    assert!(matches!(
+        check(0, &[0xB1], &none),
+        Err(ParsingError::InvalidBytecode { pc: 0, .. })
+    ));
+
+    // Throwing System.out is only fine if it's a Throwable, which is assumed, and handed back,
+    // when the classes aren't known.
+    let code = &[0xB2, 0x00, 0x07, 0xBF];
+    assert!(matches!(
+        check(1, code, &Known),
+        Err(ParsingError::TypeMismatch { pc: 3, expected, found })
+            if expected == "java/lang/Throwable" && found == "java/io/PrintStream"
+    ));
+    assert!(check(1, code, &Hierarchy).unwrap().is_empty());
+    assert_eq!(
+        check(1, code, &none).unwrap(),
+        [(
+            "java/io/PrintStream".to_string(),
+            "java/lang/Throwable".to_string()
+        )]
+    );
+
+    // javac output calling interface methods and catching exceptions, with nothing known of the
+    // JDK classes it uses. It passes, with a warning for each pair of classes it had to assume.
+    let bytes = include_bytes!("../../class_basket/exceptions.class");
+    let (_, diagnostics) = parse_and_verify(bytes, VerifyLevel::Full).unwrap();
+    assert!(!diagnostics.has_errors());
+    assert_eq!(diagnostics.len(), 7);
+    assert!(diagnostics.iter().all(|z| z.error.code() == "E0084"));
+    assert!(diagnostics.iter().any(|z| matches!(
+        &z.error,
+        ParsingError::AssumedAssignable { from, to }
+            if from == "java/util/ArrayList" && to == "java/util/List"
+    ) && z.path == "methods[2].attributes[Code]"));
+}
+
+#[test]
+pub fn type_inference() {
+    // Swaps the code of hello_world's `static main(String[])`, with room for 3 locals.
+    let infer = |code: &'static [u8]| {
+        let class = hello_world_with_code(1, |z| {
+            z.code = code;
+            z.max_locals = 3.into();
+        });
+
+        let none: Vec<ClassFile> = vec![];
+        let checked = type_check_method(&class, &class.methods[1], &none);
+        let inferred = type_infer_method(&class, &class.methods[1], &none);
+
+        (checked, inferred)
+    };
+
+    // A subroutine storing its return address in local 1, only allowed without type checking.
+    let (checked, inferred) = infer(&[0xA8, 0x00, 0x04, 0xB1, 0x4C, 0xA9, 0x01]);
+    assert!(matches!(
+        checked,
+        Err(ParsingError::InvalidBytecode { pc: 0, .. })
+    ));
+    assert!(inferred.is_ok());
+
+    // Local 2 is an int at the first jsr and null at the second, but the subroutine doesn't touch
+    // it, so each caller gets its own back.
+    let (_, inferred) = infer(&[
+        0x03, 0x3D, 0xA8, 0x00, 0x0B, 0x1C, 0x57, 0x01, 0x4D, 0xA8, 0x00, 0x04, 0xB1, 0x4C, 0xA9,
+        0x01,
+    ]);
+    assert!(inferred.is_ok());
+
+    // Unless the subroutine stores a float in it.
+    let (_, inferred) = infer(&[
+        0x03, 0x3D, 0xA8, 0x00, 0x06, 0x1C, 0x57, 0xB1, 0x4C, 0x0B, 0x45, 0xA9, 0x01,
+    ]);
+    assert!(matches!(
+        inferred,
+        Err(ParsingError::TypeMismatch { pc: 5, expected, found })
+            if expected == "int" && found == "float"
+    ));
+
+    // The stack can't have two heights where paths join.
+    let (_, inferred) = infer(&[0x03, 0x99, 0x00, 0x04, 0x03, 0xB1]);
+    assert!(matches!(
+        inferred,
+        Err(ParsingError::TypeMismatch { pc: 4, .. })
+    ));
+
+    // Local 0 is the arguments on one path and an int on the other, so it's unusable after.
+    let (_, inferred) = infer(&[0x03, 0x99, 0x00, 0x06, 0x03, 0x3B, 0x00, 0x2A, 0x57, 0xB1]);
+    assert!(matches!(
+        inferred,
+        Err(ParsingError::TypeMismatch { pc: 7, expected, found })
+            if expected == "reference" && found == "top"
+    ));
+
+    let (_, inferred) = infer(&[0xA9, 0x00]);
+    assert!(matches!(
+        inferred,
+        Err(ParsingError::TypeMismatch { pc: 0, expected, .. }) if expected == "returnAddress"
+    ));
+
+    // Knows String and Throwable, but not PrintStream.
+    struct Known;
+
+    impl ClassHierarchy for Known {
+        fn superclass(&self, class: &str) -> Option<&str> {
+            ["java/lang/String", "java/lang/Throwable"]
+                .contains(&class)
+                .then_some("java/lang/Object")
+        }
+
+        fn is_interface(&self, class: &str) -> Option<bool> {
+            [
+                "java/lang/String",
+                "java/lang/Throwable",
+                "java/lang/Object",
+            ]
+            .contains(&class)
+            .then_some(false)
+        }
+    }
+
+    // Throws either "Hello, World!" (#13) or System.out (#7). What String and PrintStream have in
+    // common can't be told, so whether it's a Throwable is assumed, and handed back.
+    let class = hello_world_with_code(1, |z| {
+        z.code = &[
+            0x03, 0x99, 0x00, 0x08, 0x12, 0x0D, 0xA7, 0x00, 0x06, 0xB2, 0x00, 0x07, 0xBF,
+        ];
+    });
+    assert_eq!(
+        type_infer_method(&class, &class.methods[1], &Known).unwrap(),
+        [(
+            "java/io/PrintStream".to_string(),
+            "java/lang/Throwable".to_string()
+        )]
+    );
+
+    let none: Vec<ClassFile> = vec![];
+
+    // An array merged with a class, or with an array of another depth, is only known to be an
+    // Object, or an array of them, whatever order the paths are merged in. The Class #16 is renamed
+    // from PrintStream to String[], so that `anewarray #16` makes a String[][], and local 0 holds
+    // the String[] arguments.
+    let merge = |code: &[u8]| {
+        let class = hello_world_with_code(1, |z| z.code = code);
+        let mut bytes = class.to_bytes().unwrap();
+
+        let name = b"\x01\x00\x13java/io/PrintStream";
+        let at = bytes.windows(name.len()).position(|z| z == name).unwrap();
+        bytes[at + 3..at + name.len()].copy_from_slice(b"[Ljava/lang/String;");
+
+        let class = class_parser::Parser::new(&bytes).parse().unwrap();
+        type_infer_method(&class, &class.methods[1], &none)
+    };
+
+    // An int[] (newarray int) and a String (ldc #13) merge into an Object, which arraylength
+    // rejects.
+    for code in [
+        &[
+            0x03, 0x99, 0x00, 0x09, 0x04, 0xBC, 0x0A, 0xA7, 0x00, 0x05, 0x12, 0x0D, 0xBE, 0x57,
+            0xB1,
+        ][..],
+        &[
+            0x03, 0x99, 0x00, 0x08, 0x12, 0x0D, 0xA7, 0x00, 0x06, 0x04, 0xBC, 0x0A, 0xBE, 0x57,
+            0xB1,
+        ],
+    ] {
+        assert!(matches!(
+            merge(code),
+            Err(ParsingError::TypeMismatch { pc: 12, found, .. }) if found == "java/lang/Object"
+        ));
+    }
+
+    // A String[][] and a String[] merge into an Object[], whose elements aaload at 13 takes, but
+    // can't index into at 15.
+    for code in [
+        &[
+            0x03, 0x99, 0x00, 0x0A, 0x04, 0xBD, 0x00, 0x10, 0xA7, 0x00, 0x04, 0x2A, 0x03, 0x32,
+            0x03, 0x32, 0x57, 0xB1,
+        ][..],
+        &[
+            0x03, 0x99, 0x00, 0x07, 0x2A, 0xA7, 0x00, 0x07, 0x04, 0xBD, 0x00, 0x10, 0x03, 0x32,
+            0x03, 0x32, 0x57, 0xB1,
+        ],
+    ] {
+        assert!(matches!(
+            merge(code),
+            Err(ParsingError::TypeMismatch { pc: 15, found, .. }) if found == "java/lang/Object"
+        ));
+    }
+
+    // Real code passes too, StackMapTable or not.
+
+    for bytes in [
+        &include_bytes!("../../class_basket/hello_world.class")[..],
+        &include_bytes!("../../class_basket/large.class")[..],
+    ] {
+        let class = class_parser::Parser::new(bytes).parse().unwrap();
+
+        for method in &class.methods {
+            assert!(type_infer_method(&class, method, &none).is_ok());
+        }
+    }
+
+    // The Verifier picks type inference for classes older than major version 50.
+    for bytes in [
+        &include_bytes!("../../class_basket/hello_world.class")[..],
+        include_bytes!("../../class_basket/exceptions.class"),
+    ] {
+        let mut bytes = bytes.to_vec();
+        bytes[7] = 49;
+        assert!(parse_and_verify(&bytes, VerifyLevel::Full).is_ok());
+    }
+
+    // main as javac compiled nested try/finally blocks before Java 6, each finally block being a
+    // subroutine printing with System.out (#7), "Hello, World!" (#13) and println (#15).
+    #[rustfmt::skip]
+    let code: &[u8] = &[
+        // try { try { print } finally { print } } finally { print }
+        0xB2, 0x00, 0x07, 0x12, 0x0D, 0xB6, 0x00, 0x0F, // 0: print
+        0xA8, 0x00, 0x0C, // 8: jsr 20
+        0xA7, 0x00, 0x14, // 11: goto 31
+        0x4C, 0xA8, 0x00, 0x05, 0x2B, 0xBF, // 14: astore_1, jsr 20, aload_1, athrow
+        0x4D, 0xB2, 0x00, 0x07, 0x12, 0x0D, 0xB6, 0x00, 0x0F, 0xA9, 0x02, // 20: the inner finally
+        0xA8, 0x00, 0x0C, // 31: jsr 43
+        0xA7, 0x00, 0x15, // 34: goto 55
+        0x4E, 0xA8, 0x00, 0x05, 0x2D, 0xBF, // 37: astore_3, jsr 43, aload_3, athrow
+        0x3A, 0x04, 0xB2, 0x00, 0x07, 0x12, 0x0D, 0xB6, 0x00, 0x0F, 0xA9, 0x04, // 43: the outer finally
+        0xB1, // 55: return
+    ];
+    // Written out as major version 49, with room for 5 locals and `handlers` catching anything.
+    let subroutines = |code: &[u8], handlers: &[(u16, u16, u16)]| {
+        let mut class = hello_world_with_code(1, |z| {
+            z.code = code;
+            z.max_locals = 5.into();
+            z.exception_table = handlers
+                .iter()
+                .map(|&(start_pc, end_pc, handler_pc)| ExceptionTableAttrCode {
+                    start_pc: start_pc.into(),
+                    end_pc: end_pc.into(),
+                    handler_pc: handler_pc.into(),
+                    catch_type: 0.into(),
+                })
+                .collect();
+        });
+        class.major_v = 49.into();
+
+        class.to_bytes().unwrap()
+    };
+    let handlers = &[(0, 8, 14), (0, 31, 37)];
+
+    let bytes = subroutines(code, handlers);
+    let (_, diagnostics) = parse_and_verify(&bytes, VerifyLevel::Full).unwrap();
+    assert!(diagnostics.is_empty());
+
+    // Returning from the outer subroutine to the inner one's address is caught.
+    let mut code = code.to_vec();
+    code[54] = 0x02;
+
+    let bytes = subroutines(&code, handlers);
+    let problems = parse_and_verify(&bytes, VerifyLevel::Full).unwrap_err();
+    assert!(matches!(
+        &problems.iter().next().unwrap().error,
+        ParsingError::TypeMismatch { pc: 53, expected, .. } if expected == "returnAddress"
+    ));
+
+    // A finally block nested in another one, the outer subroutine calling the inner one.
+    #[rustfmt::skip]
+    let code: &[u8] = &[
+        // try { print } finally { try { print } finally { print } }
+        0xB2, 0x00, 0x07, 0x12, 0x0D, 0xB6, 0x00, 0x0F, // 0: print
+        0xA8, 0x00, 0x0C, // 8: jsr 20
+        0xA7, 0x00, 0x2C, // 11: goto 55
+        0x4C, 0xA8, 0x00, 0x05, 0x2B, 0xBF, // 14: astore_1, jsr 20, aload_1, athrow
+        0x4D, 0xB2, 0x00, 0x07, 0x12, 0x0D, 0xB6, 0x00, 0x0F, // 20: the outer finally
+        0xA8, 0x00, 0x0C, // 29: jsr 41
+        0xA7, 0x00, 0x15, // 32: goto 53
+        0x4E, 0xA8, 0x00, 0x05, 0x2D, 0xBF, // 35: astore_3, jsr 41, aload_3, athrow
+        0x3A, 0x04, 0xB2, 0x00, 0x07, 0x12, 0x0D, 0xB6, 0x00, 0x0F, 0xA9, 0x04, // 41: the inner finally
+        0xA9, 0x02, // 53: ret 2, leaving the outer finally
+        0xB1, // 55: return
+    ];
+    let handlers = &[(0, 8, 14), (21, 29, 35)];
+
+    let bytes = subroutines(code, handlers);
+    let (_, diagnostics) = parse_and_verify(&bytes, VerifyLevel::Full).unwrap();
+    assert!(diagnostics.is_empty());
+
+    // Instead of printing, the inner subroutine overwrites the outer one's return address in local
+    // 2 with a float, which the outer one's ret at 53 has to see.
+    let mut clobbered = code.to_vec();
+    clobbered[43..51].copy_from_slice(&[0x0B, 0x45, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+
+    let bytes = subroutines(&clobbered, handlers);
+    let problems = parse_and_verify(&bytes, VerifyLevel::Full).unwrap_err();
+    assert!(matches!(
+        &problems.iter().next().unwrap().error,
+        ParsingError::TypeMismatch { pc: 53, expected, found }
+            if expected == "returnAddress" && found == "float"
+    ));
+
+    // What the inner subroutine writes is handed back through the outer one too: main stores an
+    // int in local 0 before the jsr at 8, and loads it back once it returns to 11.
+    let mut code = code.to_vec();
+    code[0..8].copy_from_slice(&[0x03, 0x3B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    code[11..14].copy_from_slice(&[0x1A, 0x57, 0xB1]);
+
+    let bytes = subroutines(&code, handlers);
+    assert!(parse_and_verify(&bytes, VerifyLevel::Full).is_ok());
+
+    // Unless the inner subroutine stores a float in it.
+    code[43..51].copy_from_slice(&[0x0B, 0x43, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+
+    let bytes = subroutines(&code, handlers);
+    let problems = parse_and_verify(&bytes, VerifyLevel::Full).unwrap_err();
+    assert!(matches!(
+        &problems.iter().next().unwrap().error,
+        ParsingError::TypeMismatch { pc: 11, expected, found }
+            if expected == "int" && found == "float"
+    ));
+}
+
+#[test]
+pub fn code_structure() {
+    // The code of hello_world's main is getstatic (0), ldc (3), invokevirtual (5) and return (8),
+    // and its LineNumberTable starts lines at 0 and 8.
+    let errors = |change: &dyn Fn(&mut AttrCode)| {
+        Verifier::new(hello_world_with_code(1, change))
+            .diagnostics()
+            .errors()
+            .map(|z| (z.code(), z.path.clone()))
+            .collect::<Vec<_>>()
+    };
+    let handler = |start_pc: u16, end_pc: u16, handler_pc: u16| ExceptionTableAttrCode {
+        start_pc: start_pc.into(),
+        end_pc: end_pc.into(),
+        handler_pc: handler_pc.into(),
+        catch_type: 0.into(),
+    };
+    let code = "methods[1].attributes[Code]".to_string();
+
+    assert_eq!(errors(&|_| {}), []);
+    assert_eq!(errors(&|z| z.exception_table.push(handler(0, 9, 8))), []);
+    assert_eq!(
+        errors(&|z| z.exception_table.push(handler(1, 8, 8))),
+        [("E0041", code.clone())]
+    );
+    assert_eq!(
+        errors(&|z| z.exception_table.push(handler(0, 4, 8))),
+        [("E0041", code.clone())]
+    );
+    assert_eq!(
+        errors(&|z| z.exception_table.push(handler(3, 3, 8))),
+        [("E0041", code.clone())]
+    );
+    assert_eq!(
+        errors(&|z| z.exception_table.push(handler(0, 8, 9))),
+        [("E0041", code.clone())]
+    );
+    assert_eq!(
+        errors(&|z| z.code = &[]),
+        [
+            ("E0040", code.clone()),
+            ("E0065", format!("{}.attributes[LineNumberTable]", code))
+        ]
+    );
+
+    // A goto into the middle of itself, and one past the end of the code.
+    assert_eq!(
+        errors(&|z| z.code = &[0xA7, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0xB1]),
+        [("E0064", code.clone())]
+    );
+    assert_eq!(
+        errors(&|z| z.code = &[0x00, 0x00, 0x00, 0x00, 0x00, 0xA7, 0x00, 0x04, 0xB1]),
+        [("E0064", code.clone())]
+    );
+
+    let line_numbers = |z: &mut AttrCode| {
+        z.attributes
+            .iter_mut()
+            .find_map(|z| match z {
+                Attributes::LineNumberTable(z) => Some(z),
+                _ => None,
+            })
+            .unwrap()
+            .line_number_table[1]
+            .start_pc = 7.into();
+    };
+    assert_eq!(
+        errors(&line_numbers),
+        [("E0065", format!("{}.attributes[LineNumberTable]", code))]
+    );
+}
+
+#[test]
+pub fn class_hierarchy() {
+    const A: u16 = 2;
+    const B: u16 = 4;
+    const C: u16 = 6;
+    const I: u16 = 8;
+    const OBJECT: u16 = 10;
+    const PERMITTED_SUBCLASSES: u16 = 13;
+    const NEST_HOST: u16 = 14;
+    const NEST_MEMBERS: u16 = 15;
+    const INTERFACE: u16 = class_acc_flags::INTERFACE | class_acc_flags::ABSTRACT;
+
+    let diagnostics = |classes: &[Vec<u8>]| {
+        let classes = classes
+            .iter()
+            .map(|z| class_parser::Parser::new(z).parse().unwrap())
+            .collect::<Vec<_>>();
+
+        HierarchyValidator::new(&classes)
+            .diagnostics()
+            .iter()
+            .map(|z| (z.code(), z.path.clone()))
+            .collect::<Vec<_>>()
+    };
+    let class = |flags, this_class, super_class, interfaces: &[u16]| {
+        class_in_hierarchy(flags, this_class, super_class, interfaces, None, &[])
+    };
+    let with = |this_class, super_class, attributes: &[(u16, &[u16])]| {
+        class_in_hierarchy(0, this_class, super_class, &[], None, attributes)
+    };
+    let with_method = |this_class, super_class, method| {
+        class_in_hierarchy(0, this_class, super_class, &[], Some(method), &[])
+    };
+    let path = |path: &str| path.to_string();
+
+    assert_eq!(
+        diagnostics(&[
+            class(0, A, OBJECT, &[]),
+            class(0, B, A, &[]),
+            class(0, C, A, &[I]),
+            class(INTERFACE, I, OBJECT, &[]),
+        ]),
+        []
+    );
+    assert_eq!(
+        diagnostics(&[
+            class(class_acc_flags::FINAL, A, OBJECT, &[]),
+            class(0, B, A, &[])
+        ]),
+        [("E0066", path("B"))]
+    );
+    assert_eq!(
+        diagnostics(&[class(0, B, I, &[]), class(INTERFACE, I, OBJECT, &[])]),
+        [("E0067", path("B"))]
+    );
+    assert_eq!(
+        diagnostics(&[class(0, A, OBJECT, &[]), class(0, C, OBJECT, &[A])]),
+        [("E0068", path("C"))]
+    );
+    assert_eq!(
+        diagnostics(&[
+            class(0, A, B, &[]),
+            class(0, B, A, &[]),
+            class(0, C, A, &[])
+        ]),
+        [("E0069", path("A")), ("E0069", path("B"))]
+    );
+    // Classes that aren't in the set are taken as they are.
+    assert_eq!(diagnostics(&[class(0, B, A, &[I])]), []);
+
+    // A permits B and C, but only B extends it, and so does I, which isn't permitted.
+    assert_eq!(
+        diagnostics(&[
+            with(A, OBJECT, &[(PERMITTED_SUBCLASSES, &[2, B, C])]),
+            class(0, B, A, &[]),
+            class(0, C, OBJECT, &[]),
+            class(0, I, A, &[]),
+        ]),
+        [
+            ("E0071", path("A.attributes[PermittedSubclasses]")),
+            ("E0070", path("I"))
+        ]
+    );
+
+    // A is the nest host of B, but C claims it too, and A lists I, which doesn't.
+    assert_eq!(
+        diagnostics(&[
+            with(A, OBJECT, &[(NEST_MEMBERS, &[2, B, I])]),
+            with(B, OBJECT, &[(NEST_HOST, &[A])]),
+            with(C, OBJECT, &[(NEST_HOST, &[A])]),
+            class(0, I, OBJECT, &[]),
+        ]),
+        [
+            ("E0072", path("A.attributes[NestMembers]")),
+            ("E0072", path("C.attributes[NestHost]"))
+        ]
+    );
+
+    // m is final in A, which only matters when it's neither private nor static.
+    use method_acc_flags::{FINAL, PRIVATE, PUBLIC, STATIC};
+
+    assert_eq!(
+        diagnostics(&[
+            with_method(A, OBJECT, PUBLIC | FINAL),
+            class(0, B, A, &[]),
+            with_method(C, B, PUBLIC),
+        ]),
+        [("E0073", path("C.methods[0]"))]
+    );
+    assert_eq!(
+        diagnostics(&[with_method(A, OBJECT, FINAL), with_method(B, A, 0)]),
+        [("E0073", path("B.methods[0]"))]
+    );
+    assert_eq!(
+        diagnostics(&[
+            with_method(A, OBJECT, PRIVATE | FINAL),
+            with_method(B, A, PUBLIC),
+        ]),
+        []
+    );
+    assert_eq!(
+        diagnostics(&[
+            with_method(A, OBJECT, PUBLIC | FINAL),
+            with_method(B, A, PUBLIC | STATIC),
+        ]),
+        []
+    );
+}
+
+#[test]
+pub fn record_component_mapping() {
+    let bytes = include_bytes!("../../class_basket/point.class");
+    let class = class_parser::Parser::new(bytes).parse().unwrap();
+    let components = record_components(&class).unwrap().unwrap();
+
+    let name = |index: class_parser::U2| class.cp.utf8(index.to_u2()).unwrap();
+    let found = components
+        .iter()
+        .map(|z| {
+            (
+                z.name,
+                z.descriptor.clone(),
+                name(z.field.name_index),
+                name(z.accessor.name_index),
+                name(z.accessor.descriptor_index),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        found,
+        [
+            ("x", FieldType::Int, "x", "x", "()I"),
+            (
+                "label",
+                FieldType::Object("java/lang/String"),
+                "label",
+                "label",
+                "()Ljava/lang/String;"
+            )
+        ]
+    );
+
+    let hello_world = include_bytes!("../../class_basket/hello_world.class");
+    let class = class_parser::Parser::new(hello_world).parse().unwrap();
+    assert!(record_components(&class).unwrap().is_none());
+
+    // point has the fields `x`, `label` and `static created`.
+    let error = |change: &dyn Fn(&mut ClassFile)| {
+        let mut class = class_parser::Parser::new(bytes).parse().unwrap();
+        change(&mut class);
+
+        record_components(&class).unwrap_err()
+    };
+    let accessor = |class: &ClassFile| {
+        class
+            .methods
+            .iter()
+            .position(|z| class.cp.utf8(z.name_index.to_u2()).unwrap() == "label")
+            .unwrap()
+    };
+
+    assert!(matches!(
+        error(&|class| class.access_flags = 0.into()),
+        ParsingError::RecordNotFinal
+    ));
+    assert!(matches!(
+        error(&|class| class.super_class = class.this_class),
+        ParsingError::RecordSuperclassNotRecord
+    ));
+    assert!(matches!(
+        error(&|class| class.fields[0].access_flags = field_acc_flags::PRIVATE.into()),
+        ParsingError::RecordComponentWithoutField(name) if name == "x"
+    ));
+    assert!(matches!(
+        error(&|class| class.fields[2].access_flags = 0.into()),
+        ParsingError::RecordFieldWithoutComponent(name) if name == "created"
+    ));
+    assert!(matches!(
+        error(&|class| {
+            let i = accessor(class);
+            class.methods[i].access_flags = method_acc_flags::PRIVATE.into();
+        }),
+        ParsingError::RecordComponentWithoutAccessor(name) if name == "label"
+    ));
+}
+
+#[test]
+pub fn module_descriptor_resolution() {
+    let bytes = include_bytes!("../../class_basket/modular/module-info.class");
+    let class = class_parser::Parser::new(bytes).parse().unwrap();
+    let module = module_descriptor(&class).unwrap().unwrap();
+
+    let requires = |name, transitive, static_phase, mandated| Requires {
+        name,
+        version: Some("17.0.15"),
+        transitive,
+        static_phase,
+        synthetic: false,
+        mandated,
+    };
+    let access = |package, targets| PackageAccess {
+        package,
+        targets,
+        synthetic: false,
+        mandated: false,
+    };
+
+    assert_eq!(module.name, "org.aftermath.modular");
+    assert!(!module.open && module.version.is_none());
+    assert_eq!(
+        module.requires,
+        [
+            requires("java.base", false, false, true),
+            requires("java.logging", true, false, false),
+            requires("java.sql", false, true, false),
+        ]
+    );
+    assert_eq!(
+        module.exports,
+        [
+            access("org/aftermath/modular/api", vec![]),
+            access("org/aftermath/modular/internal", vec!["java.logging"]),
+        ]
+    );
+    assert_eq!(
+        module.opens,
+        [access("org/aftermath/modular/internal", vec![])]
+    );
+    assert_eq!(module.uses, ["org/aftermath/modular/api/Service"]);
+    assert_eq!(
+        module.provides,
+        [Provides {
+            service: "org/aftermath/modular/api/Service",
+            implementations: vec!["org/aftermath/modular/internal/Impl"],
+        }]
+    );
+    assert_eq!(
+        module.packages.as_deref(),
+        Some(
+            &[
+                "org/aftermath/modular",
+                "org/aftermath/modular/api",
+                "org/aftermath/modular/internal"
+            ][..]
+        )
+    );
+    assert_eq!(module.main_class, Some("org/aftermath/modular/Main"));
+
+    let unnamed = include_bytes!("../../class_basket/module-info.class");
+    let class = class_parser::Parser::new(unnamed).parse().unwrap();
+    let module = module_descriptor(&class).unwrap().unwrap();
+    assert_eq!(module.name, "modulename");
+    assert!(module.packages.is_none() && module.main_class.is_none());
+    assert!(module.requires[0].mandated);
+
+    let hello_world = include_bytes!("../../class_basket/hello_world.class");
+    let class = class_parser::Parser::new(hello_world).parse().unwrap();
+    assert!(module_descriptor(&class).unwrap().is_none());
+
+    let error = |change: &dyn Fn(&mut Attributes)| {
+        let mut class = class_parser::Parser::new(bytes).parse().unwrap();
+        class.attributes.iter_mut().for_each(change);
+
+        module_descriptor(&class).unwrap_err()
+    };
+
+    assert!(matches!(
+        error(&|attribute| if let Attributes::Module(z) = attribute {
+            z.module_flags = module_acc_flags::OPEN.into();
+        }),
+        ParsingError::OpensInOpenModule
+    ));
+    assert!(matches!(
+        error(&|attribute| if let Attributes::Module(z) = attribute {
+            z.requires.remove(0);
+        }),
+        ParsingError::InvalidJavaBaseRequires
+    ));
+    // The first package is the one of the main class.
+    assert!(matches!(
+        error(&|attribute| if let Attributes::ModulePackages(z) = attribute {
+            z.package_index = &z.package_index[1..];
+        }),
+        ParsingError::PackageNotInModule(package) if package == "org/aftermath/modular"
+    ));
+}
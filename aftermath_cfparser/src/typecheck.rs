@@ -0,0 +1,1540 @@
+//------ aftermath_cfparser -- Classfile parser for the Aftermath JVM. -------//
+//
+// Part of the Aftermath JVM, under the MIT license. The LICENSE file is present
+// at the project root, please consult to it for license information.
+//
+//===----------------------------------------------------------------------===//
+//
+// This file contains the verification by type checking of JVMS §4.10.1, for
+// classes of major version 50 and above.
+//
+// The StackMapTable of a method gives the types of its locals and operand
+// stack at every branch target and exception handler. The checker expands
+// those frames, then goes through the instructions in order, simulating what
+// each one does to the types and checking that what flows into a frame is
+// assignable to it.
+//
+// Values are tracked by slot: longs and doubles take up two entries, on the
+// operand stack as well as in the locals, the second of which is Top. Whether
+// one class is assignable to another is answered by a ClassHierarchy, which
+// the caller plugs in. HotSpot loads the classes it needs to answer that, which
+// a parser can't do: when the hierarchy doesn't know one of the classes, the
+// check passes, and the pair of classes assumed to be assignable is handed
+// back to the caller.
+//
+//===----------------------------------------------------------------------===//
+
+use crate::class_parser::{
+    AttrCode, Attributes, ClassFile, CpNode, MethodInfo, ParsingError, StackMapFrame,
+    VerificationTypeInfo,
+};
+use crate::constant_pool::{Constant, ConstantPool, MemberKind};
+use crate::consts::{class_acc_flags, method_acc_flags};
+use crate::descriptor::{FieldType, MethodDescriptor};
+use crate::errors::CpNodeError;
+use crate::instructions::{ArrayType, Instruction, Instructions};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display};
+
+pub(crate) const OBJECT: &str = "java/lang/Object";
+const STRING: &str = "java/lang/String";
+const CLASS: &str = "java/lang/Class";
+const THROWABLE: &str = "java/lang/Throwable";
+const CLONEABLE: &str = "java/lang/Cloneable";
+const SERIALIZABLE: &str = "java/io/Serializable";
+const METHOD_TYPE: &str = "java/lang/invoke/MethodType";
+const METHOD_HANDLE: &str = "java/lang/invoke/MethodHandle";
+
+// How far up a superclass chain is followed, so that a circular hierarchy can't hang the checker.
+pub(crate) const MAX_HIERARCHY_DEPTH: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerificationType {
+    Top,
+    Integer,
+    Float,
+    Long,
+    Double,
+    Null,
+    UninitializedThis,
+    // The object created by the `new` at this pc, before its constructor was called.
+    Uninitialized(u16),
+    // A class, interface or array, by its name in internal form. Arrays are named by their
+    // descriptor (`[Ljava/lang/String;`).
+    Reference(String),
+    // The address a jsr to the subroutine at this pc pushes. Only ever seen by the type inference
+    // verifier, as jsr is rejected by type checking.
+    ReturnAddress(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub locals: Vec<VerificationType>,
+    pub stack: Vec<VerificationType>,
+    // Set in a constructor until the constructor of the superclass (or another one of this
+    // class) was called.
+    pub this_uninit: bool,
+}
+
+/// What the checker knows of the classes it didn't parse. Whether a class it knows nothing about
+/// is assignable to another, or another to it, can't be decided, so it's assumed to be, and the
+/// pair is returned along with the rest of the assumptions once the method passes.
+pub trait ClassHierarchy {
+    /// The direct superclass of `class`, `None` for `java/lang/Object` and unknown classes.
+    fn superclass(&self, class: &str) -> Option<&str>;
+
+    /// Whether `class` is an interface, `None` if the class isn't known.
+    fn is_interface(&self, class: &str) -> Option<bool>;
+}
+
+impl ClassHierarchy for Vec<ClassFile<'_>> {
+    fn superclass(&self, class: &str) -> Option<&str> {
+        superclass(self, class)
+    }
+
+    fn is_interface(&self, class: &str) -> Option<bool> {
+        is_interface(self, class)
+    }
+}
+
+// The class being checked, in front of what the caller knows.
+pub(crate) struct WithClass<'a> {
+    pub class: &'a ClassFile<'a>,
+    pub rest: &'a dyn ClassHierarchy,
+}
+
+impl ClassHierarchy for WithClass<'_> {
+    fn superclass(&self, class: &str) -> Option<&str> {
+        superclass(std::slice::from_ref(self.class), class).or_else(|| self.rest.superclass(class))
+    }
+
+    fn is_interface(&self, class: &str) -> Option<bool> {
+        is_interface(std::slice::from_ref(self.class), class)
+            .or_else(|| self.rest.is_interface(class))
+    }
+}
+
+fn find<'a, 'class>(classes: &'a [ClassFile<'class>], name: &str) -> Option<&'a ClassFile<'class>> {
+    classes
+        .iter()
+        .find(|z| z.cp.class_name(z.this_class.to_u2()).ok() == Some(name))
+}
+
+fn superclass<'a>(classes: &'a [ClassFile], class: &str) -> Option<&'a str> {
+    let class = find(classes, class)?;
+
+    match class.super_class.to_u2() {
+        0 => None,
+        index => class.cp.class_name(index).ok(),
+    }
+}
+
+fn is_interface(classes: &[ClassFile], class: &str) -> Option<bool> {
+    find(classes, class).map(|z| z.access_flags.to_u2() & class_acc_flags::INTERFACE != 0)
+}
+
+/// Type checks the code of `method` against its StackMapTable. Methods without code pass. Returns
+/// each `(from, to)` pair of classes that had to be assumed assignable, as `hierarchy` didn't know
+/// enough of them.
+pub fn type_check_method(
+    class: &ClassFile,
+    method: &MethodInfo,
+    hierarchy: &dyn ClassHierarchy,
+) -> Result<Vec<(String, String)>, ParsingError> {
+    let hierarchy = WithClass {
+        class,
+        rest: hierarchy,
+    };
+
+    match Method::new(class, method, &hierarchy)? {
+        Some(method) => {
+            method.type_check()?;
+            Ok(method.assumed.into_inner().into_iter().collect())
+        }
+        None => Ok(vec![]),
+    }
+}
+
+// An exception handler, with the type of the exception it catches.
+pub(crate) struct Handler {
+    pub start_pc: u32,
+    pub end_pc: u32,
+    pub handler_pc: u32,
+    pub catch_type: VerificationType,
+}
+
+// A method being verified, and everything about it the instructions are checked against.
+pub(crate) struct Method<'a> {
+    pub cp: &'a ConstantPool<'a>,
+    pub hierarchy: &'a dyn ClassHierarchy,
+    pub this_class: &'a str,
+    pub super_class: Option<&'a str>,
+    pub name: &'a str,
+    pub descriptor: MethodDescriptor<'a>,
+    pub is_static: bool,
+    pub code: &'a AttrCode<'a>,
+    // The classes assumed to be assignable so far.
+    pub assumed: RefCell<BTreeSet<(String, String)>>,
+}
+
+impl<'a> Method<'a> {
+    pub fn new(
+        class: &'a ClassFile<'a>,
+        method: &'a MethodInfo<'a>,
+        hierarchy: &'a dyn ClassHierarchy,
+    ) -> Result<Option<Self>, ParsingError> {
+        let Some(code) = method.attributes.iter().find_map(|z| match z {
+            Attributes::Code(code) => Some(code),
+            _ => None,
+        }) else {
+            return Ok(None);
+        };
+
+        let cp = &class.cp;
+        let super_class = match class.super_class.to_u2() {
+            0 => None,
+            index => Some(cp.class_name(index)?),
+        };
+
+        Ok(Some(Self {
+            cp,
+            hierarchy,
+            this_class: cp.class_name(class.this_class.to_u2())?,
+            super_class,
+            name: cp.utf8(method.name_index.to_u2())?,
+            descriptor: MethodDescriptor::parse(cp.utf8(method.descriptor_index.to_u2())?)?,
+            is_static: method.access_flags.to_u2() & method_acc_flags::STATIC != 0,
+            code,
+            assumed: RefCell::new(BTreeSet::new()),
+        }))
+    }
+
+    fn type_check(&self) -> Result<(), ParsingError> {
+        let initial = self.initial_locals()?;
+        let frames = self.stack_map(&initial)?;
+        let handlers = self.handlers()?;
+
+        let mut current = Some(self.frame(&initial, &[], 0)?);
+        let mut pcs = BTreeSet::new();
+        let mut last_pc = 0;
+
+        // The handlers covering the current instruction, found by going through them in order.
+        let mut pending: Vec<&Handler> = handlers.iter().collect();
+        pending.sort_by_key(|z| std::cmp::Reverse(z.start_pc));
+        let mut active: Vec<&Handler> = vec![];
+
+        for instruction in self.code.instructions() {
+            let (pc, instruction) = instruction?;
+            pcs.insert(pc);
+            last_pc = pc;
+
+            let frame = match (frames.get(&pc), current.take()) {
+                (Some(recorded), Some(current)) => {
+                    self.frame_is_assignable(pc, &current, recorded)?;
+                    recorded.clone()
+                }
+                (Some(recorded), None) => recorded.clone(),
+                (None, Some(current)) => current,
+                (None, None) => {
+                    return Err(invalid(
+                        pc,
+                        "an unconditional branch isn't followed by a frame",
+                    ))
+                }
+            };
+
+            while pending.last().is_some_and(|z| z.start_pc <= pc) {
+                active.extend(pending.pop());
+            }
+
+            active.retain(|z| pc < z.end_pc);
+
+            // A handler starts with the locals of the instruction that threw, and only the
+            // exception on the stack.
+            for handler in &active {
+                let recorded = frames
+                    .get(&handler.handler_pc)
+                    .ok_or_else(|| invalid(pc, "an exception handler has no stack map frame"))?;
+
+                self.state_is_assignable(
+                    pc,
+                    &frame.locals,
+                    std::slice::from_ref(&handler.catch_type),
+                    frame.this_uninit,
+                    recorded,
+                )?;
+            }
+
+            if let Instruction::Jsr(_) | Instruction::JsrW(_) | Instruction::Ret(_) = instruction {
+                return Err(invalid(
+                    pc,
+                    "jsr and ret can't be type checked, they're only allowed in classes older than major version 50",
+                ));
+            }
+
+            let mut next = frame;
+            self.execute(&mut next, pc, &instruction)?;
+
+            for target in instruction.branch_targets(pc) {
+                let recorded = u32::try_from(target)
+                    .ok()
+                    .and_then(|target| frames.get(&target))
+                    .ok_or_else(|| invalid(pc, "a branch target has no stack map frame"))?;
+
+                self.frame_is_assignable(pc, &next, recorded)?;
+            }
+
+            current = instruction.falls_through().then_some(next);
+        }
+
+        if current.is_some() {
+            return Err(invalid(last_pc, "execution falls off the end of the code"));
+        }
+
+        if let Some(pc) = frames.keys().find(|pc| !pcs.contains(pc)) {
+            return Err(invalid(
+                *pc,
+                "a stack map frame isn't at the start of an instruction",
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn is_init(&self) -> bool {
+        self.name == "<init>"
+    }
+
+    // `this` and the parameters, one entry per value (JVMS §4.10.1.6).
+    pub fn initial_locals(&self) -> Result<Vec<VerificationType>, ParsingError> {
+        let mut locals = vec![];
+
+        if !self.is_static {
+            locals.push(if self.is_init() && self.this_class != OBJECT {
+                VerificationType::UninitializedThis
+            } else {
+                VerificationType::Reference(self.this_class.to_string())
+            });
+        }
+
+        locals.extend(
+            self.descriptor
+                .parameters
+                .iter()
+                .map(VerificationType::from),
+        );
+
+        Ok(locals)
+    }
+
+    // Expands a list of values into slots, filling up the locals with Top up to max_locals.
+    pub fn frame(
+        &self,
+        locals: &[VerificationType],
+        stack: &[VerificationType],
+        pc: u32,
+    ) -> Result<Frame, ParsingError> {
+        let expand = |types: &[VerificationType]| {
+            let mut slots = vec![];
+
+            for z in types {
+                slots.push(z.clone());
+
+                if z.is_wide() {
+                    slots.push(VerificationType::Top);
+                }
+            }
+
+            slots
+        };
+
+        let mut expanded_locals = expand(locals);
+        let expanded_stack = expand(stack);
+        let max_locals = self.code.max_locals.to_u2() as usize;
+
+        if expanded_locals.len() > max_locals {
+            return Err(invalid(pc, "the locals don't fit in max_locals"));
+        }
+
+        if expanded_stack.len() > self.code.max_stack.to_u2() as usize {
+            return Err(invalid(pc, "the operand stack doesn't fit in max_stack"));
+        }
+
+        expanded_locals.resize(max_locals, VerificationType::Top);
+
+        Ok(Frame {
+            this_uninit: locals.contains(&VerificationType::UninitializedThis),
+            locals: expanded_locals,
+            stack: expanded_stack,
+        })
+    }
+
+    // The frames of the StackMapTable by pc, decompressed (JVMS §4.7.4).
+    fn stack_map(
+        &self,
+        initial: &[VerificationType],
+    ) -> Result<BTreeMap<u32, Frame>, ParsingError> {
+        let mut tables = self.code.attributes.iter().filter_map(|z| match z {
+            Attributes::StackMapTable(z) => Some(z),
+            _ => None,
+        });
+
+        let Some(table) = tables.next() else {
+            return Ok(BTreeMap::new());
+        };
+
+        if tables.next().is_some() {
+            return Err(invalid(0, "the code has more than one StackMapTable"));
+        }
+
+        let mut frames = BTreeMap::new();
+        let mut locals = initial.to_vec();
+        let mut previous: Option<u32> = None;
+
+        for frame in &table.entries {
+            let (offset_delta, stack) = match frame {
+                StackMapFrame::SameFrame(z) => (u32::from(z.frame_type), vec![]),
+                StackMapFrame::SameLocals1StackItemFrame(z) => (
+                    u32::from(z.frame_type) - 64,
+                    vec![self.verification_type(&z.stack)?],
+                ),
+                StackMapFrame::SameLocals1StackItemFrameExtended(z) => (
+                    z.offset_delta.to_u2().into(),
+                    vec![self.verification_type(&z.stack)?],
+                ),
+                StackMapFrame::ChopFrame(z) => {
+                    let chopped = usize::from(251 - z.frame_type);
+
+                    if chopped > locals.len() {
+                        return Err(invalid(
+                            0,
+                            "a chop frame removes more locals than there are",
+                        ));
+                    }
+
+                    locals.truncate(locals.len() - chopped);
+                    (z.offset_delta.to_u2().into(), vec![])
+                }
+                StackMapFrame::SameFrameExtended(z) => (z.offset_delta.to_u2().into(), vec![]),
+                StackMapFrame::AppendFrame(z) => {
+                    for z in &z.locals {
+                        locals.push(self.verification_type(z)?);
+                    }
+
+                    (z.offset_delta.to_u2().into(), vec![])
+                }
+                StackMapFrame::FullFrame(z) => {
+                    locals = z
+                        .locals
+                        .iter()
+                        .map(|z| self.verification_type(z))
+                        .collect::<Result<_, _>>()?;
+
+                    let stack = z
+                        .stack
+                        .iter()
+                        .map(|z| self.verification_type(z))
+                        .collect::<Result<_, _>>()?;
+
+                    (z.offset_delta.to_u2().into(), stack)
+                }
+            };
+
+            let pc = match previous {
+                Some(previous) => previous + offset_delta + 1,
+                None => offset_delta,
+            };
+
+            frames.insert(pc, self.frame(&locals, &stack, pc)?);
+            previous = Some(pc);
+        }
+
+        Ok(frames)
+    }
+
+    fn verification_type(
+        &self,
+        info: &VerificationTypeInfo,
+    ) -> Result<VerificationType, ParsingError> {
+        Ok(match info {
+            VerificationTypeInfo::TopVariableInfo(_) => VerificationType::Top,
+            VerificationTypeInfo::IntegerVariableInfo(_) => VerificationType::Integer,
+            VerificationTypeInfo::FloatVariableInfo(_) => VerificationType::Float,
+            VerificationTypeInfo::DoubleVariableInfo(_) => VerificationType::Double,
+            VerificationTypeInfo::LongVariableInfo(_) => VerificationType::Long,
+            VerificationTypeInfo::NullVariableInfo(_) => VerificationType::Null,
+            VerificationTypeInfo::UninitializedThisVariableInfo(_) => {
+                VerificationType::UninitializedThis
+            }
+            VerificationTypeInfo::ObjectVariableInfo(z) => {
+                VerificationType::Reference(self.cp.class_name(z.cp_index.to_u2())?.to_string())
+            }
+            VerificationTypeInfo::UninitializedVariableInfo(z) => {
+                VerificationType::Uninitialized(z.offset.to_u2())
+            }
+        })
+    }
+
+    pub fn handlers(&self) -> Result<Vec<Handler>, ParsingError> {
+        self.code
+            .exception_table
+            .iter()
+            .map(|z| {
+                let catch_type = match z.catch_type.to_u2() {
+                    0 => THROWABLE,
+                    index => self.cp.class_name(index)?,
+                };
+
+                Ok(Handler {
+                    start_pc: z.start_pc.to_u2().into(),
+                    end_pc: z.end_pc.to_u2().into(),
+                    handler_pc: z.handler_pc.to_u2().into(),
+                    catch_type: VerificationType::Reference(catch_type.to_string()),
+                })
+            })
+            .collect()
+    }
+
+    // -------------------------------------------------------------------------------------------
+
+    pub fn is_assignable(&self, from: &VerificationType, to: &VerificationType) -> bool {
+        use VerificationType as T;
+
+        match (from, to) {
+            (from, to) if from == to => true,
+            (_, T::Top) => true,
+            (T::Null, T::Reference(_)) => true,
+            (T::Reference(from), T::Reference(to)) => self.is_class_assignable(from, to),
+            _ => false,
+        }
+    }
+
+    // Interfaces are treated like java/lang/Object, as in JVMS §4.10.1.2.
+    fn is_class_assignable(&self, from: &str, to: &str) -> bool {
+        if from == to || to == OBJECT {
+            return true;
+        }
+
+        if let Some(to) = to.strip_prefix('[') {
+            let Some(from) = from.strip_prefix('[') else {
+                return false;
+            };
+
+            // Arrays of primitives are only assignable to the exact same type.
+            return match (reference_component(from), reference_component(to)) {
+                (Some(from), Some(to)) => self.is_class_assignable(from, to),
+                _ => false,
+            };
+        }
+
+        if from.starts_with('[') {
+            return to == CLONEABLE || to == SERIALIZABLE;
+        }
+
+        let to_interface = self.hierarchy.is_interface(to);
+
+        if to_interface == Some(true) {
+            return true;
+        }
+
+        let mut class = from;
+
+        for _ in 0..MAX_HIERARCHY_DEPTH {
+            match self.hierarchy.superclass(class) {
+                Some(superclass) if superclass == to => return true,
+                Some(superclass) => class = superclass,
+                // A class that isn't known can't be told apart from an interface, or from a
+                // superclass of `from`, without loading it as HotSpot would, so it's assumed to be
+                // one.
+                None if to_interface.is_none()
+                    || class != OBJECT && self.hierarchy.is_interface(class).is_none() =>
+                {
+                    return self.assume(from, to)
+                }
+                None => return false,
+            }
+        }
+
+        false
+    }
+
+    fn assume(&self, from: &str, to: &str) -> bool {
+        self.assumed
+            .borrow_mut()
+            .insert((from.to_string(), to.to_string()));
+        true
+    }
+
+    fn frame_is_assignable(&self, pc: u32, from: &Frame, to: &Frame) -> Result<(), ParsingError> {
+        self.state_is_assignable(pc, &from.locals, &from.stack, from.this_uninit, to)
+    }
+
+    fn state_is_assignable(
+        &self,
+        pc: u32,
+        locals: &[VerificationType],
+        stack: &[VerificationType],
+        this_uninit: bool,
+        to: &Frame,
+    ) -> Result<(), ParsingError> {
+        if stack.len() != to.stack.len() {
+            return Err(mismatch(
+                pc,
+                format!("{} stack slot(s)", to.stack.len()),
+                format!("{} stack slot(s)", stack.len()),
+            ));
+        }
+
+        for (i, (from, to)) in locals.iter().zip(&to.locals).enumerate() {
+            if !self.is_assignable(from, to) {
+                return Err(mismatch(pc, format!("{} in local {}", to, i), from));
+            }
+        }
+
+        for (i, (from, to)) in stack.iter().zip(&to.stack).enumerate() {
+            if !self.is_assignable(from, to) {
+                return Err(mismatch(pc, format!("{} in stack slot {}", to, i), from));
+            }
+        }
+
+        if this_uninit && !to.this_uninit {
+            return Err(mismatch(pc, "an initialized this", "uninitializedThis"));
+        }
+
+        Ok(())
+    }
+
+    // -------------------------------------------------------------------------------------------
+
+    pub fn push(&self, frame: &mut Frame, value: VerificationType) {
+        let wide = value.is_wide();
+        frame.stack.push(value);
+
+        if wide {
+            frame.stack.push(VerificationType::Top);
+        }
+    }
+
+    pub fn pop(
+        &self,
+        frame: &mut Frame,
+        pc: u32,
+        expected: &VerificationType,
+    ) -> Result<VerificationType, ParsingError> {
+        if expected.is_wide() {
+            self.slots(frame, pc, &[2])?;
+            frame.stack.pop();
+        } else {
+            self.slots(frame, pc, &[1])?;
+        }
+
+        let value = frame.stack.pop().unwrap_or(VerificationType::Top);
+
+        if !self.is_assignable(&value, expected) {
+            return Err(mismatch(pc, expected, &value));
+        }
+
+        Ok(value)
+    }
+
+    // Pops any kind of reference, including uninitialized ones.
+    pub fn pop_reference(
+        &self,
+        frame: &mut Frame,
+        pc: u32,
+    ) -> Result<VerificationType, ParsingError> {
+        self.slots(frame, pc, &[1])?;
+
+        match frame.stack.pop() {
+            Some(
+                value @ (VerificationType::Null
+                | VerificationType::Reference(_)
+                | VerificationType::Uninitialized(_)
+                | VerificationType::UninitializedThis),
+            ) => Ok(value),
+            value => Err(mismatch(
+                pc,
+                "reference",
+                value.unwrap_or(VerificationType::Top),
+            )),
+        }
+    }
+
+    // Pops an array whose type is one of `expected`, or null.
+    fn pop_array(&self, frame: &mut Frame, pc: u32, expected: &[&str]) -> Result<(), ParsingError> {
+        match self.pop_reference(frame, pc)? {
+            VerificationType::Null => Ok(()),
+            VerificationType::Reference(array) if expected.contains(&array.as_str()) => Ok(()),
+            value => Err(mismatch(pc, expected[0], value)),
+        }
+    }
+
+    // Checks that the operand stack holds at least the deepest of `depths` slots, and that none
+    // of `depths` would split a long or double in half.
+    fn slots(&self, frame: &Frame, pc: u32, depths: &[usize]) -> Result<(), ParsingError> {
+        for depth in depths {
+            let Some(index) = frame.stack.len().checked_sub(*depth) else {
+                return Err(invalid(pc, "the operand stack underflows"));
+            };
+
+            if frame.stack[index] == VerificationType::Top {
+                return Err(invalid(
+                    pc,
+                    "the instruction splits a long or a double in half",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Duplicates the `top` slots of the stack, inserting the copy `below` slots further down.
+    fn dup(
+        &self,
+        frame: &mut Frame,
+        pc: u32,
+        top: usize,
+        below: usize,
+    ) -> Result<(), ParsingError> {
+        self.slots(frame, pc, &[top, top + below])?;
+
+        let length = frame.stack.len();
+        let values = frame.stack[length - top..].to_vec();
+        frame
+            .stack
+            .splice(length - top - below..length - top - below, values);
+
+        Ok(())
+    }
+
+    pub fn local(
+        &self,
+        frame: &Frame,
+        pc: u32,
+        index: u16,
+    ) -> Result<VerificationType, ParsingError> {
+        frame
+            .locals
+            .get(usize::from(index))
+            .cloned()
+            .ok_or_else(|| invalid(pc, "the local variable index is past max_locals"))
+    }
+
+    pub fn load(
+        &self,
+        frame: &mut Frame,
+        pc: u32,
+        index: u16,
+        expected: VerificationType,
+    ) -> Result<(), ParsingError> {
+        let value = self.local(frame, pc, index)?;
+
+        if expected.is_wide() {
+            let next = self.local(frame, pc, index + 1)?;
+
+            if value != expected || next != VerificationType::Top {
+                return Err(mismatch(pc, &expected, &value));
+            }
+        } else if !self.is_assignable(&value, &expected) {
+            return Err(mismatch(pc, &expected, &value));
+        }
+
+        self.push(frame, value);
+        Ok(())
+    }
+
+    pub fn store(
+        &self,
+        frame: &mut Frame,
+        pc: u32,
+        index: u16,
+        value: VerificationType,
+    ) -> Result<(), ParsingError> {
+        let index = usize::from(index);
+        let wide = value.is_wide();
+
+        if index + usize::from(wide) >= frame.locals.len() {
+            return Err(invalid(pc, "the local variable index is past max_locals"));
+        }
+
+        // Overwriting the second half of a long or double invalidates it.
+        if index > 0 && frame.locals[index - 1].is_wide() {
+            frame.locals[index - 1] = VerificationType::Top;
+        }
+
+        frame.locals[index] = value;
+
+        if wide {
+            frame.locals[index + 1] = VerificationType::Top;
+        }
+
+        Ok(())
+    }
+
+    // -------------------------------------------------------------------------------------------
+
+    // What `instruction` does to the locals and operand stack (JVMS §4.10.1.9). Control flow is
+    // left to the caller.
+    pub fn execute(
+        &self,
+        frame: &mut Frame,
+        pc: u32,
+        instruction: &Instruction,
+    ) -> Result<(), ParsingError> {
+        use Instruction as I;
+        use VerificationType as T;
+
+        let reference = |name: &str| T::Reference(name.to_string());
+
+        match instruction {
+            I::Nop | I::Goto(_) | I::GotoW(_) => {}
+
+            I::AconstNull => self.push(frame, T::Null),
+            I::IconstM1
+            | I::Iconst0
+            | I::Iconst1
+            | I::Iconst2
+            | I::Iconst3
+            | I::Iconst4
+            | I::Iconst5
+            | I::Bipush(_)
+            | I::Sipush(_) => self.push(frame, T::Integer),
+            I::Lconst0 | I::Lconst1 => self.push(frame, T::Long),
+            I::Fconst0 | I::Fconst1 | I::Fconst2 => self.push(frame, T::Float),
+            I::Dconst0 | I::Dconst1 => self.push(frame, T::Double),
+
+            I::Ldc(index) | I::LdcW(index) => {
+                let value = self.constant_type(*index)?;
+
+                if value.is_wide() {
+                    return Err(invalid(pc, "ldc can't load a long or a double"));
+                }
+
+                self.push(frame, value);
+            }
+            I::Ldc2W(index) => {
+                let value = self.constant_type(*index)?;
+
+                if !value.is_wide() {
+                    return Err(invalid(pc, "ldc2_w can only load a long or a double"));
+                }
+
+                self.push(frame, value);
+            }
+
+            I::Iload(index) => self.load(frame, pc, *index, T::Integer)?,
+            I::Lload(index) => self.load(frame, pc, *index, T::Long)?,
+            I::Fload(index) => self.load(frame, pc, *index, T::Float)?,
+            I::Dload(index) => self.load(frame, pc, *index, T::Double)?,
+            I::Iload0 => self.load(frame, pc, 0, T::Integer)?,
+            I::Iload1 => self.load(frame, pc, 1, T::Integer)?,
+            I::Iload2 => self.load(frame, pc, 2, T::Integer)?,
+            I::Iload3 => self.load(frame, pc, 3, T::Integer)?,
+            I::Lload0 => self.load(frame, pc, 0, T::Long)?,
+            I::Lload1 => self.load(frame, pc, 1, T::Long)?,
+            I::Lload2 => self.load(frame, pc, 2, T::Long)?,
+            I::Lload3 => self.load(frame, pc, 3, T::Long)?,
+            I::Fload0 => self.load(frame, pc, 0, T::Float)?,
+            I::Fload1 => self.load(frame, pc, 1, T::Float)?,
+            I::Fload2 => self.load(frame, pc, 2, T::Float)?,
+            I::Fload3 => self.load(frame, pc, 3, T::Float)?,
+            I::Dload0 => self.load(frame, pc, 0, T::Double)?,
+            I::Dload1 => self.load(frame, pc, 1, T::Double)?,
+            I::Dload2 => self.load(frame, pc, 2, T::Double)?,
+            I::Dload3 => self.load(frame, pc, 3, T::Double)?,
+            I::Aload(index) => self.load_reference(frame, pc, *index)?,
+            I::Aload0 => self.load_reference(frame, pc, 0)?,
+            I::Aload1 => self.load_reference(frame, pc, 1)?,
+            I::Aload2 => self.load_reference(frame, pc, 2)?,
+            I::Aload3 => self.load_reference(frame, pc, 3)?,
+
+            I::Iaload => self.array_load(frame, pc, &["[I"], T::Integer)?,
+            I::Laload => self.array_load(frame, pc, &["[J"], T::Long)?,
+            I::Faload => self.array_load(frame, pc, &["[F"], T::Float)?,
+            I::Daload => self.array_load(frame, pc, &["[D"], T::Double)?,
+            I::Baload => self.array_load(frame, pc, &["[B", "[Z"], T::Integer)?,
+            I::Caload => self.array_load(frame, pc, &["[C"], T::Integer)?,
+            I::Saload => self.array_load(frame, pc, &["[S"], T::Integer)?,
+            I::Aaload => {
+                self.pop(frame, pc, &T::Integer)?;
+
+                let value = match self.pop_reference(frame, pc)? {
+                    T::Null => T::Null,
+                    T::Reference(array) => {
+                        match array.strip_prefix('[').and_then(reference_component) {
+                            Some(component) => reference(component),
+                            None => return Err(mismatch(pc, "array of references", array)),
+                        }
+                    }
+                    value => return Err(mismatch(pc, "array of references", value)),
+                };
+
+                self.push(frame, value);
+            }
+
+            I::Istore(index) => self.pop_store(frame, pc, *index, T::Integer)?,
+            I::Lstore(index) => self.pop_store(frame, pc, *index, T::Long)?,
+            I::Fstore(index) => self.pop_store(frame, pc, *index, T::Float)?,
+            I::Dstore(index) => self.pop_store(frame, pc, *index, T::Double)?,
+            I::Istore0 => self.pop_store(frame, pc, 0, T::Integer)?,
+            I::Istore1 => self.pop_store(frame, pc, 1, T::Integer)?,
+            I::Istore2 => self.pop_store(frame, pc, 2, T::Integer)?,
+            I::Istore3 => self.pop_store(frame, pc, 3, T::Integer)?,
+            I::Lstore0 => self.pop_store(frame, pc, 0, T::Long)?,
+            I::Lstore1 => self.pop_store(frame, pc, 1, T::Long)?,
+            I::Lstore2 => self.pop_store(frame, pc, 2, T::Long)?,
+            I::Lstore3 => self.pop_store(frame, pc, 3, T::Long)?,
+            I::Fstore0 => self.pop_store(frame, pc, 0, T::Float)?,
+            I::Fstore1 => self.pop_store(frame, pc, 1, T::Float)?,
+            I::Fstore2 => self.pop_store(frame, pc, 2, T::Float)?,
+            I::Fstore3 => self.pop_store(frame, pc, 3, T::Float)?,
+            I::Dstore0 => self.pop_store(frame, pc, 0, T::Double)?,
+            I::Dstore1 => self.pop_store(frame, pc, 1, T::Double)?,
+            I::Dstore2 => self.pop_store(frame, pc, 2, T::Double)?,
+            I::Dstore3 => self.pop_store(frame, pc, 3, T::Double)?,
+            I::Astore(index) => self.store_reference(frame, pc, *index)?,
+            I::Astore0 => self.store_reference(frame, pc, 0)?,
+            I::Astore1 => self.store_reference(frame, pc, 1)?,
+            I::Astore2 => self.store_reference(frame, pc, 2)?,
+            I::Astore3 => self.store_reference(frame, pc, 3)?,
+
+            I::Iastore => self.array_store(frame, pc, &["[I"], T::Integer)?,
+            I::Lastore => self.array_store(frame, pc, &["[J"], T::Long)?,
+            I::Fastore => self.array_store(frame, pc, &["[F"], T::Float)?,
+            I::Dastore => self.array_store(frame, pc, &["[D"], T::Double)?,
+            I::Bastore => self.array_store(frame, pc, &["[B", "[Z"], T::Integer)?,
+            I::Castore => self.array_store(frame, pc, &["[C"], T::Integer)?,
+            I::Sastore => self.array_store(frame, pc, &["[S"], T::Integer)?,
+            I::Aastore => {
+                // Whether the value fits in the array is only known at run time.
+                self.pop(frame, pc, &reference(OBJECT))?;
+                self.pop(frame, pc, &T::Integer)?;
+
+                match self.pop_reference(frame, pc)? {
+                    T::Null => {}
+                    T::Reference(array)
+                        if array
+                            .strip_prefix('[')
+                            .and_then(reference_component)
+                            .is_some() => {}
+                    value => return Err(mismatch(pc, "array of references", value)),
+                }
+            }
+
+            I::Pop => {
+                self.slots(frame, pc, &[1])?;
+                frame.stack.pop();
+            }
+            I::Pop2 => {
+                self.slots(frame, pc, &[2])?;
+                frame.stack.truncate(frame.stack.len() - 2);
+            }
+            I::Dup => self.dup(frame, pc, 1, 0)?,
+            I::DupX1 => self.dup(frame, pc, 1, 1)?,
+            I::DupX2 => self.dup(frame, pc, 1, 2)?,
+            I::Dup2 => self.dup(frame, pc, 2, 0)?,
+            I::Dup2X1 => self.dup(frame, pc, 2, 1)?,
+            I::Dup2X2 => self.dup(frame, pc, 2, 2)?,
+            I::Swap => {
+                self.slots(frame, pc, &[1, 2])?;
+
+                let length = frame.stack.len();
+                frame.stack.swap(length - 1, length - 2);
+            }
+
+            I::Iadd
+            | I::Isub
+            | I::Imul
+            | I::Idiv
+            | I::Irem
+            | I::Ishl
+            | I::Ishr
+            | I::Iushr
+            | I::Iand
+            | I::Ior
+            | I::Ixor => self.operation(frame, pc, &[T::Integer, T::Integer], T::Integer)?,
+            I::Ladd | I::Lsub | I::Lmul | I::Ldiv | I::Lrem | I::Land | I::Lor | I::Lxor => {
+                self.operation(frame, pc, &[T::Long, T::Long], T::Long)?
+            }
+            I::Fadd | I::Fsub | I::Fmul | I::Fdiv | I::Frem => {
+                self.operation(frame, pc, &[T::Float, T::Float], T::Float)?
+            }
+            I::Dadd | I::Dsub | I::Dmul | I::Ddiv | I::Drem => {
+                self.operation(frame, pc, &[T::Double, T::Double], T::Double)?
+            }
+            I::Lshl | I::Lshr | I::Lushr => {
+                self.operation(frame, pc, &[T::Integer, T::Long], T::Long)?
+            }
+            I::Ineg => self.operation(frame, pc, &[T::Integer], T::Integer)?,
+            I::Lneg => self.operation(frame, pc, &[T::Long], T::Long)?,
+            I::Fneg => self.operation(frame, pc, &[T::Float], T::Float)?,
+            I::Dneg => self.operation(frame, pc, &[T::Double], T::Double)?,
+
+            I::Iinc { index, .. } => {
+                let value = self.local(frame, pc, *index)?;
+
+                if value != T::Integer {
+                    return Err(mismatch(pc, T::Integer, value));
+                }
+            }
+
+            I::I2l => self.operation(frame, pc, &[T::Integer], T::Long)?,
+            I::I2f => self.operation(frame, pc, &[T::Integer], T::Float)?,
+            I::I2d => self.operation(frame, pc, &[T::Integer], T::Double)?,
+            I::L2i => self.operation(frame, pc, &[T::Long], T::Integer)?,
+            I::L2f => self.operation(frame, pc, &[T::Long], T::Float)?,
+            I::L2d => self.operation(frame, pc, &[T::Long], T::Double)?,
+            I::F2i => self.operation(frame, pc, &[T::Float], T::Integer)?,
+            I::F2l => self.operation(frame, pc, &[T::Float], T::Long)?,
+            I::F2d => self.operation(frame, pc, &[T::Float], T::Double)?,
+            I::D2i => self.operation(frame, pc, &[T::Double], T::Integer)?,
+            I::D2l => self.operation(frame, pc, &[T::Double], T::Long)?,
+            I::D2f => self.operation(frame, pc, &[T::Double], T::Float)?,
+            I::I2b | I::I2c | I::I2s => self.operation(frame, pc, &[T::Integer], T::Integer)?,
+
+            I::Lcmp => self.operation(frame, pc, &[T::Long, T::Long], T::Integer)?,
+            I::Fcmpl | I::Fcmpg => self.operation(frame, pc, &[T::Float, T::Float], T::Integer)?,
+            I::Dcmpl | I::Dcmpg => {
+                self.operation(frame, pc, &[T::Double, T::Double], T::Integer)?
+            }
+
+            I::Ifeq(_)
+            | I::Ifne(_)
+            | I::Iflt(_)
+            | I::Ifge(_)
+            | I::Ifgt(_)
+            | I::Ifle(_)
+            | I::TableSwitch { .. }
+            | I::LookupSwitch { .. } => {
+                self.pop(frame, pc, &T::Integer)?;
+            }
+            I::IfIcmpeq(_)
+            | I::IfIcmpne(_)
+            | I::IfIcmplt(_)
+            | I::IfIcmpge(_)
+            | I::IfIcmpgt(_)
+            | I::IfIcmple(_) => {
+                self.pop(frame, pc, &T::Integer)?;
+                self.pop(frame, pc, &T::Integer)?;
+            }
+            I::IfAcmpeq(_) | I::IfAcmpne(_) => {
+                self.pop_reference(frame, pc)?;
+                self.pop_reference(frame, pc)?;
+            }
+            I::IfNull(_) | I::IfNonNull(_) => {
+                self.pop_reference(frame, pc)?;
+            }
+
+            // Where the subroutine returns to is left to the type inference verifier.
+            I::Jsr(_) | I::JsrW(_) => {
+                let target = instruction
+                    .branch_targets(pc)
+                    .first()
+                    .and_then(|z| u32::try_from(*z).ok())
+                    .ok_or_else(|| invalid(pc, "the subroutine starts before the code"))?;
+
+                self.push(frame, T::ReturnAddress(target));
+            }
+            I::Ret(index) => match self.local(frame, pc, *index)? {
+                T::ReturnAddress(_) => {}
+                value => return Err(mismatch(pc, "returnAddress", value)),
+            },
+
+            I::Ireturn => self.return_value(frame, pc, T::Integer)?,
+            I::Lreturn => self.return_value(frame, pc, T::Long)?,
+            I::Freturn => self.return_value(frame, pc, T::Float)?,
+            I::Dreturn => self.return_value(frame, pc, T::Double)?,
+            I::Areturn => {
+                let expected = match &self.descriptor.return_type {
+                    Some(z) if z.is_reference() => T::from(z),
+                    _ => return Err(invalid(pc, "areturn doesn't match the return type")),
+                };
+
+                self.pop(frame, pc, &expected)?;
+            }
+            I::Return => {
+                if self.descriptor.return_type.is_some() {
+                    return Err(invalid(pc, "return doesn't match the return type"));
+                }
+
+                if self.is_init() && frame.this_uninit {
+                    return Err(invalid(
+                        pc,
+                        "the constructor returns without calling the constructor of its superclass",
+                    ));
+                }
+            }
+
+            I::GetStatic(index) => {
+                let field = self.field(*index)?;
+                self.push(frame, field);
+            }
+            I::PutStatic(index) => {
+                let field = self.field(*index)?;
+                self.pop(frame, pc, &field)?;
+            }
+            I::GetField(index) => {
+                let field = self.field(*index)?;
+                let class = self.cp.member_ref(*index)?.class_name;
+
+                self.pop(frame, pc, &reference(class))?;
+                self.push(frame, field);
+            }
+            I::PutField(index) => {
+                let field = self.field(*index)?;
+                let class = self.cp.member_ref(*index)?.class_name;
+
+                self.pop(frame, pc, &field)?;
+
+                // Constructors may set the fields of their own class before calling super().
+                if class == self.this_class && frame.stack.last() == Some(&T::UninitializedThis) {
+                    frame.stack.pop();
+                } else {
+                    self.pop(frame, pc, &reference(class))?;
+                }
+            }
+
+            I::InvokeVirtual(index)
+            | I::InvokeSpecial(index)
+            | I::InvokeStatic(index)
+            | I::InvokeInterface { index, .. } => self.invoke(frame, pc, instruction, *index)?,
+            I::InvokeDynamic(index) => {
+                let descriptor = match self.cp.get(*index) {
+                    Some(CpNode::InvokeDynamic(z)) => {
+                        self.cp
+                            .name_and_type(z.name_and_type_index.to_u2())?
+                            .descriptor
+                    }
+                    _ => {
+                        return Err(ParsingError::InvalidCpIndex {
+                            expected: &[CpNodeError::InvokeDynamic],
+                            index: *index,
+                        })
+                    }
+                };
+
+                let descriptor = MethodDescriptor::parse(descriptor)?;
+                self.arguments(frame, pc, &descriptor)?;
+            }
+
+            I::New(index) => {
+                if self.cp.class_name(*index)?.starts_with('[') {
+                    return Err(invalid(pc, "new can't create an array"));
+                }
+
+                self.push(frame, T::Uninitialized(pc as u16));
+            }
+            I::NewArray(array_type) => {
+                let array = match array_type {
+                    ArrayType::Boolean => "[Z",
+                    ArrayType::Char => "[C",
+                    ArrayType::Float => "[F",
+                    ArrayType::Double => "[D",
+                    ArrayType::Byte => "[B",
+                    ArrayType::Short => "[S",
+                    ArrayType::Int => "[I",
+                    ArrayType::Long => "[J",
+                };
+
+                self.operation(frame, pc, &[T::Integer], reference(array))?;
+            }
+            I::ANewArray(index) => {
+                let array = array_of(self.cp.class_name(*index)?);
+                self.operation(frame, pc, &[T::Integer], T::Reference(array))?;
+            }
+            I::ArrayLength => {
+                match self.pop_reference(frame, pc)? {
+                    T::Null => {}
+                    T::Reference(array) if array.starts_with('[') => {}
+                    value => return Err(mismatch(pc, "array", value)),
+                }
+
+                self.push(frame, T::Integer);
+            }
+            I::AThrow => {
+                self.pop(frame, pc, &reference(THROWABLE))?;
+            }
+            I::CheckCast(index) => {
+                let class = self.cp.class_name(*index)?;
+                self.operation(frame, pc, &[reference(OBJECT)], reference(class))?;
+            }
+            I::InstanceOf(index) => {
+                self.cp.class_name(*index)?;
+                self.operation(frame, pc, &[reference(OBJECT)], T::Integer)?;
+            }
+            I::MonitorEnter | I::MonitorExit => {
+                self.pop_reference(frame, pc)?;
+            }
+            I::MultiANewArray { index, dimensions } => {
+                let array = self.cp.class_name(*index)?;
+
+                if *dimensions == 0
+                    || array.bytes().take_while(|z| *z == b'[').count() < usize::from(*dimensions)
+                {
+                    return Err(invalid(
+                        pc,
+                        "multianewarray has more dimensions than its array type",
+                    ));
+                }
+
+                for _ in 0..*dimensions {
+                    self.pop(frame, pc, &T::Integer)?;
+                }
+
+                self.push(frame, reference(array));
+            }
+        }
+
+        if frame.stack.len() > self.code.max_stack.to_u2() as usize {
+            return Err(invalid(pc, "the operand stack grows past max_stack"));
+        }
+
+        Ok(())
+    }
+
+    // Pops `operands`, the first of which is on top of the stack, then pushes `result`.
+    fn operation(
+        &self,
+        frame: &mut Frame,
+        pc: u32,
+        operands: &[VerificationType],
+        result: VerificationType,
+    ) -> Result<(), ParsingError> {
+        for operand in operands {
+            self.pop(frame, pc, operand)?;
+        }
+
+        self.push(frame, result);
+        Ok(())
+    }
+
+    fn load_reference(&self, frame: &mut Frame, pc: u32, index: u16) -> Result<(), ParsingError> {
+        let value = self.local(frame, pc, index)?;
+
+        if !value.is_reference() {
+            return Err(mismatch(pc, "reference", value));
+        }
+
+        self.push(frame, value);
+        Ok(())
+    }
+
+    fn pop_store(
+        &self,
+        frame: &mut Frame,
+        pc: u32,
+        index: u16,
+        value: VerificationType,
+    ) -> Result<(), ParsingError> {
+        let value = self.pop(frame, pc, &value)?;
+        self.store(frame, pc, index, value)
+    }
+
+    pub fn store_reference(
+        &self,
+        frame: &mut Frame,
+        pc: u32,
+        index: u16,
+    ) -> Result<(), ParsingError> {
+        // astore also takes the return address pushed by a jsr.
+        let value = match frame.stack.last() {
+            Some(VerificationType::ReturnAddress(_)) => {
+                frame.stack.pop().unwrap_or(VerificationType::Top)
+            }
+            _ => self.pop_reference(frame, pc)?,
+        };
+
+        self.store(frame, pc, index, value)
+    }
+
+    fn array_load(
+        &self,
+        frame: &mut Frame,
+        pc: u32,
+        arrays: &[&str],
+        value: VerificationType,
+    ) -> Result<(), ParsingError> {
+        self.pop(frame, pc, &VerificationType::Integer)?;
+        self.pop_array(frame, pc, arrays)?;
+        self.push(frame, value);
+
+        Ok(())
+    }
+
+    fn array_store(
+        &self,
+        frame: &mut Frame,
+        pc: u32,
+        arrays: &[&str],
+        value: VerificationType,
+    ) -> Result<(), ParsingError> {
+        self.pop(frame, pc, &value)?;
+        self.pop(frame, pc, &VerificationType::Integer)?;
+        self.pop_array(frame, pc, arrays)
+    }
+
+    fn return_value(
+        &self,
+        frame: &mut Frame,
+        pc: u32,
+        value: VerificationType,
+    ) -> Result<(), ParsingError> {
+        let return_type = self
+            .descriptor
+            .return_type
+            .as_ref()
+            .map(VerificationType::from);
+
+        if return_type.as_ref() != Some(&value) {
+            return Err(invalid(
+                pc,
+                "the return instruction doesn't match the return type",
+            ));
+        }
+
+        self.pop(frame, pc, &value)?;
+        Ok(())
+    }
+
+    fn constant_type(&self, index: u16) -> Result<VerificationType, ParsingError> {
+        Ok(match self.cp.constant(index)? {
+            Constant::Integer(_) => VerificationType::Integer,
+            Constant::Float(_) => VerificationType::Float,
+            Constant::Long(_) => VerificationType::Long,
+            Constant::Double(_) => VerificationType::Double,
+            Constant::String(_) => VerificationType::Reference(STRING.to_string()),
+            Constant::Class(_) => VerificationType::Reference(CLASS.to_string()),
+            Constant::MethodType(_) => VerificationType::Reference(METHOD_TYPE.to_string()),
+            Constant::MethodHandle { .. } => VerificationType::Reference(METHOD_HANDLE.to_string()),
+            Constant::Dynamic { descriptor, .. } => (&FieldType::parse(descriptor)?).into(),
+        })
+    }
+
+    fn field(&self, index: u16) -> Result<VerificationType, ParsingError> {
+        let field = self.cp.member_ref(index)?;
+
+        if field.kind != MemberKind::Field {
+            return Err(ParsingError::InvalidCpIndex {
+                expected: &[CpNodeError::FieldRef],
+                index,
+            });
+        }
+
+        Ok((&FieldType::parse(field.descriptor)?).into())
+    }
+
+    // Pops the arguments of a call, then pushes what it returns.
+    fn arguments(
+        &self,
+        frame: &mut Frame,
+        pc: u32,
+        descriptor: &MethodDescriptor,
+    ) -> Result<(), ParsingError> {
+        for parameter in descriptor.parameters.iter().rev() {
+            self.pop(frame, pc, &parameter.into())?;
+        }
+
+        if let Some(return_type) = &descriptor.return_type {
+            self.push(frame, return_type.into());
+        }
+
+        Ok(())
+    }
+
+    fn invoke(
+        &self,
+        frame: &mut Frame,
+        pc: u32,
+        instruction: &Instruction,
+        index: u16,
+    ) -> Result<(), ParsingError> {
+        use VerificationType as T;
+
+        let method = self.cp.member_ref(index)?;
+
+        match (instruction, method.kind) {
+            (_, MemberKind::Field)
+            | (Instruction::InvokeVirtual(_), MemberKind::InterfaceMethod)
+            | (Instruction::InvokeInterface { .. }, MemberKind::Method) => {
+                return Err(invalid(
+                    pc,
+                    "the instruction doesn't match the kind of method it calls",
+                ))
+            }
+            _ => {}
+        }
+
+        let init = method.name == "<init>";
+
+        if method.name == "<clinit>"
+            || (init && !matches!(instruction, Instruction::InvokeSpecial(_)))
+        {
+            return Err(invalid(
+                pc,
+                "the method can't be called by this instruction",
+            ));
+        }
+
+        let descriptor = MethodDescriptor::parse(method.descriptor)?;
+
+        if let Instruction::InvokeInterface { count, .. } = instruction {
+            if u16::from(*count) != descriptor.parameter_slots() + 1 {
+                return Err(invalid(
+                    pc,
+                    "the count of invokeinterface doesn't match the descriptor",
+                ));
+            }
+        }
+
+        for parameter in descriptor.parameters.iter().rev() {
+            self.pop(frame, pc, &parameter.into())?;
+        }
+
+        match instruction {
+            Instruction::InvokeStatic(_) => {}
+            Instruction::InvokeSpecial(_) if init => {
+                if descriptor.return_type.is_some() {
+                    return Err(invalid(pc, "<init> has to return void"));
+                }
+
+                // `this` becomes an instance of the class being constructed, even when it's the
+                // constructor of the superclass that's called.
+                let (initialized, class) = match self.pop_reference(frame, pc)? {
+                    T::UninitializedThis
+                        if method.class_name == self.this_class
+                            || Some(method.class_name) == self.super_class =>
+                    {
+                        frame.this_uninit = false;
+                        (T::UninitializedThis, self.this_class)
+                    }
+                    value @ T::Uninitialized(new_pc)
+                        if self.new_class(new_pc)? == Some(method.class_name) =>
+                    {
+                        (value, method.class_name)
+                    }
+                    value => {
+                        return Err(mismatch(
+                            pc,
+                            format!("uninitialized {}", method.class_name),
+                            value,
+                        ))
+                    }
+                };
+
+                let class = T::Reference(class.to_string());
+
+                for value in frame.locals.iter_mut().chain(&mut frame.stack) {
+                    if *value == initialized {
+                        *value = class.clone();
+                    }
+                }
+            }
+            Instruction::InvokeSpecial(_) => {
+                self.pop(frame, pc, &T::Reference(self.this_class.to_string()))?;
+            }
+            // Interfaces are treated like java/lang/Object.
+            Instruction::InvokeInterface { .. } => {
+                self.pop(frame, pc, &T::Reference(OBJECT.to_string()))?;
+            }
+            _ => {
+                self.pop(frame, pc, &T::Reference(method.class_name.to_string()))?;
+            }
+        }
+
+        if let Some(return_type) = &descriptor.return_type {
+            self.push(frame, return_type.into());
+        }
+
+        Ok(())
+    }
+
+    // The class created by the `new` at `pc`, if there is one.
+    fn new_class(&self, pc: u16) -> Result<Option<&str>, ParsingError> {
+        let code = self.code.code.get(usize::from(pc)..).unwrap_or_default();
+
+        match Instructions::new(code).next() {
+            Some(Ok((_, Instruction::New(index)))) => Ok(Some(self.cp.class_name(index)?)),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl VerificationType {
+    pub fn is_wide(&self) -> bool {
+        matches!(self, VerificationType::Long | VerificationType::Double)
+    }
+
+    pub fn is_reference(&self) -> bool {
+        matches!(
+            self,
+            VerificationType::Null
+                | VerificationType::UninitializedThis
+                | VerificationType::Uninitialized(_)
+                | VerificationType::Reference(_)
+        )
+    }
+}
+
+impl From<&FieldType<'_>> for VerificationType {
+    fn from(field_type: &FieldType) -> Self {
+        match field_type {
+            FieldType::Boolean
+            | FieldType::Byte
+            | FieldType::Char
+            | FieldType::Short
+            | FieldType::Int => VerificationType::Integer,
+            FieldType::Float => VerificationType::Float,
+            FieldType::Long => VerificationType::Long,
+            FieldType::Double => VerificationType::Double,
+            FieldType::Object(name) => VerificationType::Reference(name.to_string()),
+            array @ FieldType::Array(_) => VerificationType::Reference(array.to_string()),
+        }
+    }
+}
+
+impl Display for VerificationType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerificationType::Top => write!(f, "top"),
+            VerificationType::Integer => write!(f, "int"),
+            VerificationType::Float => write!(f, "float"),
+            VerificationType::Long => write!(f, "long"),
+            VerificationType::Double => write!(f, "double"),
+            VerificationType::Null => write!(f, "null"),
+            VerificationType::UninitializedThis => write!(f, "uninitializedThis"),
+            VerificationType::Uninitialized(pc) => write!(f, "uninitialized({})", pc),
+            VerificationType::Reference(name) => write!(f, "{}", name),
+            VerificationType::ReturnAddress(_) => write!(f, "returnAddress"),
+        }
+    }
+}
+
+// The class of an array component that's a reference, from its descriptor.
+pub(crate) fn reference_component(component: &str) -> Option<&str> {
+    if component.starts_with('[') {
+        Some(component)
+    } else {
+        component.strip_prefix('L')?.strip_suffix(';')
+    }
+}
+
+pub(crate) fn array_of(component: &str) -> String {
+    if component.starts_with('[') {
+        format!("[{}", component)
+    } else {
+        format!("[L{};", component)
+    }
+}
+
+pub(crate) fn mismatch(pc: u32, expected: impl Display, found: impl Display) -> ParsingError {
+    ParsingError::TypeMismatch {
+        pc,
+        expected: expected.to_string(),
+        found: found.to_string(),
+    }
+}
+
+pub(crate) fn invalid(pc: u32, reason: &'static str) -> ParsingError {
+    ParsingError::InvalidBytecode { pc, reason }
+}
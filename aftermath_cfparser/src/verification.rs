@@ -1,67 +1,447 @@
-// This file is just random old garbage, ignore it. Currently not used. Will be
-// rewritten soon.
-
-use super::class_parser::{Attributes, ClassFile, CpNode, ParsingError};
+//------ aftermath_cfparser -- Classfile parser for the Aftermath JVM. -------//
+//
+// Part of the Aftermath JVM, under the MIT license. The LICENSE file is present
+// at the project root, please consult to it for license information.
+//
+//===----------------------------------------------------------------------===//
+//
+// This file contains the Verifier, which checks a parsed class against the
+// format checking rules of JVMS §4.8, and `parse_and_verify`, the entry point
+// tying the parser and the Verifier together.
+//
+// The Verifier doesn't stop at the first problem. Each constant pool node,
+// member and attribute is checked on its own, and everything wrong with the
+// class is reported as a Diagnostic, along with where in the class it is.
+//
+//===----------------------------------------------------------------------===//
+
+use super::class_parser::{
+    Annotation, AttrCode, Attributes, BootStrapMethods, ClassFile, CpNode, ElementValue, FieldInfo,
+    MethodInfo, Parser, ParsingError, StackMapFrame, TypeAnnotation, VerificationTypeInfo, U2,
+};
 use super::consts;
+use super::descriptor::{FieldType, MethodDescriptor};
+use super::diagnostics::{Diagnostics, Severity};
 use super::errors::Attributes as ErrorAttributes;
 use super::errors::CpNodeError;
+use super::inference::type_infer_method;
+use super::typecheck::{type_check_method, ClassHierarchy};
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyLevel {
+    // Only parse the class.
+    None,
+    // The format checks of JVMS §4.8.
+    FormatCheck,
+    // Format checking, plus decoding the bytecode of every method, and verifying it: by type
+    // checking against its StackMapTable from major version 50 on (JVMS §4.10.1), and by type
+    // inference before that (JVMS §4.10.2).
+    Full,
+}
 
 pub struct Verifier<'a> {
     class: ClassFile<'a>,
-    bootstrap_methods: Vec<usize>,
+    preview: Option<u16>,
+    level: VerifyLevel,
+    hierarchy: Option<&'a dyn ClassHierarchy>,
 }
 
-impl<'a> Verifier<'a> {
-    pub fn new(class: ClassFile<'a>) -> Self {
-        let bootstrap_methods: Vec<_> = class
-            .attributes
-            .iter()
-            .enumerate()
-            .filter_map(|x| {
-                if let Attributes::BootstrapMethods(_) = x.1 {
-                    Some(x.0)
-                } else {
-                    None
+/// Parses `bytes`, then verifies the class at `level`. Passes with the class and the warnings
+/// found, and fails with every diagnostic, not just the first error. A class that can't be parsed
+/// fails with the parser's error alone, at the path it was found at.
+pub fn parse_and_verify(
+    bytes: &[u8],
+    level: VerifyLevel,
+) -> Result<(ClassFile<'_>, Diagnostics), Diagnostics> {
+    let class = Parser::new(bytes).parse().map_err(|error| {
+        let mut diagnostics = Diagnostics::new();
+        let path = error.location().map(|z| z.path.clone()).unwrap_or_default();
+        diagnostics.error(path, error);
+        diagnostics
+    })?;
+
+    if level == VerifyLevel::None {
+        return Ok((class, Diagnostics::new()));
+    }
+
+    let verifier = Verifier::new(class).level(level);
+    let diagnostics = verifier.diagnostics().into_result()?;
+
+    Ok((verifier.class, diagnostics))
+}
+
+/// Checks the version of a class. `preview` is the major version preview features are enabled
+/// for, if they're enabled at all. It should be the runtime's own release, as classes using
+/// preview features only run on the exact release they were compiled for.
+pub fn verify_version(
+    minor_v: u16,
+    major_v: u16,
+    preview: Option<u16>,
+) -> Result<(), ParsingError> {
+    if !consts::MAJOR_VERSION_RANGE.contains(&major_v) {
+        return Err(ParsingError::InvalidMajorV);
+    }
+
+    if major_v < consts::FIRST_MAJOR_VERSION_WITH_PREVIEWS {
+        return Ok(());
+    }
+
+    match (minor_v, preview) {
+        (0, _) => Ok(()),
+        (consts::PREVIEW_MINOR_VERSION, None) => Err(ParsingError::PreviewNotEnabled { major_v }),
+        (consts::PREVIEW_MINOR_VERSION, Some(enabled)) if enabled != major_v => {
+            Err(ParsingError::PreviewVersionMismatch { major_v, enabled })
+        }
+        (consts::PREVIEW_MINOR_VERSION, Some(_)) => Ok(()),
+        _ => Err(ParsingError::InvalidMinorV),
+    }
+}
+
+// Abstract and native methods have no code, every other method has exactly one Code attribute
+// (JVMS §4.7.3). The flags of <clinit> are ignored, so it always needs code.
+fn verify_method_code(method: &MethodInfo, name: &str) -> Result<(), ParsingError> {
+    use consts::method_acc_flags::{ABSTRACT, NATIVE};
+
+    let codes = method
+        .attributes
+        .iter()
+        .filter(|z| matches!(z, Attributes::Code(..)))
+        .count();
+
+    if name != "<clinit>" && method.access_flags.to_u2() & (ABSTRACT | NATIVE) != 0 {
+        if codes != 0 {
+            return Err(ParsingError::CodeInAbstractOrNativeMethod);
+        }
+    } else if codes != 1 {
+        return Err(ParsingError::NotExactlyOneCodeAttribute);
+    }
+
+    Ok(())
+}
+
+// The pc of every instruction in the code, if it decodes.
+fn instruction_starts(code: &AttrCode) -> Option<BTreeSet<usize>> {
+    code.instructions()
+        .map(|z| z.map(|(pc, _)| pc as usize))
+        .collect::<Result<_, _>>()
+        .ok()
+}
+
+// Whether `pc` is the start of an instruction. When the code doesn't decode, any pc inside of it
+// is taken to be one, and the decoding error is reported on its own.
+fn is_instruction_start(code: &AttrCode, starts: Option<&BTreeSet<usize>>, pc: usize) -> bool {
+    match starts {
+        Some(starts) => starts.contains(&pc),
+        None => pc < code.code.len(),
+    }
+}
+
+// The code array can't be empty or larger than 65535 bytes, the range of every exception handler
+// has to start and end on instructions, as does every branch (JVMS §4.7.3, §4.9.1).
+fn verify_code_ranges(
+    code: &AttrCode,
+    starts: Option<&BTreeSet<usize>>,
+) -> Result<(), ParsingError> {
+    let length = code.code.len();
+
+    if length == 0 || length > u16::MAX as usize {
+        return Err(ParsingError::CodeAttributeCodeLength);
+    }
+
+    let is_start = |pc| is_instruction_start(code, starts, pc);
+
+    for entry in &code.exception_table {
+        let start_pc = entry.start_pc.to_u2() as usize;
+        let end_pc = entry.end_pc.to_u2() as usize;
+        let handler_pc = entry.handler_pc.to_u2() as usize;
+
+        if start_pc >= end_pc
+            || !is_start(start_pc)
+            || !(end_pc == length || is_start(end_pc))
+            || !is_start(handler_pc)
+        {
+            return Err(ParsingError::InvalidIndexesInCodeAttribute);
+        }
+    }
+
+    for (pc, instruction) in code.instructions().map_while(Result::ok) {
+        let targets = instruction.branch_targets(pc);
+
+        if !targets
+            .into_iter()
+            .all(|z| usize::try_from(z).is_ok_and(is_start))
+        {
+            return Err(ParsingError::InvalidBytecode {
+                pc,
+                reason: "a branch target isn't the start of an instruction",
+            });
+        }
+    }
+
+    Ok(())
+}
+
+// The pcs in the debugging attributes of the code have to be the start of an instruction. A local
+// variable may be live up to the end of the code (JVMS §4.7.12, §4.7.13, §4.7.14).
+fn verify_code_pcs(
+    code: &AttrCode,
+    starts: Option<&BTreeSet<usize>>,
+    attribute: &Attributes,
+) -> Result<(), ParsingError> {
+    use ErrorAttributes as A;
+
+    let is_start = |pc| is_instruction_start(code, starts, pc);
+    let is_end = |pc| pc == code.code.len() || is_start(pc);
+
+    let ranges: Vec<(A, U2, U2)> = match attribute {
+        Attributes::LineNumberTable(z) => {
+            for z in &z.line_number_table {
+                if !is_start(z.start_pc.to_u2() as usize) {
+                    return Err(ParsingError::InvalidPcInAttribute(
+                        A::LineNumberTable,
+                        "start_pc",
+                    ));
                 }
-            })
-            .collect();
+            }
+
+            return Ok(());
+        }
+        Attributes::LocalVariableTable(z) => z
+            .local_variable_table
+            .iter()
+            .map(|z| (A::LocalVariableTable, z.start_pc, z.length))
+            .collect(),
+        Attributes::LocalVariableTypeTable(z) => z
+            .local_variable_type_table
+            .iter()
+            .map(|z| (A::LocalVariableTypeTable, z.start_pc, z.length))
+            .collect(),
+        _ => return Ok(()),
+    };
+
+    for (kind, start_pc, length) in ranges {
+        let start_pc = start_pc.to_u2() as usize;
+
+        if !is_start(start_pc) {
+            return Err(ParsingError::InvalidPcInAttribute(kind, "start_pc"));
+        }
 
+        if !is_end(start_pc + length.to_u2() as usize) {
+            return Err(ParsingError::InvalidPcInAttribute(kind, "length"));
+        }
+    }
+
+    Ok(())
+}
+
+impl<'a> Verifier<'a> {
+    pub fn new(class: ClassFile<'a>) -> Self {
         Self {
             class,
-            bootstrap_methods,
+            preview: None,
+            level: VerifyLevel::FormatCheck,
+            hierarchy: None,
+        }
+    }
+
+    /// What's known of the other classes, when type checking bytecode at `VerifyLevel::Full`.
+    /// Without it, only the class being verified is known.
+    pub fn hierarchy(mut self, hierarchy: &'a dyn ClassHierarchy) -> Self {
+        self.hierarchy = Some(hierarchy);
+        self
+    }
+
+    pub fn level(mut self, level: VerifyLevel) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Accepts classes using the preview features of `major_v`.
+    pub fn enable_preview(mut self, major_v: u16) -> Self {
+        self.preview = Some(major_v);
+        self
+    }
+
+    pub fn verify(self) -> Result<ClassFile<'a>, ParsingError> {
+        match self.problems().into_iter().next() {
+            Some(problem) => Err(problem),
+            None => Ok(self.class),
         }
     }
 
-    pub fn verify(self) -> Result<ClassFile<'a>, ParsingError<'a>> {
+    /// Every error with the class, in the order they were found.
+    pub fn problems(&self) -> Vec<ParsingError> {
+        self.diagnostics()
+            .into_iter()
+            .filter(|z| z.severity == Severity::Error)
+            .map(|z| z.error)
+            .collect()
+    }
+
+    /// Everything wrong with the class, along with where it was found.
+    pub fn diagnostics(&self) -> Diagnostics {
         let class = &self.class;
+        let mut diagnostics = Diagnostics::new();
 
-        let major_v = class.major_v.to_u2();
-        let minor_v = class.minor_v.to_u2();
+        if let Err(error) =
+            verify_version(class.minor_v.to_u2(), class.major_v.to_u2(), self.preview)
+        {
+            diagnostics.error("", error);
+        }
+
+        for (i, node) in class.cp.iter().enumerate() {
+            if let Err(error) = self.verify_cp_node(node) {
+                diagnostics.error(format!("constant_pool[{}]", i + 1), error);
+            }
+        }
 
-        if consts::MAJOR_VERSION_RANGE.contains(&major_v) {
-            return Err(ParsingError::InvalidMajorV);
+        if let Err(error) = self.verify_class() {
+            diagnostics.error("", error);
         }
 
-        if major_v >= 56 && (minor_v != 0 || minor_v != 65535) {
-            return Err(ParsingError::InvalidMinorV);
+        for (i, field) in class.fields.iter().enumerate() {
+            let path = format!("fields[{}]", i);
+            let results = [
+                self.verify_member(field.name_index, field.descriptor_index, false),
+                self.verify_field_flags(field),
+            ];
+
+            for error in results.into_iter().filter_map(Result::err) {
+                diagnostics.error(path.as_str(), error);
+            }
         }
 
-        for node in &class.cp {
-            self.verify_cp_node(node)?;
+        for (i, method) in class.methods.iter().enumerate() {
+            let path = format!("methods[{}]", i);
+            let name = class.cp.utf8(method.name_index.to_u2()).unwrap_or_default();
+            let results = [
+                self.verify_member(method.name_index, method.descriptor_index, true),
+                self.verify_method_flags(method, name),
+                verify_method_code(method, name),
+            ];
+
+            for error in results.into_iter().filter_map(Result::err) {
+                diagnostics.error(path.as_str(), error);
+            }
         }
 
-        // Safety -> We have already verified the entirety of the constant pool, it is safe to use unreachable_unchecked here.
-        // TODO -> We should *really* make this less confusing | Urgency: Low.
-        let this_class = if let CpNode::Utf8(z) =
-            if let CpNode::Class(z) = &class.cp[class.this_class.to_u2() as usize - 1] {
-                &class.cp[z.name_index.to_u2() as usize - 1]
-            } else {
-                unreachable!()
-            } {
-            z.bytes
+        self.attribute_diagnostics(&mut diagnostics);
+
+        diagnostics
+    }
+
+    // Fields and methods need a name, and a descriptor that parses (JVMS §4.5, §4.6).
+    fn verify_member(
+        &self,
+        name_index: U2,
+        descriptor_index: U2,
+        method: bool,
+    ) -> Result<(), ParsingError> {
+        let cp = &self.class.cp;
+
+        cp.utf8(name_index.to_u2())?;
+        let descriptor = cp.utf8(descriptor_index.to_u2())?;
+
+        if method {
+            MethodDescriptor::parse(descriptor)?;
         } else {
-            unreachable!()
-        };
+            FieldType::parse(descriptor)?;
+        }
+
+        Ok(())
+    }
+
+    fn is_interface(&self) -> bool {
+        self.class.access_flags.to_u2() & consts::class_acc_flags::INTERFACE != 0
+    }
+
+    // The rules of JVMS §4.5 on the access_flags of fields.
+    fn verify_field_flags(&self, field: &FieldInfo) -> Result<(), ParsingError> {
+        use consts::field_acc_flags::*;
+
+        let flags = field.access_flags.to_u2();
+
+        if (flags & (PUBLIC | PRIVATE | PROTECTED)).count_ones() > 1 {
+            return Err(ParsingError::MultipleAccessModifiers);
+        }
+
+        if flags & FINAL != 0 && flags & VOLATILE != 0 {
+            return Err(ParsingError::FinalAndVolatileField);
+        }
+
+        if self.is_interface()
+            && (flags & (PUBLIC | STATIC | FINAL) != PUBLIC | STATIC | FINAL
+                || flags & !(PUBLIC | STATIC | FINAL | SYNTHETIC) != 0)
+        {
+            return Err(ParsingError::IllegalFlagsOnInterfaceField);
+        }
+
+        Ok(())
+    }
+
+    // The rules of JVMS §4.6 on the access_flags of methods, which depend on the version of the
+    // class for interfaces, abstract methods and <clinit>.
+    fn verify_method_flags(&self, method: &MethodInfo, name: &str) -> Result<(), ParsingError> {
+        use consts::method_acc_flags::*;
+
+        let flags = method.access_flags.to_u2();
+        let major_v = self.class.major_v.to_u2();
+
+        // Apart from ACC_STATIC since major version 51, the flags of <clinit> are ignored.
+        if name == "<clinit>" {
+            if major_v >= 51 && flags & STATIC == 0 {
+                return Err(ParsingError::ClinitNotStatic);
+            }
+
+            return Ok(());
+        }
+
+        if (flags & (PUBLIC | PRIVATE | PROTECTED)).count_ones() > 1 {
+            return Err(ParsingError::MultipleAccessModifiers);
+        }
+
+        if self.is_interface() {
+            let illegal = if major_v >= 52 {
+                flags & (PROTECTED | FINAL | SYNCHRONIZED | NATIVE) != 0
+                    || (flags & (PUBLIC | PRIVATE)).count_ones() != 1
+            } else {
+                flags & (PUBLIC | ABSTRACT) != PUBLIC | ABSTRACT
+                    || flags & !(PUBLIC | ABSTRACT | VARARGS | BRIDGE | SYNTHETIC) != 0
+            };
+
+            if illegal {
+                return Err(ParsingError::IllegalFlagsOnInterfaceMethod);
+            }
+        }
+
+        if flags & ABSTRACT != 0 {
+            let mut illegal = PRIVATE | STATIC | FINAL | SYNCHRONIZED | NATIVE;
+
+            // ACC_STRICT only meant something from Java 1.2 up to Java 16.
+            if (46..=60).contains(&major_v) {
+                illegal |= STRICT;
+            }
+
+            if flags & illegal != 0 {
+                return Err(ParsingError::IllegalFlagsOnAbstractMethod);
+            }
+        }
+
+        if name == "<init>"
+            && flags & !(PUBLIC | PRIVATE | PROTECTED | VARARGS | STRICT | SYNTHETIC) != 0
+        {
+            return Err(ParsingError::IllegalFlagsOnInit);
+        }
+
+        Ok(())
+    }
+
+    // The rules of JVMS §4.1 on access_flags, and the extra ones for modules.
+    fn verify_class(&self) -> Result<(), ParsingError> {
+        let class = &self.class;
+        let major_v = class.major_v.to_u2();
+        let this_class = class.cp.class_name(class.this_class.to_u2())?;
 
         let access_flags = class.access_flags.to_u2();
 
@@ -110,7 +490,7 @@ impl<'a> Verifier<'a> {
                 return Err(ParsingError::VarsNotZeroAsModule);
             }
 
-            let mut filtered = self.class.attributes.iter().filter(|z| {
+            let allowed = class.attributes.iter().all(|z| {
                 matches!(
                     z,
                     Attributes::Module(..)
@@ -121,165 +501,266 @@ impl<'a> Verifier<'a> {
                         | Attributes::SourceDebugExt(..)
                         | Attributes::RuntimeVisibleAnnotations(..)
                         | Attributes::RuntimeInvisibleAnnotations(..)
+                        | Attributes::Unknown { .. }
                 )
             });
+            let modules = class
+                .attributes
+                .iter()
+                .filter(|z| matches!(z, Attributes::Module(..)))
+                .count();
 
-            let has_module = filtered.any(|x| matches!(x, Attributes::Module(..)));
-
-            if !(filtered.next().is_none() && has_module) {
+            if !allowed || modules != 1 {
                 return Err(ParsingError::InvalidAttributesAsModule);
             }
         }
 
-        Ok(self.class)
+        Ok(())
     }
 
-    pub fn verify_class_attributes(&self) -> Result<(), ParsingError<'a>> {
-        let class_attributes = &self.class.attributes;
-        let cp = &self.class.cp;
-
-        for z in class_attributes {
-            match z {
-                Attributes::SourceFile(z) => {
-                    if !matches!(&cp[z.sourcefile_index.to_u2() as usize], CpNode::Utf8(..)) {
-                        return Err(ParsingError::InvalidIndexFromAttributeToNode(
-                            ErrorAttributes::SourceFile,
-                            CpNodeError::Utf8,
-                            "sourcefile_index",
-                        ));
-                    }
-                }
-                Attributes::InnerClass(_z) => {}
-                Attributes::EnclosingMethod(_z) => {}
-                Attributes::SourceDebugExt(_z) => {}
-                Attributes::BootstrapMethods(_z) => {}
-                Attributes::Module(_z) => {}
-                Attributes::ModulePackages(_z) => {}
-                Attributes::ModuleMainClass(_z) => {}
-                Attributes::NestHost(_z) => {}
-                Attributes::NestMembers(_z) => {}
-                Attributes::Record(_z) => {}
-                Attributes::PermittedSubclasses(_z) => {}
-                Attributes::Synthetic(_z) => {}
-                Attributes::Deprecated(_z) => {}
-                Attributes::Signature(_z) => {}
-                Attributes::RuntimeVisibleAnnotations(_z) => {}
-                Attributes::RuntimeInvisibleAnnotations(_z) => {}
-                Attributes::RuntimeVisibleTypeAnnotations(_z) => {}
-                Attributes::RuntimeInvisibleTypeAnnotations(_z) => {}
-                _ => unreachable!(),
-            }
+    pub fn verify_class_attributes(&self) -> Result<(), ParsingError> {
+        for z in &self.class.attributes {
+            self.verify_attributes_internal(z)?;
         }
 
         Ok(())
     }
 
-    pub fn verify_attributes(&self) -> Result<(), ParsingError<'a>> {
+    pub fn verify_attributes(&self) -> Result<(), ParsingError> {
+        let mut diagnostics = Diagnostics::new();
+        self.attribute_diagnostics(&mut diagnostics);
+
+        match diagnostics
+            .into_iter()
+            .find(|z| z.severity == Severity::Error)
+        {
+            Some(diagnostic) => Err(diagnostic.error),
+            None => Ok(()),
+        }
+    }
+
+    // Classes of major version 50 that fail type checking fall back to type inference (JVMS
+    // §4.10). The error reported is the one from type checking.
+    fn verify_method_types(
+        &self,
+        method: &MethodInfo,
+    ) -> Result<Vec<(String, String)>, ParsingError> {
+        let none: Vec<ClassFile> = vec![];
+        let hierarchy = self.hierarchy.unwrap_or(&none);
+
+        match self.class.major_v.to_u2() {
+            ..50 => type_infer_method(&self.class, method, hierarchy),
+            50 => type_check_method(&self.class, method, hierarchy).or_else(|error| {
+                type_infer_method(&self.class, method, hierarchy).map_err(|_| error)
+            }),
+            _ => type_check_method(&self.class, method, hierarchy),
+        }
+    }
+
+    // Checks that every attribute is allowed where it is (JVMS §4.7, table 4.7-C), and then what's
+    // inside of it.
+    fn attribute_diagnostics(&self, diagnostics: &mut Diagnostics) {
+        const CLASS: &[&str] = &[
+            "SourceFile",
+            "InnerClasses",
+            "EnclosingMethod",
+            "SourceDebugExtension",
+            "BootstrapMethods",
+            "Module",
+            "ModulePackages",
+            "ModuleMainClass",
+            "NestHost",
+            "NestMembers",
+            "Record",
+            "PermittedSubclasses",
+            "Synthetic",
+            "Deprecated",
+            "Signature",
+            "RuntimeVisibleAnnotations",
+            "RuntimeInvisibleAnnotations",
+            "RuntimeVisibleTypeAnnotations",
+            "RuntimeInvisibleTypeAnnotations",
+        ];
+        const FIELD: &[&str] = &[
+            "ConstantValue",
+            "Synthetic",
+            "Deprecated",
+            "Signature",
+            "RuntimeVisibleAnnotations",
+            "RuntimeInvisibleAnnotations",
+            "RuntimeVisibleTypeAnnotations",
+            "RuntimeInvisibleTypeAnnotations",
+        ];
+        const METHOD: &[&str] = &[
+            "Code",
+            "Exceptions",
+            "RuntimeVisibleParameterAnnotations",
+            "RuntimeInvisibleParameterAnnotations",
+            "AnnotationDefault",
+            "MethodParameters",
+            "Synthetic",
+            "Deprecated",
+            "Signature",
+            "RuntimeVisibleAnnotations",
+            "RuntimeInvisibleAnnotations",
+            "RuntimeVisibleTypeAnnotations",
+            "RuntimeInvisibleTypeAnnotations",
+        ];
+        const CODE: &[&str] = &[
+            "LineNumberTable",
+            "LocalVariableTable",
+            "LocalVariableTypeTable",
+            "StackMapTable",
+            "RuntimeVisibleTypeAnnotations",
+            "RuntimeInvisibleTypeAnnotations",
+        ];
+        const RECORD_COMPONENT: &[&str] = &[
+            "Signature",
+            "RuntimeVisibleAnnotations",
+            "RuntimeInvisibleAnnotations",
+            "RuntimeVisibleTypeAnnotations",
+            "RuntimeInvisibleTypeAnnotations",
+        ];
+
         for z in &self.class.attributes {
-            if !matches!(
-                z,
-                Attributes::SourceFile(..)
-                    | Attributes::InnerClass(..)
-                    | Attributes::EnclosingMethod(..)
-                    | Attributes::SourceDebugExt(..)
-                    | Attributes::BootstrapMethods(..)
-                    | Attributes::Module(..)
-                    | Attributes::ModulePackages(..)
-                    | Attributes::ModuleMainClass(..)
-                    | Attributes::NestHost(..)
-                    | Attributes::NestMembers(..)
-                    | Attributes::Record(..)
-                    | Attributes::PermittedSubclasses(..)
-                    | Attributes::Synthetic(..)
-                    | Attributes::Deprecated(..)
-                    | Attributes::Signature(..)
-                    | Attributes::RuntimeVisibleAnnotations(..)
-                    | Attributes::RuntimeInvisibleAnnotations(..)
-                    | Attributes::RuntimeVisibleTypeAnnotations(..)
-                    | Attributes::RuntimeInvisibleTypeAnnotations(..)
-            ) {
-                return Err(ParsingError::InvalidClassAttributes);
+            let path = format!("attributes[{}]", z.name());
+
+            if self.attribute_allowed(diagnostics, &path, z, CLASS, || {
+                ParsingError::InvalidClassAttributes
+            }) {
+                self.attribute_internal(diagnostics, &path, z);
             }
 
             if let Attributes::Record(z) = z {
-                for z in &z.components {
+                for (i, z) in z.components.iter().enumerate() {
                     for z in &z.attributes {
-                        if !matches!(
-                            z,
-                            Attributes::Signature(..)
-                                | Attributes::RuntimeVisibleAnnotations(..)
-                                | Attributes::RuntimeInvisibleAnnotations(..)
-                                | Attributes::RuntimeVisibleTypeAnnotations(..)
-                                | Attributes::RuntimeInvisibleTypeAnnotations(..)
-                        ) {
-                            return Err(ParsingError::InvalidRecordComponentInfoAttributes);
+                        let path = format!("{}.components[{}].attributes[{}]", path, i, z.name());
+
+                        if self.attribute_allowed(diagnostics, &path, z, RECORD_COMPONENT, || {
+                            ParsingError::InvalidRecordComponentInfoAttributes
+                        }) {
+                            self.attribute_internal(diagnostics, &path, z);
                         }
                     }
                 }
             }
         }
 
-        for z in &self.class.fields {
+        for (i, z) in self.class.fields.iter().enumerate() {
             for z in &z.attributes {
-                if !matches!(
-                    z,
-                    Attributes::Value(..)
-                        | Attributes::Synthetic(..)
-                        | Attributes::Deprecated(..)
-                        | Attributes::Signature(..)
-                        | Attributes::RuntimeVisibleAnnotations(..)
-                        | Attributes::RuntimeInvisibleAnnotations(..)
-                        | Attributes::RuntimeVisibleTypeAnnotations(..)
-                        | Attributes::RuntimeInvisibleTypeAnnotations(..)
-                ) {
-                    return Err(ParsingError::InvalidFieldInfoAttributes);
+                let path = format!("fields[{}].attributes[{}]", i, z.name());
+
+                if self.attribute_allowed(diagnostics, &path, z, FIELD, || {
+                    ParsingError::InvalidFieldInfoAttributes
+                }) {
+                    self.attribute_internal(diagnostics, &path, z);
                 }
             }
         }
 
-        for z in &self.class.methods {
-            for z in &z.attributes {
-                if !matches!(
-                    z,
-                    Attributes::Code(..)
-                        | Attributes::Exceptions(..)
-                        | Attributes::RuntimeVisibleAnnotations(..)
-                        | Attributes::RuntimeVisibleTypeAnnotations(..)
-                        | Attributes::RuntimeInvisibleAnnotations(..)
-                        | Attributes::RuntimeInvisibleTypeAnnotations(..)
-                        | Attributes::RuntimeVisibleParameterAnnotations(..)
-                        | Attributes::RuntimeInvisibleParameterAnnotations(..)
-                        | Attributes::AnnotationDefault(..)
-                        | Attributes::MethodParameters(..)
-                        | Attributes::Synthetic(..)
-                        | Attributes::Deprecated(..)
-                        | Attributes::Signature(..)
-                ) {
-                    return Err(ParsingError::InvalidMethodInfoAttributes);
-                }
-
-                if let Attributes::Code(z) = z {
-                    for z in &z.attributes {
-                        if !matches!(
-                            z,
-                            Attributes::LineNumberTable(..)
-                                | Attributes::LocalVariableTable(..)
-                                | Attributes::LocalVariableTypeTable(..)
-                                | Attributes::StackMapTable(..)
-                                | Attributes::RuntimeVisibleTypeAnnotations(..)
-                                | Attributes::RuntimeInvisibleTypeAnnotations(..)
-                        ) {
-                            return Err(ParsingError::InvalidCodeAttributes);
+        for (i, method) in self.class.methods.iter().enumerate() {
+            for z in &method.attributes {
+                let path = format!("methods[{}].attributes[{}]", i, z.name());
+
+                if !self.attribute_allowed(diagnostics, &path, z, METHOD, || {
+                    ParsingError::InvalidMethodInfoAttributes
+                }) {
+                    continue;
+                }
+
+                self.attribute_internal(diagnostics, &path, z);
+
+                if let Attributes::Code(code) = z {
+                    let starts = instruction_starts(code);
+
+                    if let Err(error) = verify_code_ranges(code, starts.as_ref()) {
+                        diagnostics.error(path.as_str(), error);
+                    }
+
+                    if self.level == VerifyLevel::Full {
+                        if let Some(error) = code.instructions().find_map(Result::err) {
+                            diagnostics.error(path.as_str(), error);
+                        } else {
+                            match self.verify_method_types(method) {
+                                Ok(assumed) => {
+                                    for (from, to) in assumed {
+                                        diagnostics.warning(
+                                            path.as_str(),
+                                            ParsingError::AssumedAssignable { from, to },
+                                        );
+                                    }
+                                }
+                                Err(error) => diagnostics.error(path.as_str(), error),
+                            }
+                        }
+                    }
+
+                    for z in &code.attributes {
+                        let path = format!("{}.attributes[{}]", path, z.name());
+
+                        if self.attribute_allowed(diagnostics, &path, z, CODE, || {
+                            ParsingError::InvalidCodeAttributes
+                        }) {
+                            self.attribute_internal(diagnostics, &path, z);
+
+                            if let Err(error) = verify_code_pcs(code, starts.as_ref(), z) {
+                                diagnostics.error(path.as_str(), error);
+                            }
                         }
                     }
                 }
             }
         }
+    }
 
-        // -----------------------------------------------------------------------------------------
+    // Unknown attributes are only warned about, as the JVM silently ignores them. Known ones in the
+    // wrong place are an error.
+    fn attribute_allowed(
+        &self,
+        diagnostics: &mut Diagnostics,
+        path: &str,
+        attribute: &Attributes,
+        allowed: &[&str],
+        error: impl FnOnce() -> ParsingError,
+    ) -> bool {
+        match attribute {
+            Attributes::Unknown { name, .. } => {
+                diagnostics.warning(path, ParsingError::UnknownAttribute(name.to_string()));
+                false
+            }
+            attribute if allowed.contains(&attribute.name()) => true,
+            _ => {
+                diagnostics.error(path, error());
+                false
+            }
+        }
+    }
 
-        self.verify_class_attributes()?;
-        Ok(())
+    // The attributes of a Code attribute or of record components are checked on their own, so they
+    // each get their path.
+    fn attribute_internal(
+        &self,
+        diagnostics: &mut Diagnostics,
+        path: &str,
+        attribute: &Attributes,
+    ) {
+        use CpNodeError as N;
+        use ErrorAttributes as A;
+
+        let result = match attribute {
+            Attributes::Code(code) => code.exception_table.iter().try_for_each(|z| {
+                self.expect_optional(A::Code, "catch_type", z.catch_type, N::Class)
+            }),
+            Attributes::Record(record) => record.components.iter().try_for_each(|z| {
+                self.expect(A::Record, "name_index", z.name_index, N::Utf8)?;
+                self.expect(A::Record, "descriptor_index", z.descriptor_index, N::Utf8)
+            }),
+            attribute => self.verify_attributes_internal(attribute),
+        };
+
+        if let Err(error) = result {
+            diagnostics.error(path, error);
+        }
     }
 
     pub fn verify_cp(&self) -> Result<(), ParsingError> {
@@ -290,200 +771,488 @@ impl<'a> Verifier<'a> {
         Ok(())
     }
 
-    pub fn verify_attributes_internal(
+    // Checks that every constant pool index inside of `attribute` points to the kind of node it
+    // should (JVMS §4.7). Indexes that may be 0 are only checked when they aren't.
+    pub fn verify_attributes_internal(&self, attribute: &Attributes) -> Result<(), ParsingError> {
+        use CpNodeError as N;
+        use ErrorAttributes as A;
+
+        const CONSTANT_VALUE: &[CpNodeError] =
+            &[N::Integer, N::Float, N::Long, N::Double, N::String];
+        const LOADABLE: &[CpNodeError] = &[
+            N::Integer,
+            N::Float,
+            N::Long,
+            N::Double,
+            N::Class,
+            N::String,
+            N::MethodHandle,
+            N::MethodType,
+            N::Dynamic,
+        ];
+
+        match attribute {
+            Attributes::Value(z) => {
+                self.expect_any(
+                    A::Value,
+                    "constantvalue_index",
+                    z.value_index,
+                    CONSTANT_VALUE,
+                )?;
+            }
+
+            Attributes::Code(z) => {
+                for entry in &z.exception_table {
+                    self.expect_optional(A::Code, "catch_type", entry.catch_type, N::Class)?;
+                }
+
+                for attribute in &z.attributes {
+                    self.verify_attributes_internal(attribute)?;
+                }
+            }
+
+            Attributes::StackMapTable(z) => {
+                for frame in &z.entries {
+                    let types: Vec<&VerificationTypeInfo> = match frame {
+                        StackMapFrame::SameLocals1StackItemFrame(z) => vec![&z.stack],
+                        StackMapFrame::SameLocals1StackItemFrameExtended(z) => vec![&z.stack],
+                        StackMapFrame::AppendFrame(z) => z.locals.iter().collect(),
+                        StackMapFrame::FullFrame(z) => z.locals.iter().chain(&z.stack).collect(),
+                        _ => vec![],
+                    };
+
+                    for z in types {
+                        if let VerificationTypeInfo::ObjectVariableInfo(z) = z {
+                            self.expect(A::StackMapTable, "cpool_index", z.cp_index, N::Class)?;
+                        }
+                    }
+                }
+            }
+
+            Attributes::Exceptions(z) => {
+                for index in z.exception_index_table {
+                    self.expect(A::Exceptions, "exception_index_table", *index, N::Class)?;
+                }
+            }
+
+            Attributes::InnerClass(z) => {
+                for z in &z.classes {
+                    self.expect(
+                        A::InnerClass,
+                        "inner_class_info_index",
+                        z.inner_class_info_index,
+                        N::Class,
+                    )?;
+                    self.expect_optional(
+                        A::InnerClass,
+                        "outer_class_info_index",
+                        z.outer_class_info_index,
+                        N::Class,
+                    )?;
+                    self.expect_optional(
+                        A::InnerClass,
+                        "inner_name_index",
+                        z.inner_name_index,
+                        N::Utf8,
+                    )?;
+                }
+            }
+
+            Attributes::EnclosingMethod(z) => {
+                self.expect(A::EnclosingMethod, "class_index", z.class_index, N::Class)?;
+                self.expect_optional(
+                    A::EnclosingMethod,
+                    "method_index",
+                    z.method_index,
+                    N::NameAndType,
+                )?;
+            }
+
+            Attributes::Signature(z) => {
+                self.expect(A::Signature, "signature_index", z.signature_index, N::Utf8)?;
+            }
+
+            Attributes::SourceFile(z) => {
+                self.expect(
+                    A::SourceFile,
+                    "sourcefile_index",
+                    z.sourcefile_index,
+                    N::Utf8,
+                )?;
+            }
+
+            Attributes::LocalVariableTable(z) => {
+                for z in &z.local_variable_table {
+                    self.expect(A::LocalVariableTable, "name_index", z.name_index, N::Utf8)?;
+                    self.expect(
+                        A::LocalVariableTable,
+                        "descriptor_index",
+                        z.descriptor_index,
+                        N::Utf8,
+                    )?;
+                }
+            }
+
+            Attributes::LocalVariableTypeTable(z) => {
+                for z in &z.local_variable_type_table {
+                    self.expect(
+                        A::LocalVariableTypeTable,
+                        "name_index",
+                        z.name_index,
+                        N::Utf8,
+                    )?;
+                    self.expect(
+                        A::LocalVariableTypeTable,
+                        "signature_index",
+                        z.signature_index,
+                        N::Utf8,
+                    )?;
+                }
+            }
+
+            Attributes::RuntimeVisibleAnnotations(z) => {
+                for z in &z.annotations {
+                    self.verify_annotation(A::RuntimeVisibleAnnotations, z)?;
+                }
+            }
+
+            Attributes::RuntimeInvisibleAnnotations(z) => {
+                for z in &z.annotations {
+                    self.verify_annotation(A::RuntimeInvisibleAnnotations, z)?;
+                }
+            }
+
+            Attributes::RuntimeVisibleParameterAnnotations(z) => {
+                for z in z.parameter_annotations.iter().flat_map(|z| &z.annotations) {
+                    self.verify_annotation(A::RuntimeVisibleParameterAnnotations, z)?;
+                }
+            }
+
+            Attributes::RuntimeInvisibleParameterAnnotations(z) => {
+                for z in z.parameter_annotations.iter().flat_map(|z| &z.annotations) {
+                    self.verify_annotation(A::RuntimeInvisibleParameterAnnotations, z)?;
+                }
+            }
+
+            Attributes::RuntimeVisibleTypeAnnotations(z) => {
+                for z in &z.type_annotation {
+                    self.verify_type_annotation(A::RuntimeVisibleTypeAnnotations, z)?;
+                }
+            }
+
+            Attributes::RuntimeInvisibleTypeAnnotations(z) => {
+                for z in &z.annotations {
+                    self.verify_type_annotation(A::RuntimeInvisibleTypeAnnotations, z)?;
+                }
+            }
+
+            Attributes::AnnotationDefault(z) => {
+                self.verify_element_value(A::AnnotationDefault, &z.default_value)?;
+            }
+
+            Attributes::BootstrapMethods(z) => {
+                for z in &z.bootstrap_methods {
+                    self.expect(
+                        A::BootstrapMethods,
+                        "bootstrap_method_ref",
+                        z.bootstrap_method_ref,
+                        N::MethodHandle,
+                    )?;
+
+                    for argument in z.bootstrap_arguments {
+                        self.expect_any(
+                            A::BootstrapMethods,
+                            "bootstrap_arguments",
+                            *argument,
+                            LOADABLE,
+                        )?;
+                    }
+                }
+            }
+
+            Attributes::MethodParameters(z) => {
+                for z in &z.parameters {
+                    self.expect_optional(A::MethodParameters, "name_index", z.name_index, N::Utf8)?;
+                }
+            }
+
+            Attributes::Module(z) => {
+                self.expect(
+                    A::Module,
+                    "module_name_index",
+                    z.module_name_index,
+                    N::Module,
+                )?;
+                self.expect_optional(
+                    A::Module,
+                    "module_version_index",
+                    z.module_version_index,
+                    N::Utf8,
+                )?;
+
+                for z in &z.requires {
+                    self.expect(A::Module, "requires_index", z.requires_index, N::Module)?;
+                    self.expect_optional(
+                        A::Module,
+                        "requires_version_index",
+                        z.require_version_index,
+                        N::Utf8,
+                    )?;
+                }
+
+                for z in &z.exports {
+                    self.expect(A::Module, "exports_index", z.exports_index, N::Package)?;
+
+                    for index in z.exports_to_index {
+                        self.expect(A::Module, "exports_to_index", *index, N::Module)?;
+                    }
+                }
+
+                for z in &z.opens {
+                    self.expect(A::Module, "opens_index", z.opens_index, N::Package)?;
+
+                    for index in z.opens_to_index {
+                        self.expect(A::Module, "opens_to_index", *index, N::Module)?;
+                    }
+                }
+
+                for index in z.uses_index {
+                    self.expect(A::Module, "uses_index", *index, N::Class)?;
+                }
+
+                for z in &z.provides {
+                    self.expect(A::Module, "provides_index", z.provides_index, N::Class)?;
+
+                    for index in z.provides_with_index {
+                        self.expect(A::Module, "provides_with_index", *index, N::Class)?;
+                    }
+                }
+            }
+
+            Attributes::ModulePackages(z) => {
+                for index in z.package_index {
+                    self.expect(A::ModulePackages, "package_index", *index, N::Package)?;
+                }
+            }
+
+            Attributes::ModuleMainClass(z) => {
+                self.expect(
+                    A::ModuleMainClass,
+                    "main_class_index",
+                    z.main_class_index,
+                    N::Class,
+                )?;
+            }
+
+            Attributes::NestHost(z) => {
+                self.expect(
+                    A::NestHost,
+                    "host_class_index",
+                    z.host_class_index,
+                    N::Class,
+                )?;
+            }
+
+            Attributes::NestMembers(z) => {
+                for index in z.classes {
+                    self.expect(A::NestMembers, "classes", *index, N::Class)?;
+                }
+            }
+
+            Attributes::PermittedSubclasses(z) => {
+                for index in z.classes {
+                    self.expect(A::PermittedSubclasses, "classes", *index, N::Class)?;
+                }
+            }
+
+            Attributes::Record(z) => {
+                for z in &z.components {
+                    self.expect(A::Record, "name_index", z.name_index, N::Utf8)?;
+                    self.expect(A::Record, "descriptor_index", z.descriptor_index, N::Utf8)?;
+
+                    for attribute in &z.attributes {
+                        self.verify_attributes_internal(attribute)?;
+                    }
+                }
+            }
+
+            // Nothing in these points into the constant pool.
+            Attributes::LineNumberTable(_)
+            | Attributes::SourceDebugExt(_)
+            | Attributes::Synthetic(_)
+            | Attributes::Deprecated(_)
+            | Attributes::Unknown { .. } => {}
+        }
+
+        Ok(())
+    }
+
+    fn verify_annotation(
         &self,
-        _attribute: Attributes,
-    ) -> Result<(), ParsingError<'a>> {
-        let _cp = &self.class.cp;
+        attribute: ErrorAttributes,
+        annotation: &Annotation,
+    ) -> Result<(), ParsingError> {
+        self.expect(
+            attribute,
+            "type_index",
+            annotation.type_index,
+            CpNodeError::Utf8,
+        )?;
+
+        for pair in &annotation.element_value_pairs {
+            self.expect(
+                attribute,
+                "element_name_index",
+                pair.element_name_index,
+                CpNodeError::Utf8,
+            )?;
+            self.verify_element_value(attribute, &pair.value)?;
+        }
+
+        Ok(())
+    }
+
+    fn verify_type_annotation(
+        &self,
+        attribute: ErrorAttributes,
+        annotation: &TypeAnnotation,
+    ) -> Result<(), ParsingError> {
+        self.expect(
+            attribute,
+            "type_index",
+            annotation.type_index,
+            CpNodeError::Utf8,
+        )?;
+
+        for pair in &annotation.element_value_pairs {
+            self.expect(
+                attribute,
+                "element_name_index",
+                pair.element_name_index,
+                CpNodeError::Utf8,
+            )?;
+            self.verify_element_value(attribute, &pair.value)?;
+        }
+
         Ok(())
+    }
+
+    fn verify_element_value(
+        &self,
+        attribute: ErrorAttributes,
+        value: &ElementValue,
+    ) -> Result<(), ParsingError> {
+        match value {
+            ElementValue::ConstValueIndex(z) => {
+                let expected = match z.tag {
+                    b'B' | b'C' | b'I' | b'S' | b'Z' => CpNodeError::Integer,
+                    b'J' => CpNodeError::Long,
+                    b'F' => CpNodeError::Float,
+                    b'D' => CpNodeError::Double,
+                    _ => CpNodeError::Utf8,
+                };
+
+                self.expect(
+                    attribute,
+                    "const_value_index",
+                    z.const_value_index,
+                    expected,
+                )
+            }
+            ElementValue::EnumConstValue(z) => {
+                self.expect(
+                    attribute,
+                    "type_name_index",
+                    z.type_name_index,
+                    CpNodeError::Utf8,
+                )?;
+                self.expect(
+                    attribute,
+                    "const_name_index",
+                    z.const_name_index,
+                    CpNodeError::Utf8,
+                )
+            }
+            ElementValue::ClassInfoIndex(index) => {
+                self.expect(attribute, "class_info_index", *index, CpNodeError::Utf8)
+            }
+            ElementValue::AnnotationValue(z) => self.verify_annotation(attribute, z),
+            ElementValue::ArrayValue(z) => {
+                for value in &z.element_value {
+                    self.verify_element_value(attribute, value)?;
+                }
 
-        // match attribute {
-        //     Attributes::Value(cv) => {
-        //         if !matches!(
-        //             cp[cv.value_index.to_u2() as usize],
-        //             CpNode::Integer(_)
-        //                 | CpNode::Float(_)
-        //                 | CpNode::Long(_)
-        //                 | CpNode::Double(_)
-        //                 | CpNode::String(_)
-        //         ) {
-        //             return Err(ParsingError::InvalidIndexFromAttributeToNodes(
-        //                 ErrorAttributes::Value,
-        //                 &[
-        //                     CpNodeError::Integer,
-        //                     CpNodeError::Float,
-        //                     CpNodeError::Long,
-        //                     CpNodeError::Double,
-        //                     CpNodeError::String,
-        //                 ],
-        //                 "value_index",
-        //             ));
-        //         }
-        //     }
-        //     Attributes::Code(code) => {
-        //         let code_arr = code.code;
-
-        //         if code_arr.len() >= 65536 || code_arr.is_empty() {
-        //             return Err(ParsingError::CodeAttributeCodeLength);
-        //         }
-
-        //         for y in &code.exception_table {
-        //             if code_arr.get(y.start_pc.to_u2() as usize).is_none()
-        //                 || code_arr.get(y.end_pc.to_u2() as usize).is_none()
-        //                 || code_arr.get(y.handler_pc.to_u2() as usize).is_none()
-        //             {
-        //                 return Err(ParsingError::InvalidIndexesInCodeAttribute);
-        //             }
-
-        //             if y.catch_type.to_u2() != 0
-        //                 && !matches!(cp[y.catch_type.to_u2() as usize], CpNode::Class(..))
-        //             {
-        //                 return Err(ParsingError::InvalidIndexFromAttributeToNode(
-        //                     ErrorAttributes::Code,
-        //                     CpNodeError::Class,
-        //                     "catch_type",
-        //                 ));
-        //             }
-        //         }
-
-        //         for attr in code.attributes {
-        //             self.verify_attributes(attr)?;
-        //         }
-        //     }
-        //     Attributes::StackMapTable(smt) => {
-        //         for smf in smt.entries {
-        //             match smf {
-        //                 StackMapFrame::SameLocals1StackItemFrame(z) => {
-        //                     if let VerificationTypeInfo::ObjectVariableInfo(y) = z.stack {
-        //                         if !matches!(cp[y.cp_index.to_u2() as usize], CpNode::Class(..)) {
-        //                             return Err(ParsingError::InvalidIndexFromAttributeToNode(
-        //                                 ErrorAttributes::StackMapTable,
-        //                                 CpNodeError::Class,
-        //                                 "cp_index",
-        //                             ));
-        //                         }
-        //                     }
-        //                 }
-        //                 StackMapFrame::SameLocals1StackItemFrameExtended(z) => {
-        //                     if let VerificationTypeInfo::ObjectVariableInfo(y) = z.stack {
-        //                         if !matches!(cp[y.cp_index.to_u2() as usize], CpNode::Class(..)) {
-        //                             return Err(ParsingError::InvalidIndexFromAttributeToNode(
-        //                                 ErrorAttributes::StackMapTable,
-        //                                 CpNodeError::Class,
-        //                                 "cp_index",
-        //                             ));
-        //                         }
-        //                     }
-        //                 }
-        //                 StackMapFrame::AppendFrame(z) => {
-        //                     for i in z.locals {
-        //                         if let VerificationTypeInfo::ObjectVariableInfo(y) = i {
-        //                             if !matches!(cp[y.cp_index.to_u2() as usize], CpNode::Class(..))
-        //                             {
-        //                                 return Err(ParsingError::InvalidIndexFromAttributeToNode(
-        //                                     ErrorAttributes::StackMapTable,
-        //                                     CpNodeError::Class,
-        //                                     "cp_index",
-        //                                 ));
-        //                             }
-        //                         }
-        //                     }
-        //                 }
-        //                 StackMapFrame::FullFrame(z) => {
-        //                     for i in z.locals {
-        //                         if let VerificationTypeInfo::ObjectVariableInfo(y) = i {
-        //                             if !matches!(cp[y.cp_index.to_u2() as usize], CpNode::Class(..))
-        //                             {
-        //                                 return Err(ParsingError::InvalidIndexFromAttributeToNode(
-        //                                     ErrorAttributes::StackMapTable,
-        //                                     CpNodeError::Class,
-        //                                     "cp_index",
-        //                                 ));
-        //                             }
-        //                         }
-        //                     }
-        //                     for i in z.stack {
-        //                         if let VerificationTypeInfo::ObjectVariableInfo(y) = i {
-        //                             if !matches!(cp[y.cp_index.to_u2() as usize], CpNode::Class(..))
-        //                             {
-        //                                 return Err(ParsingError::InvalidIndexFromAttributeToNode(
-        //                                     ErrorAttributes::StackMapTable,
-        //                                     CpNodeError::Class,
-        //                                     "cp_index",
-        //                                 ));
-        //                             }
-        //                         }
-        //                     }
-        //                 }
-        //                 _ => {}
-        //             }
-        //         }
-        //     }
-        //     Attributes::Exceptions(exception) => {
-        //         for i in exception.exception_index_table {
-        //             if !matches!(cp[i.to_u2() as usize], CpNode::Class(..)) {
-        //                 return Err(ParsingError::InvalidIndexFromAttributeToNode(
-        //                     ErrorAttributes::Exceptions,
-        //                     CpNodeError::Class,
-        //                     "exception_index_table",
-        //                 ));
-        //             }
-        //         }
-        //     }
-        //     Attributes::InnerClass(innerclass) => {
-        //         for k in innerclass.classes {
-        //             if let CpNode::Class(y) = &cp[k.inner_class_info_index.to_u2() as usize] {
-        //                 #[allow(non_snake_case)]
-        //                 let C = y;
-        //             } else {
-        //                 return Err(ParsingError::InvalidIndexFromAttributeToNode(
-        //                     ErrorAttributes::InnerClass,
-        //                     CpNodeError::Class,
-        //                     "inner_class_info_index",
-        //                 ));
-        //             }
-        //         }
-        //     }
-        //     Attributes::EnclosingMethod(enclosingmethod) => {}
-        //     Attributes::Synthetic(_) => todo!(),
-        //     Attributes::Signature(_) => todo!(),
-        //     Attributes::SourceFile(_) => todo!(),
-        //     Attributes::SourceDebugExt(_) => todo!(),
-        //     Attributes::LineNumberTable(_) => todo!(),
-        //     Attributes::LocalVariableTable(_) => todo!(),
-        //     Attributes::LocalVariableTypeTable(_) => todo!(),
-        //     Attributes::Deprecated(_) => todo!(),
-        //     Attributes::RuntimeVisibleAnnotations(_) => todo!(),
-        //     Attributes::RuntimeInvisibleAnnotations(_) => todo!(),
-        //     Attributes::RuntimeVisibleParameterAnnotations(_) => todo!(),
-        //     Attributes::RuntimeInvisibleParameterAnnotations(_) => todo!(),
-        //     Attributes::RuntimeVisibleTypeAnnotations(_) => todo!(),
-        //     Attributes::RuntimeInvisibleTypeAnnotations(_) => todo!(),
-        //     Attributes::AnnotationDefault(_) => todo!(),
-        //     Attributes::BootstrapMethods(_) => todo!(),
-        //     Attributes::MethodParameters(_) => todo!(),
-        //     Attributes::Module(_) => todo!(),
-        //     Attributes::ModulePackages(_) => todo!(),
-        //     Attributes::ModuleMainClass(_) => todo!(),
-        //     Attributes::NestHost(_) => todo!(),
-        //     Attributes::NestMembers(_) => todo!(),
-        //     Attributes::Record(_) => todo!(),
-        //     Attributes::PermittedSubclasses(_) => todo!(),
-        // }
-
-        // Ok(())
-    }
-
-    fn verify_cp_node(&self, node: &CpNode) -> Result<(), ParsingError<'a>> {
+                Ok(())
+            }
+        }
+    }
+
+    fn expect(
+        &self,
+        attribute: ErrorAttributes,
+        field: &'static str,
+        index: U2,
+        expected: CpNodeError,
+    ) -> Result<(), ParsingError> {
+        match self.class.cp.get(index.to_u2()) {
+            Some(node) if node.kind() == expected => Ok(()),
+            _ => Err(ParsingError::InvalidIndexFromAttributeToNode(
+                attribute, expected, field,
+            )),
+        }
+    }
+
+    fn expect_optional(
+        &self,
+        attribute: ErrorAttributes,
+        field: &'static str,
+        index: U2,
+        expected: CpNodeError,
+    ) -> Result<(), ParsingError> {
+        if index.to_u2() == 0 {
+            return Ok(());
+        }
+
+        self.expect(attribute, field, index, expected)
+    }
+
+    fn expect_any(
+        &self,
+        attribute: ErrorAttributes,
+        field: &'static str,
+        index: U2,
+        expected: &'static [CpNodeError],
+    ) -> Result<(), ParsingError> {
+        match self.class.cp.get(index.to_u2()) {
+            Some(node) if expected.contains(&node.kind()) => Ok(()),
+            _ => Err(ParsingError::InvalidIndexFromAttributeToNodes(
+                attribute, expected, field,
+            )),
+        }
+    }
+
+    // The BootstrapMethods attribute, which there must be exactly one of when the constant pool
+    // has Dynamic or InvokeDynamic nodes.
+    fn bootstrap_methods(&self) -> Result<&BootStrapMethods<'_>, ParsingError> {
+        let mut tables = self.class.attributes.iter().filter_map(|z| match z {
+            Attributes::BootstrapMethods(z) => Some(z),
+            _ => None,
+        });
+
+        match (tables.next(), tables.next()) {
+            (Some(z), None) => Ok(z),
+            _ => Err(ParsingError::InvalidAmountOfBootStrapMethodsInClass),
+        }
+    }
+
+    fn verify_cp_node(&self, node: &CpNode) -> Result<(), ParsingError> {
         let cp = &self.class.cp;
-        let bootstrap_methods = &self.bootstrap_methods;
 
         match node {
             CpNode::Class(class) => {
-                let node = &cp[class.name_index.to_u2() as usize - 1];
-                if let CpNode::Utf8(string) = node {
+                let node = cp.get(class.name_index.to_u2());
+                if let Some(CpNode::Utf8(string)) = node {
                     string.verify_binary_class_or_interface_name()?;
-                    self.verify_cp_node(node)?;
                 } else {
                     return Err(ParsingError::InvalidIndexFromNodeToNode(
                         CpNodeError::Class,
@@ -497,8 +1266,8 @@ impl<'a> Verifier<'a> {
                 let class_index = fieldref.class_index;
                 let name_and_type = fieldref.name_and_type_index;
 
-                let node = &cp[class_index.to_u2() as usize - 1];
-                if let CpNode::Class(_) = node {
+                let node = cp.get(class_index.to_u2());
+                if let Some(node @ CpNode::Class(_)) = node {
                     self.verify_cp_node(node)?
                 } else {
                     return Err(ParsingError::InvalidIndexFromNodeToNode(
@@ -508,8 +1277,8 @@ impl<'a> Verifier<'a> {
                     ));
                 };
 
-                let node = &cp[name_and_type.to_u2() as usize - 1];
-                if let CpNode::NameAndType(_) = node {
+                let node = cp.get(name_and_type.to_u2());
+                if let Some(node @ CpNode::NameAndType(_)) = node {
                     self.verify_cp_node(node)?
                 } else {
                     return Err(ParsingError::InvalidIndexFromNodeToNode(
@@ -524,8 +1293,8 @@ impl<'a> Verifier<'a> {
                 let class_index = methodref.class_index;
                 let name_and_type = methodref.name_and_type_index;
 
-                let node = &cp[class_index.to_u2() as usize - 1];
-                if let CpNode::Class(_) = node {
+                let node = cp.get(class_index.to_u2());
+                if let Some(node @ CpNode::Class(_)) = node {
                     self.verify_cp_node(node)?
                 } else {
                     return Err(ParsingError::InvalidIndexFromNodeToNode(
@@ -535,8 +1304,8 @@ impl<'a> Verifier<'a> {
                     ));
                 };
 
-                let node = &cp[name_and_type.to_u2() as usize - 1];
-                if let CpNode::NameAndType(_) = node {
+                let node = cp.get(name_and_type.to_u2());
+                if let Some(node @ CpNode::NameAndType(_)) = node {
                     self.verify_cp_node(node)?
                 } else {
                     return Err(ParsingError::InvalidIndexFromNodeToNode(
@@ -551,8 +1320,8 @@ impl<'a> Verifier<'a> {
                 let class_index = interfacemethodref.class_index;
                 let name_and_type = interfacemethodref.name_and_type_index;
 
-                let node = &cp[class_index.to_u2() as usize - 1];
-                if let CpNode::Class(_) = node {
+                let node = cp.get(class_index.to_u2());
+                if let Some(node @ CpNode::Class(_)) = node {
                     self.verify_cp_node(node)?
                 } else {
                     return Err(ParsingError::InvalidIndexFromNodeToNode(
@@ -562,8 +1331,8 @@ impl<'a> Verifier<'a> {
                     ));
                 };
 
-                let node = &cp[name_and_type.to_u2() as usize - 1];
-                if let CpNode::NameAndType(_) = node {
+                let node = cp.get(name_and_type.to_u2());
+                if let Some(node @ CpNode::NameAndType(_)) = node {
                     self.verify_cp_node(node)?
                 } else {
                     return Err(ParsingError::InvalidIndexFromNodeToNode(
@@ -575,8 +1344,8 @@ impl<'a> Verifier<'a> {
             }
 
             CpNode::String(string) => {
-                let node = &cp[string.string_index.to_u2() as usize - 1];
-                if let CpNode::Utf8(_) = node {
+                let node = cp.get(string.string_index.to_u2());
+                if let Some(node @ CpNode::Utf8(_)) = node {
                     self.verify_cp_node(node)?
                 } else {
                     return Err(ParsingError::InvalidIndexFromNodeToNode(
@@ -588,8 +1357,8 @@ impl<'a> Verifier<'a> {
             }
 
             CpNode::MethodType(methodtype) => {
-                let node = &cp[methodtype.descriptor_index.to_u2() as usize - 1];
-                if let CpNode::Utf8(_) = node {
+                let node = cp.get(methodtype.descriptor_index.to_u2());
+                if let Some(node @ CpNode::Utf8(_)) = node {
                     self.verify_cp_node(node)?
                 } else {
                     return Err(ParsingError::InvalidIndexFromNodeToNode(
@@ -601,8 +1370,8 @@ impl<'a> Verifier<'a> {
             }
 
             CpNode::Module(module) => {
-                let node = &cp[module.name_index.to_u2() as usize - 1];
-                if let CpNode::Utf8(_) = node {
+                let node = cp.get(module.name_index.to_u2());
+                if let Some(node @ CpNode::Utf8(_)) = node {
                     self.verify_cp_node(node)?
                 } else {
                     return Err(ParsingError::InvalidIndexFromNodeToNode(
@@ -614,8 +1383,8 @@ impl<'a> Verifier<'a> {
             }
 
             CpNode::Package(package) => {
-                let package = &cp[package.name_index.to_u2() as usize - 1];
-                if let CpNode::Utf8(_) = package {
+                let package = cp.get(package.name_index.to_u2());
+                if let Some(node @ CpNode::Utf8(_)) = package {
                     self.verify_cp_node(node)?
                 } else {
                     return Err(ParsingError::InvalidIndexFromNodeToNode(
@@ -629,18 +1398,11 @@ impl<'a> Verifier<'a> {
             CpNode::Dynamic(dynamic) => {
                 let bootstrap_method_attr_index = dynamic.bootstrap_method_attr_index.to_u2();
 
-                if bootstrap_methods.len() != 1 {
-                    return Err(ParsingError::InvalidAmountOfBootStrapMethodsInClass);
-                } else if if let Attributes::BootstrapMethods(z) =
-                    &self.class.attributes[bootstrap_methods[0]]
-                {
-                    z
-                } else {
-                    unreachable!()
-                }
-                .bootstrap_methods
-                .get(bootstrap_method_attr_index as usize)
-                .is_none()
+                if self
+                    .bootstrap_methods()?
+                    .bootstrap_methods
+                    .get(bootstrap_method_attr_index as usize)
+                    .is_none()
                 {
                     return Err(
                         ParsingError::BootstrapMethodAttrIndexInDynamicAttributeIsNotValidIndex,
@@ -648,8 +1410,8 @@ impl<'a> Verifier<'a> {
                 }
 
                 let name_and_type_index = dynamic.name_and_type_index.to_u2();
-                let node = &cp[name_and_type_index as usize - 1];
-                if let CpNode::NameAndType(_) = node {
+                let node = cp.get(name_and_type_index);
+                if let Some(node @ CpNode::NameAndType(_)) = node {
                     self.verify_cp_node(node)?
                 } else {
                     return Err(ParsingError::InvalidIndexFromNodeToNode(
@@ -663,8 +1425,8 @@ impl<'a> Verifier<'a> {
             CpNode::NameAndType(nameandtype) => {
                 let name_index = nameandtype.name_index.to_u2();
 
-                let node = &cp[name_index as usize - 1];
-                if let CpNode::Utf8(_) = node {
+                let node = cp.get(name_index);
+                if let Some(node @ CpNode::Utf8(_)) = node {
                     self.verify_cp_node(node)?
                 } else {
                     return Err(ParsingError::InvalidIndexFromNodeToNode(
@@ -673,32 +1435,42 @@ impl<'a> Verifier<'a> {
                         "name_index",
                     ));
                 };
+
+                // Whether it's for a field or a method depends on what refers to it, but it has to
+                // be one or the other (JVMS §4.4.6).
+                let Some(CpNode::Utf8(descriptor)) = cp.get(nameandtype.descriptor_index.to_u2())
+                else {
+                    return Err(ParsingError::InvalidIndexFromNodeToNode(
+                        CpNodeError::NameAndType,
+                        CpNodeError::Utf8,
+                        "descriptor_index",
+                    ));
+                };
+
+                if descriptor.bytes.starts_with('(') {
+                    MethodDescriptor::parse(&descriptor.bytes)?;
+                } else {
+                    FieldType::parse(&descriptor.bytes)?;
+                }
             }
 
             CpNode::InvokeDynamic(dynamic) => {
                 let bootstrap_method_attr_index = dynamic.bootstrap_method_attr_index.to_u2();
 
-                if bootstrap_methods.len() != 1 {
-                    return Err(ParsingError::InvalidAmountOfBootStrapMethodsInClass);
-                } else if if let Attributes::BootstrapMethods(z) =
-                    &self.class.attributes[bootstrap_methods[0]]
-                {
-                    z
-                } else {
-                    unreachable!()
-                }
-                .bootstrap_methods
-                .get(bootstrap_method_attr_index as usize)
-                .is_none()
+                if self
+                    .bootstrap_methods()?
+                    .bootstrap_methods
+                    .get(bootstrap_method_attr_index as usize)
+                    .is_none()
                 {
                     return Err(
-                            ParsingError::BootstrapMethodAttrIndexInInvokeDynamicAttributeIsNotValidIndex,
-                        );
+                        ParsingError::BootstrapMethodAttrIndexInInvokeDynamicAttributeIsNotValidIndex,
+                    );
                 }
 
                 let name_and_type_index = dynamic.name_and_type_index.to_u2();
-                let node = &cp[name_and_type_index as usize - 1];
-                if let CpNode::NameAndType(_) = node {
+                let node = cp.get(name_and_type_index);
+                if let Some(node @ CpNode::NameAndType(_)) = node {
                     self.verify_cp_node(node)?
                 } else {
                     return Err(ParsingError::InvalidIndexFromNodeToNode(
@@ -716,19 +1488,38 @@ impl<'a> Verifier<'a> {
                 if !(1..=9).contains(reference_kind) {
                     return Err(ParsingError::InvalidReferenceKind);
                 } else if (1..=4).contains(reference_kind) {
-                    let node = &cp[reference_index as usize - 1];
-                    if let CpNode::FieldRef(_) = node {
+                    let node = cp.get(reference_index);
+                    if let Some(node @ CpNode::FieldRef(_)) = node {
                         self.verify_cp_node(node)?
                     } else {
                         return Err(ParsingError::MethodHandle1to4NotPointingToFieldRef);
                     };
                 } else if reference_kind == &5 || reference_kind == &8 {
-                    let node = &cp[reference_index as usize - 1];
-                    if let CpNode::MethodRef(_) = node {
+                    let node = cp.get(reference_index);
+                    if let Some(node @ CpNode::MethodRef(_)) = node {
                         self.verify_cp_node(node)?
                     } else {
                         return Err(ParsingError::MethodHandle5or8NotPointingToMethodRef);
                     };
+                } else if reference_kind == &6 || reference_kind == &7 {
+                    // Static and special methods of interfaces can be referenced from Java 8 on.
+                    let node = cp.get(reference_index);
+                    match node {
+                        Some(node @ CpNode::MethodRef(_)) => self.verify_cp_node(node)?,
+                        Some(node @ CpNode::InterfaceMethodRef(_))
+                            if self.class.major_v.to_u2() >= 52 =>
+                        {
+                            self.verify_cp_node(node)?
+                        }
+                        _ => return Err(ParsingError::MethodHandle6or7NotPointingToMethod),
+                    }
+                } else {
+                    let node = cp.get(reference_index);
+                    if let Some(node @ CpNode::InterfaceMethodRef(_)) = node {
+                        self.verify_cp_node(node)?
+                    } else {
+                        return Err(ParsingError::MethodHandle9NotPointingToInterfaceMethodRef);
+                    };
                 }
             }
 
@@ -0,0 +1,346 @@
+//------ aftermath_cfparser -- Classfile parser for the Aftermath JVM. -------//
+//
+// Part of the Aftermath JVM, under the MIT license. The LICENSE file is present
+// at the project root, please consult to it for license information.
+//
+//===----------------------------------------------------------------------===//
+//
+// This file contains the visitor traits driven by `Parser::accept`.
+//
+// Instead of building a ClassFile, the parser calls into a ClassVisitor as it
+// reads the class. This isn't streaming all the way down: each attribute is
+// parsed whole, annotations and instructions included, then replayed to the
+// visitor. Returning `None` from `visit_field` or `visit_method` skips over
+// the member without parsing its attributes, and returning `None` from an
+// annotation method only skips visiting an annotation that's already parsed.
+// Code attributes are only decoded when `visit_code` returns true, and the
+// attributes of the class when `visit_attributes` does. Every method has an
+// empty default, so a visitor only implements what it looks at.
+//
+// Visitors can be chained: one that hands what it's visited on to another,
+// changing some of it along the way, and ends with a ClassWriter, transforms
+// the class.
+//
+// Constant pool indexes are handed out as they appear in the class file,
+// alongside the constant pool to resolve them with.
+//
+//===----------------------------------------------------------------------===//
+
+use crate::class_parser::{
+    Annotation, Attributes, ConstantPool, ElementValue, ExceptionTableAttrCode, StackMapFrame,
+    TypeAnnotation, U2,
+};
+use crate::instructions::Instruction;
+
+type U1 = u8;
+
+#[allow(unused_variables)]
+pub trait ClassVisitor<'class> {
+    fn visit(&mut self, minor_v: U2, major_v: U2, cp: &ConstantPool<'class>) {}
+
+    fn visit_class(
+        &mut self,
+        access_flags: U2,
+        this_class: U2,
+        super_class: U2,
+        interfaces: &'class [U2],
+        cp: &ConstantPool<'class>,
+    ) {
+    }
+
+    fn visit_field(
+        &mut self,
+        access_flags: U2,
+        name_index: U2,
+        descriptor_index: U2,
+        cp: &ConstantPool<'class>,
+    ) -> Option<&mut dyn FieldVisitor<'class>> {
+        None
+    }
+
+    fn visit_method(
+        &mut self,
+        access_flags: U2,
+        name_index: U2,
+        descriptor_index: U2,
+        cp: &ConstantPool<'class>,
+    ) -> Option<&mut dyn MethodVisitor<'class>> {
+        None
+    }
+
+    fn visit_annotation(
+        &mut self,
+        type_index: U2,
+        visible: bool,
+        cp: &ConstantPool<'class>,
+    ) -> Option<&mut dyn AnnotationVisitor> {
+        None
+    }
+
+    fn visit_type_annotation(
+        &mut self,
+        annotation: &TypeAnnotation,
+        visible: bool,
+        cp: &ConstantPool<'class>,
+    ) -> Option<&mut dyn AnnotationVisitor> {
+        None
+    }
+
+    /// Return true to have the attributes of the class decoded and visited, after the fields and
+    /// methods.
+    fn visit_attributes(&mut self, cp: &ConstantPool<'class>) -> bool {
+        false
+    }
+
+    // Any attribute that isn't an annotation.
+    fn visit_attribute(&mut self, attribute: Attributes<'class>, cp: &ConstantPool<'class>) {}
+
+    fn visit_end(&mut self) {}
+}
+
+#[allow(unused_variables)]
+pub trait FieldVisitor<'class> {
+    fn visit_annotation(
+        &mut self,
+        type_index: U2,
+        visible: bool,
+        cp: &ConstantPool<'class>,
+    ) -> Option<&mut dyn AnnotationVisitor> {
+        None
+    }
+
+    fn visit_type_annotation(
+        &mut self,
+        annotation: &TypeAnnotation,
+        visible: bool,
+        cp: &ConstantPool<'class>,
+    ) -> Option<&mut dyn AnnotationVisitor> {
+        None
+    }
+
+    fn visit_attribute(&mut self, attribute: Attributes<'class>, cp: &ConstantPool<'class>) {}
+
+    fn visit_end(&mut self) {}
+}
+
+#[allow(unused_variables)]
+pub trait MethodVisitor<'class> {
+    fn visit_annotation(
+        &mut self,
+        type_index: U2,
+        visible: bool,
+        cp: &ConstantPool<'class>,
+    ) -> Option<&mut dyn AnnotationVisitor> {
+        None
+    }
+
+    fn visit_type_annotation(
+        &mut self,
+        annotation: &TypeAnnotation,
+        visible: bool,
+        cp: &ConstantPool<'class>,
+    ) -> Option<&mut dyn AnnotationVisitor> {
+        None
+    }
+
+    // The number of parameters the parameter annotations that follow are for, which can be more
+    // than the number of parameters annotated.
+    fn visit_annotable_parameter_count(
+        &mut self,
+        count: U1,
+        visible: bool,
+        cp: &ConstantPool<'class>,
+    ) {
+    }
+
+    fn visit_parameter_annotation(
+        &mut self,
+        parameter: U1,
+        type_index: U2,
+        visible: bool,
+        cp: &ConstantPool<'class>,
+    ) -> Option<&mut dyn AnnotationVisitor> {
+        None
+    }
+
+    fn visit_annotation_default(
+        &mut self,
+        cp: &ConstantPool<'class>,
+    ) -> Option<&mut dyn AnnotationVisitor> {
+        None
+    }
+
+    /// Return true to have the exception table, instructions, stack map frames, line numbers and
+    /// the other attributes of the Code attribute visited.
+    fn visit_code(&mut self, max_stack: U2, max_locals: U2, cp: &ConstantPool<'class>) -> bool {
+        false
+    }
+
+    fn visit_exception_handler(
+        &mut self,
+        handler: &ExceptionTableAttrCode,
+        cp: &ConstantPool<'class>,
+    ) {
+    }
+
+    /// `wide` tells whether the instruction was prefixed by `wide`, which its operands may not need.
+    fn visit_instruction(
+        &mut self,
+        pc: u32,
+        instruction: &Instruction,
+        wide: bool,
+        cp: &ConstantPool<'class>,
+    ) {
+    }
+
+    fn visit_frame(&mut self, frame: &StackMapFrame, cp: &ConstantPool<'class>) {}
+
+    fn visit_line_number(&mut self, start_pc: U2, line_number: U2) {}
+
+    // Any attribute of the method, or of its Code attribute, that isn't covered above. Whatever is
+    // visited between `visit_code` and `visit_code_end` belongs to the Code attribute.
+    fn visit_attribute(&mut self, attribute: Attributes<'class>, cp: &ConstantPool<'class>) {}
+
+    fn visit_code_end(&mut self) {}
+
+    fn visit_end(&mut self) {}
+}
+
+// `name_index` is `None` for the elements of an array.
+#[allow(unused_variables)]
+pub trait AnnotationVisitor {
+    /// A constant, enum or class element.
+    fn visit_value(&mut self, name_index: Option<U2>, value: &ElementValue) {}
+
+    fn visit_annotation(
+        &mut self,
+        name_index: Option<U2>,
+        type_index: U2,
+    ) -> Option<&mut dyn AnnotationVisitor> {
+        None
+    }
+
+    fn visit_array(&mut self, name_index: Option<U2>) -> Option<&mut dyn AnnotationVisitor> {
+        None
+    }
+
+    fn visit_end(&mut self) {}
+}
+
+// What the class, field and method visitors have in common, so attributes can be handed out the
+// same way to all three.
+pub(crate) trait AttributeVisitor<'class> {
+    fn annotation(
+        &mut self,
+        type_index: U2,
+        visible: bool,
+        cp: &ConstantPool<'class>,
+    ) -> Option<&mut dyn AnnotationVisitor>;
+
+    fn type_annotation(
+        &mut self,
+        annotation: &TypeAnnotation,
+        visible: bool,
+        cp: &ConstantPool<'class>,
+    ) -> Option<&mut dyn AnnotationVisitor>;
+
+    fn attribute(&mut self, attribute: Attributes<'class>, cp: &ConstantPool<'class>);
+}
+
+macro_rules! attribute_visitor {
+    ($visitor: ident) => {
+        impl<'class> AttributeVisitor<'class> for dyn $visitor<'class> + '_ {
+            fn annotation(
+                &mut self,
+                type_index: U2,
+                visible: bool,
+                cp: &ConstantPool<'class>,
+            ) -> Option<&mut dyn AnnotationVisitor> {
+                self.visit_annotation(type_index, visible, cp)
+            }
+
+            fn type_annotation(
+                &mut self,
+                annotation: &TypeAnnotation,
+                visible: bool,
+                cp: &ConstantPool<'class>,
+            ) -> Option<&mut dyn AnnotationVisitor> {
+                self.visit_type_annotation(annotation, visible, cp)
+            }
+
+            fn attribute(&mut self, attribute: Attributes<'class>, cp: &ConstantPool<'class>) {
+                self.visit_attribute(attribute, cp)
+            }
+        }
+    };
+}
+
+attribute_visitor!(ClassVisitor);
+attribute_visitor!(FieldVisitor);
+attribute_visitor!(MethodVisitor);
+
+pub(crate) fn attribute<'class>(
+    visitor: &mut (impl AttributeVisitor<'class> + ?Sized),
+    attribute: Attributes<'class>,
+    cp: &ConstantPool<'class>,
+) {
+    let (annotations, type_annotations, visible) = match attribute {
+        Attributes::RuntimeVisibleAnnotations(z) => (z.annotations, vec![], true),
+        Attributes::RuntimeInvisibleAnnotations(z) => (z.annotations, vec![], false),
+        Attributes::RuntimeVisibleTypeAnnotations(z) => (vec![], z.type_annotation, true),
+        Attributes::RuntimeInvisibleTypeAnnotations(z) => (vec![], z.annotations, false),
+        attribute => return visitor.attribute(attribute, cp),
+    };
+
+    for annotation in &annotations {
+        if let Some(annotation_visitor) = visitor.annotation(annotation.type_index, visible, cp) {
+            self::annotation(annotation_visitor, annotation);
+        }
+    }
+
+    for annotation in &type_annotations {
+        if let Some(annotation_visitor) = visitor.type_annotation(annotation, visible, cp) {
+            for pair in &annotation.element_value_pairs {
+                element_value(
+                    annotation_visitor,
+                    Some(pair.element_name_index),
+                    &pair.value,
+                );
+            }
+
+            annotation_visitor.visit_end();
+        }
+    }
+}
+
+pub(crate) fn annotation(visitor: &mut dyn AnnotationVisitor, annotation: &Annotation) {
+    for pair in &annotation.element_value_pairs {
+        element_value(visitor, Some(pair.element_name_index), &pair.value);
+    }
+
+    visitor.visit_end();
+}
+
+pub(crate) fn element_value(
+    visitor: &mut dyn AnnotationVisitor,
+    name_index: Option<U2>,
+    value: &ElementValue,
+) {
+    match value {
+        ElementValue::AnnotationValue(z) => {
+            if let Some(visitor) = visitor.visit_annotation(name_index, z.type_index) {
+                annotation(visitor, z);
+            }
+        }
+        ElementValue::ArrayValue(z) => {
+            if let Some(visitor) = visitor.visit_array(name_index) {
+                for value in &z.element_value {
+                    element_value(visitor, None, value);
+                }
+
+                visitor.visit_end();
+            }
+        }
+        value => visitor.visit_value(name_index, value),
+    }
+}
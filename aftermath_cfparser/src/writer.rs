@@ -0,0 +1,1480 @@
+//------ aftermath_cfparser -- Classfile parser for the Aftermath JVM. -------//
+//
+// Part of the Aftermath JVM, under the MIT license. The LICENSE file is present
+// at the project root, please consult to it for license information.
+//
+//===----------------------------------------------------------------------===//
+//
+// This file contains the class file writer, which serializes a ClassFile back
+// into bytes. Writing a freshly parsed class gives back the exact bytes it was
+// parsed from.
+//
+// Attributes don't remember the index of their name in the constant pool, so
+// the writer uses the first Utf8 node holding that name. A class containing
+// the same attribute name twice in its constant pool will still be written
+// out correctly, just not byte for byte identical.
+//
+// It also contains the ClassWriter, a ClassVisitor writing out the class it
+// visits as it goes, which ends chains of visitors transforming a class.
+// Annotations, stack map frames and line numbers are visited one by one, so
+// consecutive ones are gathered back into a single attribute.
+//
+//===----------------------------------------------------------------------===//
+
+use crate::class_parser::*;
+use crate::instructions::Instruction;
+use crate::mutf8;
+use crate::visitor::{AnnotationVisitor, ClassVisitor, FieldVisitor, MethodVisitor};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+
+type U1 = u8;
+type U4 = u32;
+
+// The index of the Utf8 node holding each attribute name.
+type Names<'a> = HashMap<Cow<'a, str>, U2>;
+
+struct Writer {
+    bytes: Vec<U1>,
+}
+
+impl<'class> ClassFile<'class> {
+    pub fn write_to(&self, writer: &mut impl Write) -> Result<(), ParsingError> {
+        writer.write_all(&self.to_bytes()?)?;
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<U1>, ParsingError> {
+        let names = names(&self.cp);
+        let mut writer = Writer {
+            bytes: Vec::with_capacity(1024),
+        };
+
+        writer.class(self, &names)?;
+        Ok(writer.bytes)
+    }
+}
+
+fn names<'a>(cp: &ConstantPool<'a>) -> Names<'a> {
+    let mut names = HashMap::new();
+
+    for (index, node) in cp.iter().enumerate().rev() {
+        if let CpNode::Utf8(utf8) = node {
+            names.insert(utf8.bytes.clone(), U2::from(index as u16 + 1));
+        }
+    }
+
+    names
+}
+
+impl Writer {
+    fn u1(&mut self, value: U1) {
+        self.bytes.push(value);
+    }
+
+    fn u1_range(&mut self, values: &[U1]) {
+        self.bytes.extend_from_slice(values);
+    }
+
+    fn u2(&mut self, value: U2) {
+        self.u1_range(&value.to_u2().to_be_bytes());
+    }
+
+    fn u2_range(&mut self, values: &[U2]) {
+        for value in values {
+            self.u2(*value);
+        }
+    }
+
+    fn u4(&mut self, value: U4) {
+        self.u1_range(&value.to_be_bytes());
+    }
+
+    fn count(length: usize, field: &'static str) -> Result<U2, ParsingError> {
+        u16::try_from(length)
+            .map(U2::from)
+            .map_err(|_| ParsingError::TooLarge(field))
+    }
+
+    fn u1_count(length: usize, field: &'static str) -> Result<U1, ParsingError> {
+        u8::try_from(length).map_err(|_| ParsingError::TooLarge(field))
+    }
+
+    fn class(&mut self, class: &ClassFile, names: &Names) -> Result<(), ParsingError> {
+        self.u4(crate::consts::MAGIC);
+        self.u2(class.minor_v);
+        self.u2(class.major_v);
+
+        self.u2(Self::count(class.cp.len() + 1, "constant_pool_count")?);
+
+        for node in &class.cp {
+            self.cp_node(node)?;
+        }
+
+        self.u2(class.access_flags);
+        self.u2(class.this_class);
+        self.u2(class.super_class);
+        self.u2(Self::count(class.interfaces.len(), "interfaces_count")?);
+        self.u2_range(class.interfaces);
+
+        self.u2(Self::count(class.fields.len(), "fields_count")?);
+
+        for field in &class.fields {
+            self.u2(field.access_flags);
+            self.u2(field.name_index);
+            self.u2(field.descriptor_index);
+            self.attributes(&field.attributes, names)?;
+        }
+
+        self.u2(Self::count(class.methods.len(), "methods_count")?);
+
+        for method in &class.methods {
+            self.u2(method.access_flags);
+            self.u2(method.name_index);
+            self.u2(method.descriptor_index);
+            self.attributes(&method.attributes, names)?;
+        }
+
+        self.attributes(&class.attributes, names)
+    }
+
+    fn cp_node(&mut self, node: &CpNode) -> Result<(), ParsingError> {
+        match node {
+            CpNode::Utf8(z) => {
+                let bytes = mutf8::encode(&z.bytes);
+
+                self.u1(1);
+                self.u2(Self::count(bytes.len(), "Utf8 length")?);
+                self.u1_range(&bytes);
+            }
+            CpNode::Integer(z) => {
+                self.u1(3);
+                self.u4(z.bytes);
+            }
+            CpNode::Float(z) => {
+                self.u1(4);
+                self.u4(z.bytes);
+            }
+            CpNode::Long(z) => {
+                self.u1(5);
+                self.u4(z.high_bytes);
+                self.u4(z.low_bytes);
+            }
+            CpNode::Double(z) => {
+                self.u1(6);
+                self.u4(z.high_bytes);
+                self.u4(z.low_bytes);
+            }
+            CpNode::Class(z) => {
+                self.u1(7);
+                self.u2(z.name_index);
+            }
+            CpNode::String(z) => {
+                self.u1(8);
+                self.u2(z.string_index);
+            }
+            CpNode::FieldRef(z) => {
+                self.u1(9);
+                self.u2(z.class_index);
+                self.u2(z.name_and_type_index);
+            }
+            CpNode::MethodRef(z) => {
+                self.u1(10);
+                self.u2(z.class_index);
+                self.u2(z.name_and_type_index);
+            }
+            CpNode::InterfaceMethodRef(z) => {
+                self.u1(11);
+                self.u2(z.class_index);
+                self.u2(z.name_and_type_index);
+            }
+            CpNode::NameAndType(z) => {
+                self.u1(12);
+                self.u2(z.name_index);
+                self.u2(z.descriptor_index);
+            }
+            CpNode::MethodHandle(z) => {
+                self.u1(15);
+                self.u1(z.reference_kind);
+                self.u2(z.reference_index);
+            }
+            CpNode::MethodType(z) => {
+                self.u1(16);
+                self.u2(z.descriptor_index);
+            }
+            CpNode::Dynamic(z) => {
+                self.u1(17);
+                self.u2(z.bootstrap_method_attr_index);
+                self.u2(z.name_and_type_index);
+            }
+            CpNode::InvokeDynamic(z) => {
+                self.u1(18);
+                self.u2(z.bootstrap_method_attr_index);
+                self.u2(z.name_and_type_index);
+            }
+            CpNode::Module(z) => {
+                self.u1(19);
+                self.u2(z.name_index);
+            }
+            CpNode::Package(z) => {
+                self.u1(20);
+                self.u2(z.name_index);
+            }
+            // The unusable slot after a Long or Double.
+            CpNode::None => {}
+        }
+
+        Ok(())
+    }
+
+    fn attributes(&mut self, attributes: &[Attributes], names: &Names) -> Result<(), ParsingError> {
+        self.u2(Self::count(attributes.len(), "attributes_count")?);
+
+        for attribute in attributes {
+            self.attribute(attribute, names)?;
+        }
+
+        Ok(())
+    }
+
+    // Writes the name of an attribute and a placeholder for its length, returning where the length
+    // goes.
+    fn attribute_header(&mut self, name: &str, names: &Names) -> Result<usize, ParsingError> {
+        let name_index = *names
+            .get(name)
+            .ok_or_else(|| ParsingError::AttributeNameNotInConstantPool(name.to_string()))?;
+
+        self.u2(name_index);
+
+        let length_index = self.bytes.len();
+        self.u4(0);
+
+        Ok(length_index)
+    }
+
+    // Patches in the length of the attribute whose length goes at `length_index`, once its body
+    // has been written.
+    fn attribute_length(&mut self, length_index: usize) -> Result<(), ParsingError> {
+        let length = U4::try_from(self.bytes.len() - length_index - 4)
+            .map_err(|_| ParsingError::TooLarge("attribute_length"))?;
+        self.bytes[length_index..length_index + 4].copy_from_slice(&length.to_be_bytes());
+
+        Ok(())
+    }
+
+    // Patches in a u2 count written as a placeholder at `index`.
+    fn patch_count(
+        &mut self,
+        index: usize,
+        count: usize,
+        field: &'static str,
+    ) -> Result<(), ParsingError> {
+        let count = Self::count(count, field)?.to_u2();
+        self.bytes[index..index + 2].copy_from_slice(&count.to_be_bytes());
+
+        Ok(())
+    }
+
+    fn attribute(&mut self, attribute: &Attributes, names: &Names) -> Result<(), ParsingError> {
+        let length_index = self.attribute_header(attribute.name(), names)?;
+
+        match attribute {
+            Attributes::Value(z) => self.u2(z.value_index),
+
+            Attributes::Code(z) => {
+                self.u2(z.max_stack);
+                self.u2(z.max_locals);
+                self.u4(U4::try_from(z.code.len()).map_err(|_| ParsingError::TooLarge("code"))?);
+                self.u1_range(z.code);
+                self.u2(Self::count(
+                    z.exception_table.len(),
+                    "exception_table_length",
+                )?);
+
+                for entry in &z.exception_table {
+                    self.u2(entry.start_pc);
+                    self.u2(entry.end_pc);
+                    self.u2(entry.handler_pc);
+                    self.u2(entry.catch_type);
+                }
+
+                self.attributes(&z.attributes, names)?;
+            }
+
+            Attributes::StackMapTable(z) => {
+                self.u2(Self::count(z.entries.len(), "number_of_entries")?);
+
+                for frame in &z.entries {
+                    self.stackmapframe(frame)?;
+                }
+            }
+
+            Attributes::Exceptions(z) => {
+                self.u2(Self::count(
+                    z.exception_index_table.len(),
+                    "number_of_exceptions",
+                )?);
+                self.u2_range(z.exception_index_table);
+            }
+
+            Attributes::InnerClass(z) => {
+                self.u2(Self::count(z.classes.len(), "number_of_classes")?);
+
+                for class in &z.classes {
+                    self.u2(class.inner_class_info_index);
+                    self.u2(class.outer_class_info_index);
+                    self.u2(class.inner_name_index);
+                    self.u2(class.inner_class_access_flags);
+                }
+            }
+
+            Attributes::EnclosingMethod(z) => {
+                self.u2(z.class_index);
+                self.u2(z.method_index);
+            }
+
+            Attributes::Synthetic(_) | Attributes::Deprecated(_) => {}
+
+            Attributes::Signature(z) => self.u2(z.signature_index),
+
+            Attributes::SourceFile(z) => self.u2(z.sourcefile_index),
+
+            Attributes::SourceDebugExt(z) => self.u1_range(z.debug_extension),
+
+            Attributes::LineNumberTable(z) => {
+                self.u2(Self::count(
+                    z.line_number_table.len(),
+                    "line_number_table_length",
+                )?);
+
+                for entry in &z.line_number_table {
+                    self.u2(entry.start_pc);
+                    self.u2(entry.line_number);
+                }
+            }
+
+            Attributes::LocalVariableTable(z) => {
+                self.u2(Self::count(
+                    z.local_variable_table.len(),
+                    "local_variable_table_length",
+                )?);
+
+                for entry in &z.local_variable_table {
+                    self.u2(entry.start_pc);
+                    self.u2(entry.length);
+                    self.u2(entry.name_index);
+                    self.u2(entry.descriptor_index);
+                    self.u2(entry.index);
+                }
+            }
+
+            Attributes::LocalVariableTypeTable(z) => {
+                self.u2(Self::count(
+                    z.local_variable_type_table.len(),
+                    "local_variable_type_table_length",
+                )?);
+
+                for entry in &z.local_variable_type_table {
+                    self.u2(entry.start_pc);
+                    self.u2(entry.length);
+                    self.u2(entry.name_index);
+                    self.u2(entry.signature_index);
+                    self.u2(entry.index);
+                }
+            }
+
+            Attributes::RuntimeVisibleAnnotations(RuntimeVisibleAnnotations { annotations })
+            | Attributes::RuntimeInvisibleAnnotations(RuntimeInvisibleAnnotations {
+                annotations,
+            }) => self.annotations(annotations)?,
+
+            Attributes::RuntimeVisibleParameterAnnotations(
+                RuntimeVisibleParameterAnnotations {
+                    parameter_annotations,
+                },
+            )
+            | Attributes::RuntimeInvisibleParameterAnnotations(
+                RuntimeInvisibleParameterAnnotations {
+                    parameter_annotations,
+                },
+            ) => {
+                self.u1(Self::u1_count(
+                    parameter_annotations.len(),
+                    "num_parameters",
+                )?);
+
+                for parameter in parameter_annotations {
+                    self.annotations(&parameter.annotations)?;
+                }
+            }
+
+            Attributes::RuntimeVisibleTypeAnnotations(RuntimeVisibleTypeAnnotations {
+                type_annotation: annotations,
+            })
+            | Attributes::RuntimeInvisibleTypeAnnotations(RuntimeInvisibleTypeAnnotations {
+                annotations,
+            }) => {
+                self.u2(Self::count(annotations.len(), "num_annotations")?);
+
+                for annotation in annotations {
+                    self.type_annotation(annotation)?;
+                }
+            }
+
+            Attributes::AnnotationDefault(z) => self.element_value(&z.default_value)?,
+
+            Attributes::BootstrapMethods(z) => {
+                self.u2(Self::count(
+                    z.bootstrap_methods.len(),
+                    "num_bootstrap_methods",
+                )?);
+
+                for method in &z.bootstrap_methods {
+                    self.u2(method.bootstrap_method_ref);
+                    self.u2(Self::count(
+                        method.bootstrap_arguments.len(),
+                        "num_bootstrap_arguments",
+                    )?);
+                    self.u2_range(method.bootstrap_arguments);
+                }
+            }
+
+            Attributes::MethodParameters(z) => {
+                self.u1(Self::u1_count(z.parameters.len(), "parameters_count")?);
+
+                for parameter in &z.parameters {
+                    self.u2(parameter.name_index);
+                    self.u2(parameter.access_flags);
+                }
+            }
+
+            Attributes::Module(z) => {
+                self.u2(z.module_name_index);
+                self.u2(z.module_flags);
+                self.u2(z.module_version_index);
+
+                self.u2(Self::count(z.requires.len(), "requires_count")?);
+
+                for requires in &z.requires {
+                    self.u2(requires.requires_index);
+                    self.u2(requires.requires_flags);
+                    self.u2(requires.require_version_index);
+                }
+
+                self.u2(Self::count(z.exports.len(), "exports_count")?);
+
+                for exports in &z.exports {
+                    self.u2(exports.exports_index);
+                    self.u2(exports.exports_flags);
+                    self.u2(Self::count(
+                        exports.exports_to_index.len(),
+                        "exports_to_count",
+                    )?);
+                    self.u2_range(exports.exports_to_index);
+                }
+
+                self.u2(Self::count(z.opens.len(), "opens_count")?);
+
+                for opens in &z.opens {
+                    self.u2(opens.opens_index);
+                    self.u2(opens.opens_flags);
+                    self.u2(Self::count(opens.opens_to_index.len(), "opens_to_count")?);
+                    self.u2_range(opens.opens_to_index);
+                }
+
+                self.u2(Self::count(z.uses_index.len(), "uses_count")?);
+                self.u2_range(z.uses_index);
+
+                self.u2(Self::count(z.provides.len(), "provides_count")?);
+
+                for provides in &z.provides {
+                    self.u2(provides.provides_index);
+                    self.u2(Self::count(
+                        provides.provides_with_index.len(),
+                        "provides_with_count",
+                    )?);
+                    self.u2_range(provides.provides_with_index);
+                }
+            }
+
+            Attributes::ModulePackages(z) => {
+                self.u2(Self::count(z.package_index.len(), "package_count")?);
+                self.u2_range(z.package_index);
+            }
+
+            Attributes::ModuleMainClass(z) => self.u2(z.main_class_index),
+
+            Attributes::NestHost(z) => self.u2(z.host_class_index),
+
+            Attributes::NestMembers(NestMembers { classes })
+            | Attributes::PermittedSubclasses(PermittedSubclasses { classes }) => {
+                self.u2(Self::count(classes.len(), "number_of_classes")?);
+                self.u2_range(classes);
+            }
+
+            Attributes::Record(z) => {
+                self.u2(Self::count(z.components.len(), "components_count")?);
+
+                for component in &z.components {
+                    self.u2(component.name_index);
+                    self.u2(component.descriptor_index);
+                    self.attributes(&component.attributes, names)?;
+                }
+            }
+
+            Attributes::Unknown { info, .. } => self.u1_range(info),
+        }
+
+        self.attribute_length(length_index)
+    }
+
+    fn stackmapframe(&mut self, frame: &StackMapFrame) -> Result<(), ParsingError> {
+        match frame {
+            StackMapFrame::SameFrame(z) => self.u1(z.frame_type),
+            StackMapFrame::SameLocals1StackItemFrame(z) => {
+                self.u1(z.frame_type);
+                self.verification_type_info(&z.stack);
+            }
+            StackMapFrame::SameLocals1StackItemFrameExtended(z) => {
+                self.u1(z.frame_type);
+                self.u2(z.offset_delta);
+                self.verification_type_info(&z.stack);
+            }
+            StackMapFrame::ChopFrame(z) => {
+                self.u1(z.frame_type);
+                self.u2(z.offset_delta);
+            }
+            StackMapFrame::SameFrameExtended(z) => {
+                self.u1(z.frame_type);
+                self.u2(z.offset_delta);
+            }
+            StackMapFrame::AppendFrame(z) => {
+                self.u1(z.frame_type);
+                self.u2(z.offset_delta);
+
+                for local in &z.locals {
+                    self.verification_type_info(local);
+                }
+            }
+            StackMapFrame::FullFrame(z) => {
+                self.u1(z.frame_type);
+                self.u2(z.offset_delta);
+                self.u2(Self::count(z.locals.len(), "number_of_locals")?);
+
+                for local in &z.locals {
+                    self.verification_type_info(local);
+                }
+
+                self.u2(Self::count(z.stack.len(), "number_of_stack_items")?);
+
+                for stack in &z.stack {
+                    self.verification_type_info(stack);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn verification_type_info(&mut self, info: &VerificationTypeInfo) {
+        match info {
+            VerificationTypeInfo::TopVariableInfo(TopVariableInfo { tag })
+            | VerificationTypeInfo::IntegerVariableInfo(IntegerVariableInfo { tag })
+            | VerificationTypeInfo::FloatVariableInfo(FloatVariableInfo { tag })
+            | VerificationTypeInfo::DoubleVariableInfo(DoubleVariableInfo { tag })
+            | VerificationTypeInfo::LongVariableInfo(LongVariableInfo { tag })
+            | VerificationTypeInfo::NullVariableInfo(NullVariableInfo { tag })
+            | VerificationTypeInfo::UninitializedThisVariableInfo(
+                UninitializedThisVariableInfo { tag },
+            ) => self.u1(*tag),
+            VerificationTypeInfo::ObjectVariableInfo(z) => {
+                self.u1(z.tag);
+                self.u2(z.cp_index);
+            }
+            VerificationTypeInfo::UninitializedVariableInfo(z) => {
+                self.u1(z.tag);
+                self.u2(z.offset);
+            }
+        }
+    }
+
+    fn annotations(&mut self, annotations: &[Annotation]) -> Result<(), ParsingError> {
+        self.u2(Self::count(annotations.len(), "num_annotations")?);
+
+        for annotation in annotations {
+            self.annotation(annotation)?;
+        }
+
+        Ok(())
+    }
+
+    fn annotation(&mut self, annotation: &Annotation) -> Result<(), ParsingError> {
+        self.u2(annotation.type_index);
+        self.u2(Self::count(
+            annotation.element_value_pairs.len(),
+            "num_element_value_pairs",
+        )?);
+
+        for pair in &annotation.element_value_pairs {
+            self.u2(pair.element_name_index);
+            self.element_value(&pair.value)?;
+        }
+
+        Ok(())
+    }
+
+    fn element_value(&mut self, value: &ElementValue) -> Result<(), ParsingError> {
+        match value {
+            ElementValue::ConstValueIndex(z) => {
+                self.u1(z.tag);
+                self.u2(z.const_value_index);
+            }
+            ElementValue::EnumConstValue(z) => {
+                self.u1(b'e');
+                self.u2(z.type_name_index);
+                self.u2(z.const_name_index);
+            }
+            ElementValue::ClassInfoIndex(z) => {
+                self.u1(b'c');
+                self.u2(*z);
+            }
+            ElementValue::AnnotationValue(z) => {
+                self.u1(b'@');
+                self.annotation(z)?;
+            }
+            ElementValue::ArrayValue(z) => {
+                self.u1(b'[');
+                self.u2(Self::count(z.element_value.len(), "num_values")?);
+
+                for value in &z.element_value {
+                    self.element_value(value)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn type_annotation(&mut self, annotation: &TypeAnnotation) -> Result<(), ParsingError> {
+        self.type_annotation_target(annotation)?;
+        self.u2(Self::count(
+            annotation.element_value_pairs.len(),
+            "num_element_value_pairs",
+        )?);
+
+        for pair in &annotation.element_value_pairs {
+            self.u2(pair.element_name_index);
+            self.element_value(&pair.value)?;
+        }
+
+        Ok(())
+    }
+
+    // Everything of a type annotation up to its element value pairs.
+    fn type_annotation_target(&mut self, annotation: &TypeAnnotation) -> Result<(), ParsingError> {
+        self.u1(annotation.target_type);
+
+        match &annotation.target_info {
+            TargetInfo::TypeParameterTarget(z) => self.u1(z.type_parameter_index),
+            TargetInfo::Supertype(z) => self.u2(z.supertype_index),
+            TargetInfo::TypeParameterBound(z) => {
+                self.u1(z.type_parameter_index);
+                self.u1(z.bound_index);
+            }
+            TargetInfo::Empty => {}
+            TargetInfo::FormalParameter(z) => self.u1(z.formal_parameter_index),
+            TargetInfo::Throws(z) => self.u1(z.throws_type_index),
+            TargetInfo::Localvar(z) => {
+                self.u2(Self::count(z.table.len(), "table_length")?);
+
+                for entry in &z.table {
+                    self.u2(entry.start_pc);
+                    self.u2(entry.length);
+                    self.u2(entry.index);
+                }
+            }
+            TargetInfo::Catch(z) => self.u2(z.exception_table_index),
+            TargetInfo::Offset(z) => self.u2(z.offset),
+            TargetInfo::TypeArgument(z) => {
+                self.u2(z.offset);
+                self.u1(z.type_argument_index);
+            }
+        }
+
+        self.u1(Self::u1_count(
+            annotation.target_path.path.len(),
+            "path_length",
+        )?);
+
+        for path in &annotation.target_path.path {
+            self.u1(path.type_path_kind);
+            self.u1(path.type_argument_index);
+        }
+
+        self.u2(annotation.type_index);
+
+        Ok(())
+    }
+}
+
+/// A ClassVisitor writing out the class it visits. Visiting a class unchanged gives back the bytes
+/// it was parsed from, as long as it has no empty annotation attributes, which aren't visited.
+///
+/// Instructions are written where they end up in the code array, so a visitor adding or removing
+/// some has to fix the branch offsets, exception handlers and stack map frames around them itself.
+pub struct ClassWriter<'class> {
+    names: Rc<Names<'class>>,
+    // From the magic to the interfaces.
+    header: Writer,
+    fields: Members,
+    methods: Members,
+    // The field or method being visited, written out once the next one starts.
+    member: Option<MemberWriter<'class>>,
+    attributes: AttributeTable<'class>,
+    error: Option<ParsingError>,
+}
+
+#[derive(Default)]
+struct Members {
+    count: usize,
+    bytes: Vec<U1>,
+}
+
+struct MemberWriter<'class> {
+    method: bool,
+    // The access flags, name and descriptor.
+    header: Writer,
+    attributes: AttributeTable<'class>,
+    // The Code attribute being visited, everything is written into it until `visit_code_end`.
+    code: Option<CodeWriter<'class>>,
+}
+
+struct CodeWriter<'class> {
+    max_stack: U2,
+    max_locals: U2,
+    code: Vec<U1>,
+    handlers: Members,
+    attributes: AttributeTable<'class>,
+}
+
+// An attribute table written one attribute at a time.
+struct AttributeTable<'class> {
+    names: Rc<Names<'class>>,
+    writer: Writer,
+    count: usize,
+    // The attribute consecutive annotations, frames or line numbers are being added to.
+    open: Option<Open>,
+    // Where the number of values of each annotation and array being written goes, how many were
+    // written so far, and the name of that number.
+    values: Vec<(usize, usize, &'static str)>,
+    error: Option<ParsingError>,
+}
+
+struct Open {
+    name: &'static str,
+    length_index: usize,
+    // Where the number of entries goes, how many there are so far, and the name of that number.
+    // For parameter annotations, these are the annotations of the last parameter.
+    entries: Option<(usize, usize, &'static str)>,
+    parameters: Option<Parameters>,
+}
+
+struct Parameters {
+    // Where num_parameters goes.
+    index: usize,
+    // The number of parameters visited, 0 when it wasn't.
+    declared: usize,
+    // The number of parameters whose annotations were started.
+    started: usize,
+}
+
+impl Default for ClassWriter<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'class> ClassWriter<'class> {
+    pub fn new() -> Self {
+        let names = Rc::new(Names::new());
+
+        Self {
+            names: names.clone(),
+            header: Writer { bytes: vec![] },
+            fields: Members::default(),
+            methods: Members::default(),
+            member: None,
+            attributes: AttributeTable::new(names),
+            error: None,
+        }
+    }
+
+    /// The class file, or the first error met while writing it.
+    pub fn to_bytes(mut self) -> Result<Vec<U1>, ParsingError> {
+        self.end_member();
+
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        let mut writer = self.header;
+
+        for members in [self.fields, self.methods] {
+            writer.u2(Writer::count(members.count, "members_count")?);
+            writer.u1_range(&members.bytes);
+        }
+
+        writer.u1_range(&self.attributes.finish()?);
+
+        Ok(writer.bytes)
+    }
+
+    fn record(&mut self, result: Result<(), ParsingError>) {
+        if let Err(error) = result {
+            self.error.get_or_insert(error);
+        }
+    }
+
+    fn end_member(&mut self) {
+        let Some(member) = self.member.take() else {
+            return;
+        };
+
+        let members = match member.method {
+            true => &mut self.methods,
+            false => &mut self.fields,
+        };
+
+        match member.finish() {
+            Ok(bytes) => {
+                members.count += 1;
+                members.bytes.extend(bytes);
+            }
+            Err(error) => self.record(Err(error)),
+        }
+    }
+
+    fn start_member(
+        &mut self,
+        method: bool,
+        access_flags: U2,
+        name_index: U2,
+        descriptor_index: U2,
+    ) -> &mut MemberWriter<'class> {
+        self.end_member();
+
+        let mut header = Writer { bytes: vec![] };
+        header.u2(access_flags);
+        header.u2(name_index);
+        header.u2(descriptor_index);
+
+        self.member.insert(MemberWriter {
+            method,
+            header,
+            attributes: AttributeTable::new(self.names.clone()),
+            code: None,
+        })
+    }
+}
+
+impl<'class> ClassVisitor<'class> for ClassWriter<'class> {
+    fn visit(&mut self, minor_v: U2, major_v: U2, cp: &ConstantPool<'class>) {
+        self.names = Rc::new(names(cp));
+        self.attributes = AttributeTable::new(self.names.clone());
+
+        self.header.u4(crate::consts::MAGIC);
+        self.header.u2(minor_v);
+        self.header.u2(major_v);
+
+        let result = Writer::count(cp.len() + 1, "constant_pool_count").and_then(|count| {
+            self.header.u2(count);
+            cp.iter().try_for_each(|node| self.header.cp_node(node))
+        });
+        self.record(result);
+    }
+
+    fn visit_class(
+        &mut self,
+        access_flags: U2,
+        this_class: U2,
+        super_class: U2,
+        interfaces: &'class [U2],
+        _: &ConstantPool<'class>,
+    ) {
+        self.header.u2(access_flags);
+        self.header.u2(this_class);
+        self.header.u2(super_class);
+
+        let result = Writer::count(interfaces.len(), "interfaces_count").map(|count| {
+            self.header.u2(count);
+            self.header.u2_range(interfaces);
+        });
+        self.record(result);
+    }
+
+    fn visit_field(
+        &mut self,
+        access_flags: U2,
+        name_index: U2,
+        descriptor_index: U2,
+        _: &ConstantPool<'class>,
+    ) -> Option<&mut dyn FieldVisitor<'class>> {
+        Some(self.start_member(false, access_flags, name_index, descriptor_index))
+    }
+
+    fn visit_method(
+        &mut self,
+        access_flags: U2,
+        name_index: U2,
+        descriptor_index: U2,
+        _: &ConstantPool<'class>,
+    ) -> Option<&mut dyn MethodVisitor<'class>> {
+        Some(self.start_member(true, access_flags, name_index, descriptor_index))
+    }
+
+    fn visit_annotation(
+        &mut self,
+        type_index: U2,
+        visible: bool,
+        _: &ConstantPool<'class>,
+    ) -> Option<&mut dyn AnnotationVisitor> {
+        self.end_member();
+        self.attributes.annotation(visible, type_index)
+    }
+
+    fn visit_type_annotation(
+        &mut self,
+        annotation: &TypeAnnotation,
+        visible: bool,
+        _: &ConstantPool<'class>,
+    ) -> Option<&mut dyn AnnotationVisitor> {
+        self.end_member();
+        self.attributes.type_annotation(visible, annotation)
+    }
+
+    fn visit_attributes(&mut self, _: &ConstantPool<'class>) -> bool {
+        self.end_member();
+        true
+    }
+
+    fn visit_attribute(&mut self, attribute: Attributes<'class>, _: &ConstantPool<'class>) {
+        self.end_member();
+        self.attributes.attribute(&attribute);
+    }
+
+    fn visit_end(&mut self) {
+        self.end_member();
+    }
+}
+
+impl<'class> MemberWriter<'class> {
+    // The attributes annotations and other attributes go to, the ones of the Code attribute while
+    // it's being visited.
+    fn table(&mut self) -> &mut AttributeTable<'class> {
+        match &mut self.code {
+            Some(code) => &mut code.attributes,
+            None => &mut self.attributes,
+        }
+    }
+
+    fn end_code(&mut self) {
+        let Some(code) = self.code.take() else {
+            return;
+        };
+
+        let body = (|| {
+            let mut writer = Writer { bytes: vec![] };
+            writer.u2(code.max_stack);
+            writer.u2(code.max_locals);
+            writer.u4(U4::try_from(code.code.len()).map_err(|_| ParsingError::TooLarge("code"))?);
+            writer.u1_range(&code.code);
+            writer.u2(Writer::count(
+                code.handlers.count,
+                "exception_table_length",
+            )?);
+            writer.u1_range(&code.handlers.bytes);
+            writer.u1_range(&code.attributes.finish()?);
+
+            Ok(writer.bytes)
+        })();
+
+        match body {
+            Ok(body) => self.attributes.raw("Code", &body),
+            Err(error) => {
+                self.attributes.record(Err(error));
+            }
+        }
+    }
+
+    fn finish(mut self) -> Result<Vec<U1>, ParsingError> {
+        self.end_code();
+
+        let mut bytes = self.header.bytes;
+        bytes.extend(self.attributes.finish()?);
+
+        Ok(bytes)
+    }
+}
+
+impl<'class> FieldVisitor<'class> for MemberWriter<'class> {
+    fn visit_annotation(
+        &mut self,
+        type_index: U2,
+        visible: bool,
+        _: &ConstantPool<'class>,
+    ) -> Option<&mut dyn AnnotationVisitor> {
+        self.table().annotation(visible, type_index)
+    }
+
+    fn visit_type_annotation(
+        &mut self,
+        annotation: &TypeAnnotation,
+        visible: bool,
+        _: &ConstantPool<'class>,
+    ) -> Option<&mut dyn AnnotationVisitor> {
+        self.table().type_annotation(visible, annotation)
+    }
+
+    fn visit_attribute(&mut self, attribute: Attributes<'class>, _: &ConstantPool<'class>) {
+        self.table().attribute(&attribute);
+    }
+}
+
+impl<'class> MethodVisitor<'class> for MemberWriter<'class> {
+    fn visit_annotation(
+        &mut self,
+        type_index: U2,
+        visible: bool,
+        _: &ConstantPool<'class>,
+    ) -> Option<&mut dyn AnnotationVisitor> {
+        self.table().annotation(visible, type_index)
+    }
+
+    fn visit_type_annotation(
+        &mut self,
+        annotation: &TypeAnnotation,
+        visible: bool,
+        _: &ConstantPool<'class>,
+    ) -> Option<&mut dyn AnnotationVisitor> {
+        self.table().type_annotation(visible, annotation)
+    }
+
+    fn visit_annotable_parameter_count(
+        &mut self,
+        count: U1,
+        visible: bool,
+        _: &ConstantPool<'class>,
+    ) {
+        self.attributes.parameter_count(visible, count);
+    }
+
+    fn visit_parameter_annotation(
+        &mut self,
+        parameter: U1,
+        type_index: U2,
+        visible: bool,
+        _: &ConstantPool<'class>,
+    ) -> Option<&mut dyn AnnotationVisitor> {
+        self.attributes
+            .parameter_annotation(visible, parameter, type_index)
+    }
+
+    fn visit_annotation_default(
+        &mut self,
+        _: &ConstantPool<'class>,
+    ) -> Option<&mut dyn AnnotationVisitor> {
+        self.attributes.annotation_default()
+    }
+
+    fn visit_code(&mut self, max_stack: U2, max_locals: U2, _: &ConstantPool<'class>) -> bool {
+        self.end_code();
+        self.code = Some(CodeWriter {
+            max_stack,
+            max_locals,
+            code: vec![],
+            handlers: Members::default(),
+            attributes: AttributeTable::new(self.attributes.names.clone()),
+        });
+
+        true
+    }
+
+    fn visit_exception_handler(
+        &mut self,
+        handler: &ExceptionTableAttrCode,
+        _: &ConstantPool<'class>,
+    ) {
+        if let Some(code) = &mut self.code {
+            let mut writer = Writer {
+                bytes: std::mem::take(&mut code.handlers.bytes),
+            };
+            writer.u2(handler.start_pc);
+            writer.u2(handler.end_pc);
+            writer.u2(handler.handler_pc);
+            writer.u2(handler.catch_type);
+
+            code.handlers.bytes = writer.bytes;
+            code.handlers.count += 1;
+        }
+    }
+
+    fn visit_instruction(
+        &mut self,
+        _: u32,
+        instruction: &Instruction,
+        wide: bool,
+        _: &ConstantPool<'class>,
+    ) {
+        if let Some(code) = &mut self.code {
+            if wide {
+                instruction.encode_wide(&mut code.code);
+            } else {
+                instruction.encode(&mut code.code);
+            }
+        }
+    }
+
+    fn visit_frame(&mut self, frame: &StackMapFrame, _: &ConstantPool<'class>) {
+        self.table().frame(frame);
+    }
+
+    fn visit_line_number(&mut self, start_pc: U2, line_number: U2) {
+        self.table().line_number(start_pc, line_number);
+    }
+
+    fn visit_attribute(&mut self, attribute: Attributes<'class>, _: &ConstantPool<'class>) {
+        self.table().attribute(&attribute);
+    }
+
+    fn visit_code_end(&mut self) {
+        self.end_code();
+    }
+}
+
+impl<'class> AttributeTable<'class> {
+    fn new(names: Rc<Names<'class>>) -> Self {
+        Self {
+            names,
+            writer: Writer { bytes: vec![] },
+            count: 0,
+            open: None,
+            values: vec![],
+            error: None,
+        }
+    }
+
+    fn record(&mut self, result: Result<(), ParsingError>) -> bool {
+        match result {
+            Ok(()) => true,
+            Err(error) => {
+                self.error.get_or_insert(error);
+                false
+            }
+        }
+    }
+
+    // The attributes_count, and the attributes.
+    fn finish(mut self) -> Result<Vec<U1>, ParsingError> {
+        let result = self.close();
+        self.record(result);
+
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        let mut bytes = Writer::count(self.count, "attributes_count")?
+            .to_u2()
+            .to_be_bytes()
+            .to_vec();
+        bytes.extend(self.writer.bytes);
+
+        Ok(bytes)
+    }
+
+    fn close(&mut self) -> Result<(), ParsingError> {
+        let Some(mut open) = self.open.take() else {
+            return Ok(());
+        };
+
+        if let Some(parameters) = &open.parameters {
+            for _ in parameters.started..parameters.declared {
+                self.next_parameter(&mut open)?;
+            }
+        }
+
+        if let Some(parameters) = &open.parameters {
+            self.writer.bytes[parameters.index] =
+                Writer::u1_count(parameters.started, "num_parameters")?;
+        }
+
+        if let Some((index, count, field)) = open.entries {
+            self.writer.patch_count(index, count, field)?;
+        }
+
+        self.writer.attribute_length(open.length_index)
+    }
+
+    // Starts a new attribute, written to until another attribute starts.
+    fn open(
+        &mut self,
+        name: &'static str,
+        entries: Option<&'static str>,
+    ) -> Result<&mut Open, ParsingError> {
+        self.close()?;
+
+        let length_index = self.writer.attribute_header(name, &self.names)?;
+        self.count += 1;
+
+        let entries = entries.map(|field| {
+            let index = self.writer.bytes.len();
+            self.writer.u2(0.into());
+            (index, 0, field)
+        });
+
+        Ok(self.open.insert(Open {
+            name,
+            length_index,
+            entries,
+            parameters: None,
+        }))
+    }
+
+    // Adds an entry to the attribute being written, when it's a `name` one, and to a new one
+    // otherwise.
+    fn entry(&mut self, name: &'static str, field: &'static str) -> Result<(), ParsingError> {
+        let open = match &mut self.open {
+            Some(open) if open.name == name && open.parameters.is_none() => open,
+            _ => self.open(name, Some(field))?,
+        };
+
+        if let Some((_, count, _)) = &mut open.entries {
+            *count += 1;
+        }
+
+        Ok(())
+    }
+
+    // Starts the annotations of the next parameter.
+    fn next_parameter(&mut self, open: &mut Open) -> Result<(), ParsingError> {
+        if let Some((index, count, field)) = open.entries.take() {
+            self.writer.patch_count(index, count, field)?;
+        }
+
+        open.entries = Some((self.writer.bytes.len(), 0, "num_annotations"));
+        self.writer.u2(0.into());
+
+        if let Some(parameters) = &mut open.parameters {
+            parameters.started += 1;
+        }
+
+        Ok(())
+    }
+
+    // The values of the annotation or array written next.
+    fn start_values(&mut self, field: &'static str) -> Option<&mut dyn AnnotationVisitor> {
+        self.values.push((self.writer.bytes.len(), 0, field));
+        self.writer.u2(0.into());
+
+        Some(self)
+    }
+
+    fn attribute(&mut self, attribute: &Attributes) {
+        let result = self.close().and_then(|()| {
+            self.count += 1;
+            self.writer.attribute(attribute, &self.names)
+        });
+        self.record(result);
+    }
+
+    fn raw(&mut self, name: &str, body: &[U1]) {
+        let result = self.close().and_then(|()| {
+            let length_index = self.writer.attribute_header(name, &self.names)?;
+            self.count += 1;
+            self.writer.u1_range(body);
+            self.writer.attribute_length(length_index)
+        });
+        self.record(result);
+    }
+
+    fn annotation(&mut self, visible: bool, type_index: U2) -> Option<&mut dyn AnnotationVisitor> {
+        let name = match visible {
+            true => "RuntimeVisibleAnnotations",
+            false => "RuntimeInvisibleAnnotations",
+        };
+
+        let result = self.entry(name, "num_annotations");
+
+        if !self.record(result) {
+            return None;
+        }
+
+        self.writer.u2(type_index);
+        self.start_values("num_element_value_pairs")
+    }
+
+    fn type_annotation(
+        &mut self,
+        visible: bool,
+        annotation: &TypeAnnotation,
+    ) -> Option<&mut dyn AnnotationVisitor> {
+        let name = match visible {
+            true => "RuntimeVisibleTypeAnnotations",
+            false => "RuntimeInvisibleTypeAnnotations",
+        };
+
+        let result = self
+            .entry(name, "num_annotations")
+            .and_then(|()| self.writer.type_annotation_target(annotation));
+
+        if !self.record(result) {
+            return None;
+        }
+
+        self.start_values("num_element_value_pairs")
+    }
+
+    fn parameters_name(visible: bool) -> &'static str {
+        match visible {
+            true => "RuntimeVisibleParameterAnnotations",
+            false => "RuntimeInvisibleParameterAnnotations",
+        }
+    }
+
+    fn open_parameters(&mut self, visible: bool, declared: usize) -> Result<(), ParsingError> {
+        self.open(Self::parameters_name(visible), None)?;
+
+        let index = self.writer.bytes.len();
+        self.writer.u1(0);
+
+        if let Some(open) = &mut self.open {
+            open.parameters = Some(Parameters {
+                index,
+                declared,
+                started: 0,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn parameter_count(&mut self, visible: bool, count: U1) {
+        let result = self.open_parameters(visible, count.into());
+        self.record(result);
+    }
+
+    fn parameter_annotation(
+        &mut self,
+        visible: bool,
+        parameter: U1,
+        type_index: U2,
+    ) -> Option<&mut dyn AnnotationVisitor> {
+        let result = (|| {
+            let name = Self::parameters_name(visible);
+
+            if !matches!(&self.open, Some(open) if open.name == name && open.parameters.is_some()) {
+                self.open_parameters(visible, 0)?;
+            }
+
+            let mut open = self
+                .open
+                .take()
+                .expect("parameter annotations were just opened");
+            let mut result = Ok(());
+
+            while result.is_ok()
+                && open
+                    .parameters
+                    .as_ref()
+                    .is_some_and(|z| z.started <= usize::from(parameter))
+            {
+                result = self.next_parameter(&mut open);
+            }
+
+            if let Some((_, count, _)) = &mut open.entries {
+                *count += 1;
+            }
+
+            self.open = Some(open);
+            result
+        })();
+
+        if !self.record(result) {
+            return None;
+        }
+
+        self.writer.u2(type_index);
+        self.start_values("num_element_value_pairs")
+    }
+
+    fn annotation_default(&mut self) -> Option<&mut dyn AnnotationVisitor> {
+        let result = self.open("AnnotationDefault", None).map(|_| ());
+
+        match self.record(result) {
+            true => Some(self),
+            false => None,
+        }
+    }
+
+    fn frame(&mut self, frame: &StackMapFrame) {
+        let result = self
+            .entry("StackMapTable", "number_of_entries")
+            .and_then(|()| self.writer.stackmapframe(frame));
+        self.record(result);
+    }
+
+    fn line_number(&mut self, start_pc: U2, line_number: U2) {
+        let result = self.entry("LineNumberTable", "line_number_table_length");
+
+        if self.record(result) {
+            self.writer.u2(start_pc);
+            self.writer.u2(line_number);
+        }
+    }
+
+    // Counts a value of the annotation or array being written, and writes its name.
+    fn element(&mut self, name_index: Option<U2>) {
+        if let Some((_, count, _)) = self.values.last_mut() {
+            *count += 1;
+        }
+
+        if let Some(name_index) = name_index {
+            self.writer.u2(name_index);
+        }
+    }
+}
+
+// The annotations visited on a class, field or method are written straight into its attribute
+// table.
+impl AnnotationVisitor for AttributeTable<'_> {
+    fn visit_value(&mut self, name_index: Option<U2>, value: &ElementValue) {
+        self.element(name_index);
+        let result = self.writer.element_value(value);
+        self.record(result);
+    }
+
+    fn visit_annotation(
+        &mut self,
+        name_index: Option<U2>,
+        type_index: U2,
+    ) -> Option<&mut dyn AnnotationVisitor> {
+        self.element(name_index);
+        self.writer.u1(b'@');
+        self.writer.u2(type_index);
+        self.start_values("num_element_value_pairs")
+    }
+
+    fn visit_array(&mut self, name_index: Option<U2>) -> Option<&mut dyn AnnotationVisitor> {
+        self.element(name_index);
+        self.writer.u1(b'[');
+        self.start_values("num_values")
+    }
+
+    fn visit_end(&mut self) {
+        if let Some((index, count, field)) = self.values.pop() {
+            let result = self.writer.patch_count(index, count, field);
+            self.record(result);
+        }
+    }
+}
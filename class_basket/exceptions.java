@@ -0,0 +1,36 @@
+import java.util.ArrayList;
+import java.util.List;
+
+class exceptions {
+    static int length(CharSequence text) {
+        return text.length();
+    }
+
+    static List<String> names() {
+        List<String> names = new ArrayList<>();
+        names.add("exceptions");
+        return names;
+    }
+
+    static void check(boolean ok) {
+        if (!ok) {
+            throw new IllegalStateException("not ok");
+        }
+    }
+
+    public static void main(String[] args) throws Exception {
+        Throwable failure = null;
+
+        try {
+            check(length("abc") == names().size());
+        } catch (IllegalStateException | IllegalArgumentException e) {
+            failure = e;
+        } finally {
+            System.out.println(failure);
+        }
+
+        if (failure != null) {
+            throw new Exception(failure);
+        }
+    }
+}
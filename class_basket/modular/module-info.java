@@ -0,0 +1,11 @@
+module org.aftermath.modular {
+    requires transitive java.logging;
+    requires static java.sql;
+
+    exports org.aftermath.modular.api;
+    exports org.aftermath.modular.internal to java.logging;
+    opens org.aftermath.modular.internal;
+
+    uses org.aftermath.modular.api.Service;
+    provides org.aftermath.modular.api.Service with org.aftermath.modular.internal.Impl;
+}
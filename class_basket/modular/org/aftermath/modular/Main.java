@@ -0,0 +1,5 @@
+package org.aftermath.modular;
+
+public class Main {
+    public static void main(String[] args) {}
+}
@@ -0,0 +1,5 @@
+package org.aftermath.modular.api;
+
+public interface Service {
+    void run();
+}
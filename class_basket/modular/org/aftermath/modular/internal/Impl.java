@@ -0,0 +1,7 @@
+package org.aftermath.modular.internal;
+
+import org.aftermath.modular.api.Service;
+
+public class Impl implements Service {
+    public void run() {}
+}
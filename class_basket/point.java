@@ -0,0 +1,7 @@
+record point(int x, String label) {
+    static int created;
+
+    point {
+        created++;
+    }
+}